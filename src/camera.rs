@@ -1,30 +1,35 @@
-use crate::utility::{rand_in_unit_disk};
+use rand::Rng;
+use crate::utility::{rand_in_unit_disk, rng};
 use crate::ray::{Ray};
+use crate::float::{Float, Vec3};
 
 
 #[derive(Debug, Clone)]
 pub struct Camera
 {
-    viewport_aspect: f32,
+    viewport_aspect: Float,
 
     // viewport 左上角的坐标，用于 uv 的原点
-    upper_left_corner: glm::Vec3,
+    upper_left_corner: Vec3,
 
     // 摄像机的位置
-    pos: glm::Vec3,
+    pos: Vec3,
 
     // viewport 的 uv 方向向量
     // 大小与 viewport 尺寸相同
-    viewport_u: glm::Vec3,
-    viewport_v: glm::Vec3,
+    viewport_u: Vec3,
+    viewport_v: Vec3,
 
     // 摄像机坐标系基向量，是单位向量
-    camera_u: glm::Vec3,
-    camera_v: glm::Vec3,
-    camera_w: glm::Vec3,
+    camera_u: Vec3,
+    camera_v: Vec3,
+    camera_w: Vec3,
 
     // 光圈大小，用于景深
-    lens_radius: f32,
+    lens_radius: Float,
+
+    // 快门打开和关闭的时刻，用于运动模糊
+    shutter: (Float, Float),
 }
 
 
@@ -32,7 +37,7 @@ impl Camera {
     /// fov 是垂直方向的，单位是 degree
     /// aperture 是光圈的大小
     /// focus_dist 是景深，从相机到场景中成像清晰的位置的距离
-    pub fn new(pos: glm::Vec3, lookat: glm::Vec3, up: glm::Vec3, vfov: f32, aspect_ratio: f32, aperture: f32, focus_dist: f32) -> Camera
+    pub fn new(pos: Vec3, lookat: Vec3, up: Vec3, vfov: Float, aspect_ratio: Float, aperture: Float, focus_dist: Float) -> Camera
     {
         // viewport 平面到摄像机的距离默认为 1.0
 
@@ -66,17 +71,28 @@ impl Camera {
             camera_v,
             camera_w,
             lens_radius,
+            shutter: (0.0, 0.0),
         }
     }
 
 
-    pub fn aspect(&self) -> f32 { self.viewport_aspect }
+    /// 设置快门打开和关闭的时刻，每条光线的时刻在这个区间内均匀分布；默认两者都是 0，即没有运动模糊
+    pub fn set_shutter(&mut self, open: Float, close: Float)
+    {
+        debug_assert!(open.is_finite() && close.is_finite() && open <= close);
+
+        self.shutter = (open, close);
+    }
 
-    pub fn camera_w(&self) -> &glm::Vec3 { &self.camera_w }
+
+    pub fn aspect(&self) -> Float { self.viewport_aspect }
+    pub fn shutter(&self) -> (Float, Float) { self.shutter }
+
+    pub fn camera_w(&self) -> &Vec3 { &self.camera_w }
 
     /// 根据 uv 坐标，创建出对应的射线；
     /// uv 原点位于左上角，范围是 (0, 1)^2
-    pub fn ray_from_uv(&self, uv: (f32, f32)) -> Ray
+    pub fn ray_from_uv(&self, uv: (Float, Float)) -> Ray
     {
         // 基于光圈大小，随机生成一个点
         let rd = rand_in_unit_disk() * self.lens_radius;
@@ -84,6 +100,13 @@ impl Camera {
 
         let target = self.upper_left_corner + self.viewport_u * uv.0 + self.viewport_v * uv.1;
 
-        Ray::new(self.pos + offset, target)
+        // 快门没有打开的时间时，不消耗随机数
+        let time = if self.shutter.0 < self.shutter.1 {
+            rng().gen_range(self.shutter.0, self.shutter.1)
+        } else {
+            self.shutter.0
+        };
+
+        Ray::new(self.pos + offset, target).with_time(time)
     }
 }
@@ -0,0 +1,35 @@
+//! 渲染器使用的标量类型，以及对应的向量、矩阵类型
+//!
+//! 默认使用单精度；启用 `f64` feature 之后整个渲染器（光线、交点、包围盒、相机以及几何体）都使用双精度，
+//! 用于尺度非常大的场景，例如半径为 5000 的体积雾或者星球尺度的地形。其他代码只通过这里的类型别名使用标量，
+//! 两种模式的公开接口相同
+
+
+#[cfg(not(feature = "f64"))]
+pub type Float = f32;
+
+#[cfg(feature = "f64")]
+pub type Float = f64;
+
+
+pub type Vec2 = glm::Vector2<Float>;
+pub type Vec3 = glm::Vector3<Float>;
+pub type Vec4 = glm::Vector4<Float>;
+pub type Mat4 = glm::Matrix4<Float>;
+
+
+/// 和 `glm::vec2`、`glm::vec3`、`glm::vec4` 相同，但是分量的类型为 `Float`
+#[inline]
+pub fn vec2(x: Float, y: Float) -> Vec2 { Vec2::new(x, y) }
+
+#[inline]
+pub fn vec3(x: Float, y: Float, z: Float) -> Vec3 { Vec3::new(x, y, z) }
+
+#[inline]
+pub fn vec4(x: Float, y: Float, z: Float, w: Float) -> Vec4 { Vec4::new(x, y, z, w) }
+
+
+/// 转换为双精度，用于少数需要更高精度的局部计算，双精度模式下不做任何转换
+#[inline]
+#[allow(clippy::useless_conversion)]
+pub fn to_f64(x: Float) -> f64 { f64::from(x) }
@@ -1,5 +1,37 @@
+use std::io::Write;
 use geefr_ppm::Ppm as PPM;
-use crate::utility::{gamma_correction};
+use crate::utility::{gamma_correction, random};
+use crate::float::{Float, Vec3};
+
+
+/// 支持的图片输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat
+{
+    Ppm,
+    Bmp,
+}
+
+
+impl ImageFormat
+{
+    pub fn from_name(name: &str) -> Option<ImageFormat>
+    {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "bmp" => Some(ImageFormat::Bmp),
+            _ => None,
+        }
+    }
+
+    /// 根据文件的扩展名推断图片格式
+    pub fn from_path(path: &str) -> Option<ImageFormat>
+    {
+        std::path::Path::new(path).extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_name)
+    }
+}
 
 
 #[derive(Clone, Copy)]
@@ -26,6 +58,20 @@ impl Grid {
 
         res
     }
+
+
+    /// 将区域划分为 block x block 的小块，位于边缘的小块可能更小
+    pub fn blocks(&self, block: u32) -> Vec<Grid>
+    {
+        let mut res = Vec::new();
+        for x in (self.pos.0..self.pos.0 + self.size.0).step_by(block as usize) {
+            for y in (self.pos.1..self.pos.1 + self.size.1).step_by(block as usize) {
+                let size = (block.min(self.pos.0 + self.size.0 - x), block.min(self.pos.1 + self.size.1 - y));
+                res.push(Grid { pos: (x, y), size });
+            }
+        }
+        res
+    }
 }
 
 
@@ -39,9 +85,9 @@ pub struct FrameBuffer {
 
 impl FrameBuffer
 {
-    pub fn new(width: u32, aspect: f32) -> FrameBuffer
+    pub fn new(width: u32, aspect: Float) -> FrameBuffer
     {
-        let height = (width as f32 / aspect) as u32;
+        let height = (width as Float / aspect) as u32;
         FrameBuffer {
             width,
             height,
@@ -51,14 +97,14 @@ impl FrameBuffer
 
     /// 将颜色写入 ppm 中
     /// - 颜色是真实的颜色，范围可以超过 1，该函数会进行 Gamma 矫正
-    pub fn write_color(&mut self, pos: (u32, u32), color: &glm::Vec3)
+    pub fn write_color(&mut self, pos: (u32, u32), color: &Vec3)
     {
         debug_assert!(color.x >= 0.0 && color.y >= 0.0 && color.z >= 0.0);
 
         let color = gamma_correction(*color);
 
         // 将颜色从浮点数截取到 [0, 1] 范围，并使用 unormal-u8 进行编码
-        let to_ppm_color = |c: f32| (c.clamp(0.0, 0.999) * 256.0) as u8;
+        let to_ppm_color = |c: Float| (c.clamp(0.0, 0.999) * 256.0) as u8;
 
         self.ppm.set_pixel(pos.0 as usize, pos.1 as usize,
                            to_ppm_color(color.x),
@@ -68,10 +114,10 @@ impl FrameBuffer
 
 
     /// 获得屏幕上某一点对应的 uv，如果超出范围，则返回 None
-    pub fn get_uv(&self, pos: (u32, u32)) -> Option<(f32, f32)>
+    pub fn get_uv(&self, pos: (u32, u32)) -> Option<(Float, Float)>
     {
         if pos.0 >= self.width || pos.1 >= self.height { return None; }
-        Some((pos.0 as f32 / self.width as f32, pos.1 as f32 / self.height as f32))
+        Some((pos.0 as Float / self.width as Float, pos.1 as Float / self.height as Float))
     }
 
 
@@ -80,17 +126,17 @@ impl FrameBuffer
 
 
     /// 为某个像素生成多个 sample，并计算每个 sample 的 uv
-    pub fn multi_sample(framebuffer_size: (u32, u32), pos: (u32, u32), samples: u32) -> Vec<(f32, f32)>
+    pub fn multi_sample(framebuffer_size: (u32, u32), pos: (u32, u32), samples: u32) -> Vec<(Float, Float)>
     {
         debug_assert!(pos.0 < framebuffer_size.0 && pos.1 < framebuffer_size.1);
         debug_assert!(samples > 0);
 
-        let width_inv = 1.0 / framebuffer_size.0 as f32;
-        let height_inv = 1.0 / framebuffer_size.1 as f32;
+        let width_inv = 1.0 / framebuffer_size.0 as Float;
+        let height_inv = 1.0 / framebuffer_size.1 as Float;
 
         let gen_uv = |_|
-            ((pos.0 as f32 + rand::random::<f32>()) * width_inv,
-             (pos.1 as f32 + rand::random::<f32>()) * height_inv);
+            ((pos.0 as Float + random::<Float>()) * width_inv,
+             (pos.1 as Float + random::<Float>()) * height_inv);
 
         (0..samples).map(gen_uv).collect()
     }
@@ -103,6 +149,56 @@ impl FrameBuffer
     }
 
 
+    /// 以指定的格式将结果写入文件中
+    pub fn save_as(&self, file_path: &str, format: ImageFormat) -> std::io::Result<()>
+    {
+        match format {
+            ImageFormat::Ppm => self.save(file_path.to_string()),
+            ImageFormat::Bmp => self.save_bmp(file_path),
+        }
+    }
+
+
+    /// 写入 24 位的 BMP 文件：像素按行从下往上存储，每行对齐到 4 字节
+    fn save_bmp(&self, file_path: &str) -> std::io::Result<()>
+    {
+        let row_size = (self.width * 3).div_ceil(4) * 4;
+        let image_size = row_size * self.height;
+        let file_size = 14 + 40 + image_size;
+
+        let mut data: Vec<u8> = Vec::with_capacity(file_size as usize);
+
+        // file header
+        data.extend_from_slice(b"BM");
+        data.extend_from_slice(&file_size.to_le_bytes());
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        data.extend_from_slice(&(14_u32 + 40).to_le_bytes());
+
+        // info header
+        data.extend_from_slice(&40_u32.to_le_bytes());
+        data.extend_from_slice(&(self.width as i32).to_le_bytes());
+        data.extend_from_slice(&(self.height as i32).to_le_bytes());
+        data.extend_from_slice(&1_u16.to_le_bytes());
+        data.extend_from_slice(&24_u16.to_le_bytes());
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        data.extend_from_slice(&image_size.to_le_bytes());
+        data.extend_from_slice(&2835_i32.to_le_bytes());
+        data.extend_from_slice(&2835_i32.to_le_bytes());
+        data.extend_from_slice(&0_u32.to_le_bytes());
+        data.extend_from_slice(&0_u32.to_le_bytes());
+
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let (r, g, b) = self.ppm.get_pixel(x as usize, y as usize);
+                data.extend_from_slice(&[b, g, r]);
+            }
+            data.resize(data.len() + (row_size - self.width * 3) as usize, 0);
+        }
+
+        std::fs::File::create(file_path)?.write_all(&data)
+    }
+
+
     pub fn pixel_iter(&self) -> impl Iterator<Item=(u32, u32)>
     {
         let w = self.width;
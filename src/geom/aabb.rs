@@ -2,13 +2,14 @@ use crate::geom::Axis;
 use crate::hit::Hittable;
 use crate::ray::Ray;
 use crate::utility::check_and;
+use crate::float::{Float, Vec3, vec3};
 
 
 #[derive(Clone)]
 pub struct AABB
 {
-    minimum: glm::Vec3,
-    maximum: glm::Vec3,
+    minimum: Vec3,
+    maximum: Vec3,
 }
 
 impl AABB
@@ -17,17 +18,17 @@ impl AABB
     pub fn new_default() -> AABB
     {
         AABB {
-            minimum: glm::vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
-            maximum: glm::vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+            minimum: vec3(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+            maximum: vec3(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY),
         }
     }
 
 
     /// 提供 AABB 中两个边界点来创建 AABB
-    pub fn new(a: glm::Vec3, b: glm::Vec3) -> AABB
+    pub fn new(a: Vec3, b: Vec3) -> AABB
     {
-        debug_assert!(check_and(&a, f32::is_finite));
-        debug_assert!(check_and(&b, f32::is_finite));
+        debug_assert!(check_and(&a, Float::is_finite));
+        debug_assert!(check_and(&b, Float::is_finite));
         debug_assert!(a.x <= b.x && a.y <= b.y && a.z <= b.z);
 
         AABB {
@@ -36,21 +37,40 @@ impl AABB
         }
     }
 
-    pub fn min(&self) -> &glm::Vec3 { &self.minimum }
-    pub fn max(&self) -> &glm::Vec3 { &self.maximum }
+    pub fn min(&self) -> &Vec3 { &self.minimum }
+    pub fn max(&self) -> &Vec3 { &self.maximum }
+
+    pub fn centroid(&self) -> Vec3 { (self.minimum + self.maximum) * 0.5 }
+
+
+    /// 表面积，用于 SAH
+    pub fn surface_area(&self) -> Float
+    {
+        let d = self.maximum - self.minimum;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
 
 
     /// 判断光线是否与 bounding box 相交
-    pub fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> bool {
+    pub fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        let dir = ray.dir();
+        self.hit_inv(ray.orig(), &vec3(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z), t_range)
+    }
+
+
+    /// 使用预先计算的光线方向的倒数进行求交，同一条光线和多个 AABB 求交时（例如遍历 BVH）可以避免重复的除法
+    #[inline(always)]
+    pub fn hit_inv(&self, orig: &Vec3, inv_dir: &Vec3, t_range: (Float, Float)) -> bool {
         debug_assert!(t_range.0 < t_range.1);
 
+        let (mut t_min, mut t_max) = t_range;
         for i in 0..3 {
             // ray 某个分量为 0，那么 inv_d 应该是 inf 或者 -inf
             // 分析可知，即使是光线与 bounding box 平行的情况，结果仍然是正确的
-            let inv_d = 1.0 / ray.dir()[i];
+            let inv_d = inv_dir[i];
 
-            let mut t0 = (self.minimum[i] - ray.orig()[i]) * inv_d;
-            let mut t1 = (self.maximum[i] - ray.orig()[i]) * inv_d;
+            let mut t0 = (self.minimum[i] - orig[i]) * inv_d;
+            let mut t1 = (self.maximum[i] - orig[i]) * inv_d;
 
             // 只有当 inv_d = inf，且 ray 的原点和 aabb 某个面重合时，才会出现 NaN，判定为不相交
             if t0.is_nan() || t1.is_nan() {
@@ -61,15 +81,41 @@ impl AABB
                 std::mem::swap(&mut t0, &mut t1);
             }
 
-            let t_min = if t0 > t_range.0 { t0 } else { t_range.0 };
-            let t_max = if t1 < t_range.1 { t1 } else { t_range.1 };
+            // 三个方向的范围依次取交集
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
 
             if t_max <= t_min {
                 return false;
             }
         }
 
-        return true;
+        true
+    }
+
+
+    /// 光线在 AABB 内部的 t 的范围，和 t_range 取交集；不相交时返回 None
+    pub fn clip(&self, ray: &Ray, t_range: (Float, Float)) -> Option<(Float, Float)>
+    {
+        let (mut t_min, mut t_max) = t_range;
+        for i in 0..3 {
+            let inv_d = 1.0 / ray.dir()[i];
+            let mut t0 = (self.minimum[i] - ray.orig()[i]) * inv_d;
+            let mut t1 = (self.maximum[i] - ray.orig()[i]) * inv_d;
+            if t0.is_nan() || t1.is_nan() {
+                return None;
+            }
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max <= t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
     }
 
 
@@ -79,8 +125,8 @@ impl AABB
         let minimum = glm::min(box_a.minimum, box_b.minimum);
         let maximum = glm::max(box_a.maximum, box_b.maximum);
 
-        debug_assert!(check_and(&minimum, f32::is_finite));
-        debug_assert!(check_and(&maximum, f32::is_finite));
+        debug_assert!(check_and(&minimum, Float::is_finite));
+        debug_assert!(check_and(&maximum, Float::is_finite));
 
         AABB { minimum, maximum }
     }
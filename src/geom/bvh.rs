@@ -1,105 +1,974 @@
+use std::fmt;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
 use crate::geom::aabb::AABB;
 use crate::geom::Axis;
 use crate::geom::hittable_list::HittableList;
 use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
 use crate::ray::Ray;
+use crate::float::{Float, Vec3, vec3};
 
 
+/// SAH 中遍历一个节点的代价，以及和一个物体求交的代价
+const TRAVERSAL_COST: Float = 1.0;
+const INTERSECT_COST: Float = 1.0;
+
+/// SAH 在每个轴上划分的桶的数量
+const SAH_BINS: usize = 16;
+
+/// `BVHNode::new` 使用的叶节点最大物体数量
+pub const DEFAULT_LEAF_SIZE: usize = 4;
+
+/// 物体数量超过这个值的子树才会交给新的线程构建，避免线程的开销超过构建本身
+const PARALLEL_BUILD_MIN: usize = 4096;
+
+/// `BVHNode::new` 和 `BVHNode::new_sah` 使用的线程数量
+static BUILD_THREADS: AtomicUsize = AtomicUsize::new(1);
+
+
+/// 设置构建 BVH 时使用的线程数量，一般和渲染器的线程数量相同
+pub fn set_build_threads(threads: u32)
+{
+    debug_assert!(threads > 0);
+    BUILD_THREADS.store(threads.max(1) as usize, Ordering::Relaxed);
+}
+
+
+pub fn build_threads() -> u32 { BUILD_THREADS.load(Ordering::Relaxed) as u32 }
+
+
+/// BVH 的统计信息，用于比较不同的构建方法
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BvhStats
+{
+    /// 从根节点到最深的叶节点经过的内部节点数量
+    pub depth: usize,
+
+    /// 内部节点的数量
+    pub nodes: usize,
+
+    pub leaves: usize,
+
+    /// 叶节点中物体的总数，同一个物体被多个叶节点引用时会重复计数
+    pub primitives: usize,
+
+    /// 根据 SAH 估计的一条光线的求交代价，以 `INTERSECT_COST` 为单位
+    pub sah_cost: Float,
+}
+
+
+impl BvhStats
+{
+    fn leaf(count: usize) -> BvhStats
+    {
+        BvhStats { depth: 0, nodes: 0, leaves: 1, primitives: count, sah_cost: INTERSECT_COST * count as Float }
+    }
+
+
+    /// 两个子树合并为一个内部节点，子树的代价按照表面积的比例加权
+    fn node(left: &BvhStats, left_area: Float, right: &BvhStats, right_area: Float, area: Float) -> BvhStats
+    {
+        let ratio = |child_area: Float| if area > 0.0 { child_area / area } else { 1.0 };
+
+        BvhStats {
+            depth: 1 + left.depth.max(right.depth),
+            nodes: 1 + left.nodes + right.nodes,
+            leaves: left.leaves + right.leaves,
+            primitives: left.primitives + right.primitives,
+            sah_cost: TRAVERSAL_COST + ratio(left_area) * left.sah_cost + ratio(right_area) * right.sah_cost,
+        }
+    }
+}
+
+
+impl fmt::Display for BvhStats
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "depth {}, {} nodes, {} leaves, {} primitives, SAH cost {:.2}",
+               self.depth, self.nodes, self.leaves, self.primitives, self.sah_cost)
+    }
+}
+
+
+/// 线性化的 BVH：所有节点按照深度优先的顺序存放在一个数组中，使用显式的栈进行遍历
+///
+/// 内部节点的第一个子节点紧跟在它的后面，第二个子节点的位置记录在节点中；
+/// 叶节点记录了它的物体在 primitives 中的范围
 pub struct BVHNode
 {
-    left: Arc<dyn Hittable + Send + Sync>,
-    right: Arc<dyn Hittable + Send + Sync>,
+    /// 所有物体都没有包围盒时为空
+    nodes: Vec<LinearNode>,
+
+    primitives: Vec<Arc<dyn Hittable + Send + Sync>>,
+
+    /// primitives 中每个物体在构建时传入的数组中的位置，用于 `refit` 时找到对应的物体
+    ids: Vec<u32>,
+
+    /// 没有包围盒的物体（例如无限大的平面），不放入 BVH 中，每次求交时单独检测
+    unbounded: Vec<Arc<dyn Hittable + Send + Sync>>,
+    unbounded_ids: Vec<u32>,
+
+    /// 统计信息，不包括 unbounded 中的物体
+    stats: BvhStats,
+
+    /// 最近一次构建时的 SAH 代价，refit 之后的代价和它比较，判断是否需要重新构建
+    built_cost: Float,
+
+    max_leaf_size: usize,
+}
+
+
+/// 线性 BVH 的节点，单精度时大小为 32 字节，双精度时为 56 字节
+struct LinearNode
+{
+    aabb: AABB,
+
+    /// 叶节点：第一个物体在 primitives 中的位置；内部节点：第二个子节点在 nodes 中的位置
+    offset: u32,
+
+    /// 叶节点中物体的数量，内部节点为 0
+    count: u16,
+
+    /// 内部节点的划分轴，用于决定先访问哪一个子节点
+    axis: u8,
+}
+
+
+/// 遍历时栈的大小，构建时会保证 BVH 的深度不超过这个值
+const STACK_SIZE: usize = 128;
+
+/// SAH 构建的深度超过这个值之后，按照数量对半划分，保证深度不会超过 STACK_SIZE
+const MAX_SAH_DEPTH: usize = 64;
+
+
+/// 构建 SAH BVH 时使用的物体信息，避免重复计算包围盒
+struct BuildItem
+{
+    obj: Arc<dyn Hittable + Send + Sync>,
+    id: u32,
     aabb: AABB,
+    centroid: Vec3,
+}
+
+
+/// SAH 的一个桶
+#[derive(Clone, Default)]
+struct Bin
+{
+    aabb: Option<AABB>,
+    count: usize,
+}
+
+
+fn grow(aabb: &Option<AABB>, other: &AABB) -> Option<AABB>
+{
+    match aabb {
+        None => Some(other.clone()),
+        Some(aabb) => Some(AABB::combine(aabb, other)),
+    }
+}
+
+
+fn area(aabb: &Option<AABB>) -> Float
+{
+    aabb.as_ref().map_or(0.0, AABB::surface_area)
 }
 
 
 impl BVHNode
 {
+    /// 使用 SAH 构建 BVH，叶节点最多包含 `DEFAULT_LEAF_SIZE` 个物体
+    ///
+    /// 没有包围盒的物体会被放在 BVH 之外，此时 BVH 整体也没有包围盒；objects 为空时，BVH 不会被任何光线击中
+    pub fn new(objects: &[Arc<dyn Hittable + Send + Sync>]) -> BVHNode
+    {
+        Self::new_sah(objects, DEFAULT_LEAF_SIZE)
+    }
+
+
+    /// 使用分桶的 SAH（surface area heuristic）构建 BVH
+    ///
+    /// 在三个轴上，按照包围盒中心将物体分到若干个桶中，在桶的边界中选择代价最小的划分位置；
+    /// 物体数量不超过 max_leaf_size，并且不划分的代价更小时，生成叶节点。
+    /// 使用 `set_build_threads` 设置的线程数量进行构建
+    pub fn new_sah(objects: &[Arc<dyn Hittable + Send + Sync>], max_leaf_size: usize) -> BVHNode
+    {
+        Self::new_parallel(objects, max_leaf_size, build_threads())
+    }
+
+
+    /// 使用 threads 个线程构建 SAH BVH，结果和单线程构建的完全相同
+    ///
+    /// 划分之后的两棵子树互不相关，可以同时构建：较大的子树被交给新的线程，线程数量也随之对半分配，
+    /// 每个线程将子树构建在自己的数组中，最后再拼接起来
+    pub fn new_parallel(objects: &[Arc<dyn Hittable + Send + Sync>], max_leaf_size: usize, threads: u32) -> BVHNode
+    {
+        debug_assert!((1..=u16::MAX as usize).contains(&max_leaf_size));
+        debug_assert!(threads > 0);
+
+        let threads = threads.max(1) as usize;
+        Self::new_with(objects, max_leaf_size, threads, |bvh, mut items| bvh.build_sah(&mut items, max_leaf_size, 0, threads).1)
+    }
+
+
+    /// 随机选择一个轴，按照中位数进行划分，每个叶节点只有一个物体
+    ///
+    /// 构建速度很快，但是树的形状每次都不同，在物体分布不均匀时质量较差
+    pub fn new_median(objects: &[Arc<dyn Hittable + Send + Sync>]) -> BVHNode
+    {
+        Self::new_with(objects, 1, 1, |bvh, mut items| bvh.build_median(&mut items).1)
+    }
+
+
+    pub fn new_with_list(hittalbe_list: &HittableList) -> BVHNode
+    {
+        Self::new(hittalbe_list.objects())
+    }
+
+
+    pub fn stats(&self) -> BvhStats { self.stats }
+
+
+    /// 将没有包围盒的物体分离出来，剩下的物体使用 build 构建 BVH
+    fn new_with<F>(objects: &[Arc<dyn Hittable + Send + Sync>], max_leaf_size: usize, threads: usize, build: F) -> BVHNode
+        where F: FnOnce(&mut BVHNode, Vec<BuildItem>) -> BvhStats
+    {
+        let mut items = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for (id, (obj, aabb)) in objects.iter().zip(bounding_boxes(objects, threads)).enumerate() {
+            match aabb {
+                Some(aabb) => items.push(BuildItem { obj: obj.clone(), id: id as u32, centroid: aabb.centroid(), aabb }),
+                None => unbounded.push((obj.clone(), id as u32)),
+            }
+        }
+
+        let mut bvh = Self::with_capacity(items.len(), max_leaf_size);
+        (bvh.unbounded, bvh.unbounded_ids) = unbounded.into_iter().unzip();
+        if !items.is_empty() {
+            bvh.stats = build(&mut bvh, items);
+            debug_assert!(bvh.stats.depth < STACK_SIZE);
+        }
+
+        bvh.built_cost = bvh.stats.sah_cost;
+        bvh.nodes.shrink_to_fit();
+        bvh
+    }
+
+
+    fn with_capacity(primitives: usize, max_leaf_size: usize) -> BVHNode
+    {
+        BVHNode {
+            nodes: Vec::with_capacity(2 * primitives),
+            primitives: Vec::with_capacity(primitives),
+            ids: Vec::with_capacity(primitives),
+            unbounded: Vec::new(),
+            unbounded_ids: Vec::new(),
+            stats: BvhStats::default(),
+            built_cost: 0.0,
+            max_leaf_size,
+        }
+    }
+
+
+    /// 替换 BVH 中的物体，并在原有的树结构上重新计算所有节点的包围盒（refit），不改变树的结构
+    ///
+    /// update 的参数是物体在构建时传入的数组中的位置，以及当前的物体，返回 None 表示物体没有变化。
+    /// 物体移动的距离较大时，树的质量会逐渐变差，可以使用 `refit_or_rebuild`。
+    ///
+    /// 如果有物体失去了包围盒（例如被替换为无限大的平面），或者没有包围盒的物体变得有界，
+    /// 原有的树结构无法容纳它们，此时会重新构建整个 BVH，返回 true
+    pub fn refit<F>(&mut self, mut update: F) -> bool
+        where F: FnMut(usize, &Arc<dyn Hittable + Send + Sync>) -> Option<Arc<dyn Hittable + Send + Sync>>
+    {
+        let objects = self.primitives.iter_mut().zip(&self.ids).chain(self.unbounded.iter_mut().zip(&self.unbounded_ids));
+        for (obj, &id) in objects {
+            if let Some(new_obj) = update(id as usize, obj) {
+                *obj = new_obj;
+            }
+        }
+
+        let boxes: Option<Vec<AABB>> = self.primitives.iter().map(|obj| obj.bounding_box()).collect();
+        let boxes = match boxes {
+            Some(boxes) if self.unbounded.iter().all(|obj| obj.bounding_box().is_none()) => boxes,
+            _ => {
+                self.rebuild();
+                return true;
+            }
+        };
+
+        // 子节点总是位于父节点之后，因此逆序遍历时，子节点的包围盒已经更新过了
+        let mut stats = vec![BvhStats::default(); self.nodes.len()];
+        for idx in (0..self.nodes.len()).rev() {
+            let node = &self.nodes[idx];
+            let (aabb, node_stats) = if node.count > 0 {
+                let first = node.offset as usize;
+                let leaf_boxes = &boxes[first..first + node.count as usize];
+                let aabb = leaf_boxes[1..].iter().fold(leaf_boxes[0].clone(), |aabb, b| AABB::combine(&aabb, b));
+                (aabb, BvhStats::leaf(leaf_boxes.len()))
+            } else {
+                let (left, right) = (&self.nodes[idx + 1].aabb, &self.nodes[node.offset as usize].aabb);
+                let aabb = AABB::combine(left, right);
+                let area = aabb.surface_area();
+                (aabb, BvhStats::node(&stats[idx + 1], left.surface_area(), &stats[node.offset as usize], right.surface_area(), area))
+            };
+
+            self.nodes[idx].aabb = aabb;
+            stats[idx] = node_stats;
+        }
+
+        if let Some(root) = stats.first() {
+            self.stats = *root;
+        }
+        false
+    }
+
+
+    /// 先进行 refit，如果 SAH 代价超过了上一次构建时的 max_cost_ratio 倍，就使用 SAH 重新构建，返回是否重新构建
+    pub fn refit_or_rebuild<F>(&mut self, update: F, max_cost_ratio: Float) -> bool
+        where F: FnMut(usize, &Arc<dyn Hittable + Send + Sync>) -> Option<Arc<dyn Hittable + Send + Sync>>
+    {
+        debug_assert!(max_cost_ratio >= 1.0);
+
+        if self.refit(update) {
+            return true;
+        }
+        if self.stats.sah_cost <= self.built_cost * max_cost_ratio {
+            return false;
+        }
+
+        self.rebuild();
+        true
+    }
+
+
+    /// 使用 SAH 重新构建，并重新划分有界和无界的物体
+    ///
+    /// 保持物体原来的编号，重新构建之后 refit 依然可以使用相同的编号
+    fn rebuild(&mut self)
+    {
+        let mut objects: Vec<(u32, Arc<dyn Hittable + Send + Sync>)> = self.ids.iter().copied().zip(self.primitives.drain(..))
+            .chain(self.unbounded_ids.iter().copied().zip(self.unbounded.drain(..)))
+            .collect();
+        objects.sort_by_key(|(id, _)| *id);
+
+        let mut items = Vec::with_capacity(objects.len());
+        let mut unbounded = Vec::new();
+        for (id, obj) in objects {
+            match obj.bounding_box() {
+                Some(aabb) => items.push(BuildItem { obj, id, centroid: aabb.centroid(), aabb }),
+                None => unbounded.push((obj, id)),
+            }
+        }
+
+        let mut bvh = Self::with_capacity(items.len(), self.max_leaf_size);
+        (bvh.unbounded, bvh.unbounded_ids) = unbounded.into_iter().unzip();
+        if !items.is_empty() {
+            bvh.stats = bvh.build_sah(&mut items, self.max_leaf_size, 0, build_threads() as usize).1;
+            debug_assert!(bvh.stats.depth < STACK_SIZE);
+        }
+        bvh.built_cost = bvh.stats.sah_cost;
+        *self = bvh;
+    }
+
+
+    /// 将另一个线程构建的子树拼接在数组的末尾，子树中记录的位置都需要加上偏移
+    fn append(&mut self, subtree: BVHNode)
+    {
+        let node_base = self.nodes.len() as u32;
+        let primitive_base = self.primitives.len() as u32;
+
+        self.nodes.extend(subtree.nodes.into_iter().map(|mut node| {
+            node.offset += if node.count > 0 { primitive_base } else { node_base };
+            node
+        }));
+        self.primitives.extend(subtree.primitives);
+        self.ids.extend(subtree.ids);
+    }
+
+
+    fn push_leaf(&mut self, aabb: AABB, items: &[BuildItem]) -> BvhStats
+    {
+        let offset = self.primitives.len();
+        let count = items.len();
+        self.primitives.extend(items.iter().map(|item| item.obj.clone()));
+        self.ids.extend(items.iter().map(|item| item.id));
+
+        self.nodes.push(LinearNode { aabb, offset: offset as u32, count: count as u16, axis: 0 });
+        BvhStats::leaf(count)
+    }
+
+
+    /// 先放入内部节点，再依次构建两个子树，返回内部节点的统计信息
+    fn push_interior<L, R>(&mut self, aabb: AABB, axis: usize, build_left: L, build_right: R) -> BvhStats
+        where L: FnOnce(&mut BVHNode) -> (AABB, BvhStats),
+              R: FnOnce(&mut BVHNode) -> (AABB, BvhStats)
+    {
+        let idx = self.nodes.len();
+        let area = aabb.surface_area();
+        self.nodes.push(LinearNode { aabb, offset: 0, count: 0, axis: axis as u8 });
+
+        let (box_left, stats_left) = build_left(self);
+        self.nodes[idx].offset = self.nodes.len() as u32;
+        let (box_right, stats_right) = build_right(self);
+
+        BvhStats::node(&stats_left, box_left.surface_area(), &stats_right, box_right.surface_area(), area)
+    }
+
+
+    /// 构建子树，返回子树的包围盒以及统计信息
+    fn build_sah(&mut self, items: &mut [BuildItem], max_leaf_size: usize, depth: usize, threads: usize) -> (AABB, BvhStats)
+    {
+        debug_assert!(!items.is_empty());
+
+        let aabb = items[1..].iter().fold(items[0].aabb.clone(), |aabb, item| AABB::combine(&aabb, &item.aabb));
+        if items.len() == 1 {
+            return (aabb.clone(), self.push_leaf(aabb, items));
+        }
+
+        // 包围盒中心的范围，桶是在这个范围内均匀划分的
+        let mut c_min = items[0].centroid;
+        let mut c_max = items[0].centroid;
+        for item in &items[1..] {
+            c_min = glm::min(c_min, item.centroid);
+            c_max = glm::max(c_max, item.centroid);
+        }
+        let bin_index = |centroid: &Vec3, axis: usize| {
+            let extent = c_max[axis] - c_min[axis];
+            (((centroid[axis] - c_min[axis]) / extent * SAH_BINS as Float) as usize).min(SAH_BINS - 1)
+        };
+
+        // 找到代价最小的 (axis, 划分位置)，划分位置 k 表示 [0, k) 的桶位于左侧
+        let parent_area = aabb.surface_area();
+        let mut best: Option<(usize, usize, Float)> = None;
+        for axis in 0..3 {
+            if c_max[axis] - c_min[axis] <= 0.0 || depth >= MAX_SAH_DEPTH {
+                continue;
+            }
+
+            let mut bins = vec![Bin::default(); SAH_BINS];
+            for item in items.iter() {
+                let bin = &mut bins[bin_index(&item.centroid, axis)];
+                bin.aabb = grow(&bin.aabb, &item.aabb);
+                bin.count += 1;
+            }
+
+            // 从右向左累积，得到每个划分位置右侧的面积和数量
+            let mut right_area = [0.0; SAH_BINS];
+            let mut right_count = [0; SAH_BINS];
+            let mut acc = Bin::default();
+            for k in (1..SAH_BINS).rev() {
+                if let Some(aabb) = &bins[k].aabb {
+                    acc.aabb = grow(&acc.aabb, aabb);
+                }
+                acc.count += bins[k].count;
+                right_area[k] = area(&acc.aabb);
+                right_count[k] = acc.count;
+            }
+
+            let mut acc = Bin::default();
+            for k in 1..SAH_BINS {
+                if let Some(aabb) = &bins[k - 1].aabb {
+                    acc.aabb = grow(&acc.aabb, aabb);
+                }
+                acc.count += bins[k - 1].count;
+                if acc.count == 0 || right_count[k] == 0 {
+                    continue;
+                }
+
+                let cost = TRAVERSAL_COST + INTERSECT_COST
+                    * (area(&acc.aabb) * acc.count as Float + right_area[k] * right_count[k] as Float) / parent_area;
+                if best.is_none_or(|(_, _, best_cost)| cost < best_cost) {
+                    best = Some((axis, k, cost));
+                }
+            }
+        }
+
+        let leaf_cost = INTERSECT_COST * items.len() as Float;
+        let (axis, mid) = match best {
+            Some((_, _, cost)) if items.len() <= max_leaf_size && leaf_cost <= cost => {
+                return (aabb.clone(), self.push_leaf(aabb, items));
+            }
+            Some((axis, k, _)) => (axis, partition(items, |item| bin_index(&item.centroid, axis) < k)),
+
+            // 所有物体的中心重合，无法划分
+            None if items.len() <= max_leaf_size => {
+                return (aabb.clone(), self.push_leaf(aabb, items));
+            }
+
+            // 中心重合，或者深度过大时，沿着中心范围最大的轴对半划分
+            None => {
+                let extent = c_max - c_min;
+                let axis = if extent.x >= extent.y && extent.x >= extent.z { 0 } else if extent.y >= extent.z { 1 } else { 2 };
+                let mid = items.len() / 2;
+                items.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+                (axis, mid)
+            }
+        };
+
+        let (items_left, items_right) = items.split_at_mut(mid);
+        if threads <= 1 || items_left.len().min(items_right.len()) < PARALLEL_BUILD_MIN {
+            let stats = self.push_interior(aabb.clone(), axis,
+                                           |bvh| bvh.build_sah(items_left, max_leaf_size, depth + 1, threads),
+                                           |bvh| bvh.build_sah(items_right, max_leaf_size, depth + 1, threads));
+            return (aabb, stats);
+        }
+
+        // 左子树交给新的线程，右子树在当前线程中构建
+        let left_threads = threads / 2;
+        let right_threads = threads - left_threads;
+        let build_subtree = |items: &mut [BuildItem], threads: usize| {
+            let mut subtree = Self::with_capacity(items.len(), max_leaf_size);
+            let (aabb, stats) = subtree.build_sah(items, max_leaf_size, depth + 1, threads);
+            (subtree, aabb, stats)
+        };
+        let (left, right) = thread::scope(|scope| {
+            let left = scope.spawn(|| build_subtree(items_left, left_threads));
+            let right = build_subtree(items_right, right_threads);
+            (left.join().unwrap(), right)
+        });
+
+        let stats = self.push_interior(aabb.clone(), axis,
+                                       |bvh| {
+                                           bvh.append(left.0);
+                                           (left.1, left.2)
+                                       },
+                                       |bvh| {
+                                           bvh.append(right.0);
+                                           (right.1, right.2)
+                                       });
+        (aabb, stats)
+    }
+
+
     /// 构建 BVH，确保这些 object 都是存在包围盒的
     /// BVH 的构建策略：
-    /// - 如果只有一个物体，那么将左右子节点都设为这个物体
+    /// - 如果只有一个物体，那么就是叶节点
     /// - 如果有多个物体，就随机选择一个轴进行排序，再对半分
-    pub fn new(objects: &[Arc<dyn Hittable + Send + Sync>]) -> BVHNode
+    fn build_median(&mut self, items: &mut [BuildItem]) -> (AABB, BvhStats)
     {
-        debug_assert!(!objects.is_empty());
+        debug_assert!(!items.is_empty());
 
-        let left: Arc<dyn Hittable + Send + Sync>;
-        let right: Arc<dyn Hittable + Send + Sync>;
+        if items.len() == 1 {
+            let aabb = items[0].aabb.clone();
+            return (aabb.clone(), self.push_leaf(aabb, items));
+        }
 
         // 随机选择一个轴，并使用对应的 comparator
-        let comparator = match Axis::rand() {
+        let axis = Axis::rand();
+        let comparator = match axis {
             Axis::X => AABB::compare_x,
             Axis::Y => AABB::compare_y,
             Axis::Z => AABB::compare_z,
         };
+        items.sort_by(|a, b| comparator(a.obj.deref(), b.obj.deref()));
+
+        let aabb = items[1..].iter().fold(items[0].aabb.clone(), |aabb, item| AABB::combine(&aabb, &item.aabb));
+        let (left, right) = items.split_at_mut(items.len() / 2);
+        let stats = self.push_interior(aabb.clone(), axis as usize, |bvh| bvh.build_median(left), |bvh| bvh.build_median(right));
+        (aabb, stats)
+    }
+}
+
+
+/// 所有物体的包围盒，物体很多时使用多个线程计算
+fn bounding_boxes(objects: &[Arc<dyn Hittable + Send + Sync>], threads: usize) -> Vec<Option<AABB>>
+{
+    if threads <= 1 || objects.len() < PARALLEL_BUILD_MIN {
+        return objects.iter().map(|obj| obj.bounding_box()).collect();
+    }
+
+    let chunk_size = objects.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let handles: Vec<_> = objects.chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(|obj| obj.bounding_box()).collect::<Vec<_>>()))
+            .collect();
+        handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+    })
+}
+
+
+/// 将满足条件的元素移动到前面，返回满足条件的元素数量
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], pred: F) -> usize
+{
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+
+impl Hittable for BVHNode
+{
+    /// 使用栈遍历 BVH，在内部节点处根据光线的方向先访问较近的子节点，
+    /// 这样找到的交点可以尽早地缩小 t 的范围，剔除更远的节点
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let mut res: Option<HitPayload> = None;
+        let mut closest_so_far = t_range.1;
+
+        if !self.nodes.is_empty() {
+            let dir = *ray.dir();
+            let inv_dir = vec3(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+            let dir_neg = [inv_dir.x < 0.0, inv_dir.y < 0.0, inv_dir.z < 0.0];
+
+            let mut stack = [0_u32; STACK_SIZE];
+            let mut stack_len = 0;
+            let mut idx = 0;
+            loop {
+                let node = &self.nodes[idx];
+                if node.aabb.hit_inv(ray.orig(), &inv_dir, (t_range.0, closest_so_far)) {
+                    if node.count > 0 {
+                        let first = node.offset as usize;
+                        for obj in &self.primitives[first..first + node.count as usize] {
+                            if let Some(payload) = obj.hit(ray, (t_range.0, closest_so_far)) {
+                                closest_so_far = payload.t();
+                                res = Some(payload);
+                            }
+                        }
+                    } else {
+                        // 光线沿着划分轴的负方向前进时，第二个子节点更近
+                        let (near, far) = match dir_neg[node.axis as usize] {
+                            false => (idx as u32 + 1, node.offset),
+                            true => (node.offset, idx as u32 + 1),
+                        };
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                        idx = near as usize;
+                        continue;
+                    }
+                }
 
-        match objects.len() {
-            1 => {
-                left = objects[0].clone();
-                right = objects[0].clone();
-            }
-            2 => {
-                if comparator(objects[0].deref(), objects[1].deref()) == std::cmp::Ordering::Less {
-                    left = objects[0].clone();
-                    right = objects[1].clone();
-                } else {
-                    left = objects[1].clone();
-                    right = objects[0].clone();
+                if stack_len == 0 {
+                    break;
                 }
+                stack_len -= 1;
+                idx = stack[stack_len] as usize;
             }
-            _ => {
-                let mut objects = objects.to_vec();
-                objects.sort_by(|a, b| comparator(a.deref(), b.deref()));
+        }
 
-                let len = objects.len();
-                let mid = len / 2;
-                left = Arc::new(Self::new(&mut objects[0..mid]));
-                right = Arc::new(Self::new(&mut objects[mid..len]));
+        // 没有包围盒的物体需要逐个检测
+        for obj in &self.unbounded {
+            if let Some(payload) = obj.hit(ray, (t_range.0, closest_so_far)) {
+                closest_so_far = payload.t();
+                res = Some(payload);
             }
         }
 
-        let box_left = left.bounding_box().unwrap();
-        let box_right = right.bounding_box().unwrap();
+        res
+    }
 
-        BVHNode {
-            left,
-            right,
-            aabb: AABB::combine(&box_left, &box_right),
+
+    /// 整个光线包一起遍历 BVH：只要有一条光线和节点相交就访问这个节点，叶节点中的物体同时和整个光线包求交
+    ///
+    /// 光线包中光线的方向很接近，因此使用第一条光线的方向决定子节点的访问顺序
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        debug_assert!(t_range.0 < t_range.1);
+
+        if !self.nodes.is_empty() {
+            let dir_neg = [packet.dir()[0][0] < 0.0, packet.dir()[1][0] < 0.0, packet.dir()[2][0] < 0.0];
+
+            let mut stack = [0_u32; STACK_SIZE];
+            let mut stack_len = 0;
+            let mut idx = 0;
+            loop {
+                let node = &self.nodes[idx];
+                if packet.hit_aabb(&node.aabb, t_range.0, packet.t_max(hits, t_range.1)) {
+                    if node.count > 0 {
+                        let first = node.offset as usize;
+                        for obj in &self.primitives[first..first + node.count as usize] {
+                            obj.hit_packet(packet, t_range, hits);
+                        }
+                    } else {
+                        let (near, far) = match dir_neg[node.axis as usize] {
+                            false => (idx as u32 + 1, node.offset),
+                            true => (node.offset, idx as u32 + 1),
+                        };
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                        idx = near as usize;
+                        continue;
+                    }
+                }
+
+                if stack_len == 0 {
+                    break;
+                }
+                stack_len -= 1;
+                idx = stack[stack_len] as usize;
+            }
+        }
+
+        for obj in &self.unbounded {
+            obj.hit_packet(packet, t_range, hits);
         }
     }
 
 
-    pub fn new_with_list(hittalbe_list: &HittableList) -> BVHNode
-    {
-        Self::new(hittalbe_list.objects())
+    /// 找到任意一个交点即可返回，因此不需要按照远近顺序访问子节点
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        debug_assert!(t_range.0 < t_range.1);
+
+        if !self.nodes.is_empty() {
+            let dir = *ray.dir();
+            let inv_dir = vec3(1.0 / dir.x, 1.0 / dir.y, 1.0 / dir.z);
+
+            let mut stack = [0_u32; STACK_SIZE];
+            let mut stack_len = 0;
+            let mut idx = 0;
+            loop {
+                let node = &self.nodes[idx];
+                if node.aabb.hit_inv(ray.orig(), &inv_dir, t_range) {
+                    if node.count > 0 {
+                        let first = node.offset as usize;
+                        if self.primitives[first..first + node.count as usize].iter().any(|obj| obj.occluded(ray, t_range)) {
+                            return true;
+                        }
+                    } else {
+                        stack[stack_len] = node.offset;
+                        stack_len += 1;
+                        idx += 1;
+                        continue;
+                    }
+                }
+
+                if stack_len == 0 {
+                    break;
+                }
+                stack_len -= 1;
+                idx = stack[stack_len] as usize;
+            }
+        }
+
+        self.unbounded.iter().any(|obj| obj.occluded(ray, t_range))
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        match self.unbounded.is_empty() {
+            true => self.nodes.first().map(|node| node.aabb.clone()),
+            false => None,
+        }
     }
 }
 
 
-impl Hittable for BVHNode
+#[cfg(test)]
+mod test
 {
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload> {
-        debug_assert!(t_range.0 < t_range.1);
+    use super::*;
+    use crate::float::{Mat4, vec2};
+    use num::One;
+    use crate::geom::{Axis, MovingSphere, Sphere};
+    use crate::geom::cone::Cone;
+    use crate::geom::cube::Cube;
+    use crate::geom::cylinder::Cylinder;
+    use crate::geom::disk::Disk;
+    use crate::geom::heightfield::Heightfield;
+    use crate::geom::plane::Plane;
+    use crate::geom::quad::Quad;
+    use crate::geom::rect::AxisRect;
+    use crate::geom::torus::Torus;
+    use crate::geom::transform::{MovingTransform, RotateY, Transform, Translate};
+    use crate::geom::triangle::Triangle;
+    use crate::material::{Lambertian, Material};
+    use crate::noise::Perlin;
+    use crate::utility::{rand_unit_vec, random};
 
-        // 首先检查整体的包围盒是否击中，如果击中
-        // 再依次判断左子节点和右子节点是否被击中，并选择 t 最小的作为结果
+    #[test]
+    fn test_sah_bvh()
+    {
+        // 分布很不均匀的场景：一小团密集的球，以及零散分布在远处的球
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let mut objects: Vec<Arc<dyn Hittable + Send + Sync>> = Vec::new();
+        for _ in 0..500 {
+            let center = vec3(random::<Float>(), random::<Float>(), random::<Float>()) * 2.0;
+            objects.push(Arc::new(Sphere::new(center, 0.05, mat.clone())));
+        }
+        for _ in 0..20 {
+            let center = vec3(random::<Float>(), random::<Float>(), random::<Float>()) * 200.0 - vec3(100.0, 100.0, 100.0);
+            objects.push(Arc::new(Sphere::new(center, 1.0, mat.clone())));
+        }
 
-        if !self.aabb.hit(ray, t_range) {
-            return None;
+        assert_eq!(std::mem::size_of::<LinearNode>(), 6 * std::mem::size_of::<Float>() + 8);
+
+        let sah = BVHNode::new_sah(&objects, 4);
+        let median = BVHNode::new_median(&objects);
+        let (sah_stats, median_stats) = (sah.stats(), median.stats());
+        assert_eq!(sah_stats.primitives, objects.len());
+        assert_eq!(sah_stats.leaves, sah_stats.nodes + 1);
+        assert!(sah_stats.sah_cost < median_stats.sah_cost, "sah: {}, median: {}", sah_stats, median_stats);
+
+        // 两种 BVH 和逐个求交的结果相同
+        let mut list = HittableList::default();
+        for obj in &objects {
+            list.add(obj.clone());
         }
+        for _ in 0..1000 {
+            let ray = Ray::new_d(rand_unit_vec() * 3.0 + vec3(1.0, 1.0, 1.0), rand_unit_vec());
+            let expected = list.hit(&ray, (0.001, Float::INFINITY)).map(|payload| payload.t());
+            assert_eq!(sah.hit(&ray, (0.001, Float::INFINITY)).map(|payload| payload.t()), expected);
+            assert_eq!(median.hit(&ray, (0.001, Float::INFINITY)).map(|payload| payload.t()), expected);
+        }
+
+        // 物体的中心重合，无法划分，整个 BVH 只有一个叶节点
+        let same: Vec<Arc<dyn Hittable + Send + Sync>> = (1..=3).map(|i| {
+            Arc::new(Sphere::new(vec3(0.0, 0.0, 0.0), i as Float, mat.clone())) as Arc<dyn Hittable + Send + Sync>
+        }).collect();
+        let small = BVHNode::new_sah(&same, 4);
+        assert_eq!((small.stats().nodes, small.stats().leaves, small.stats().primitives), (0, 1, 3));
+    }
 
-        let left_payload = self.left.hit(ray, t_range);
-        let new_range_max = if let Some(payload) = &left_payload { payload.t() } else { t_range.1 };
-        let right_payload = self.right.hit(ray, (t_range.0, new_range_max));
+    #[test]
+    fn test_parallel_build()
+    {
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let objects: Vec<Arc<dyn Hittable + Send + Sync>> = (0..20000).map(|_| {
+            let center = vec3(random::<Float>(), random::<Float>(), random::<Float>()) * 100.0;
+            Arc::new(Sphere::new(center, 0.2, mat.clone())) as Arc<dyn Hittable + Send + Sync>
+        }).collect();
 
-        Option::or(right_payload, left_payload)
+        // 多线程构建的结果和单线程的完全相同
+        let serial = BVHNode::new_parallel(&objects, 4, 1);
+        let parallel = BVHNode::new_parallel(&objects, 4, 4);
+        assert_eq!(serial.nodes.len(), parallel.nodes.len());
+        assert_eq!(serial.stats().sah_cost, parallel.stats().sah_cost);
+        for (a, b) in serial.nodes.iter().zip(&parallel.nodes) {
+            assert_eq!((a.offset, a.count, a.axis), (b.offset, b.count, b.axis));
+            assert_eq!((a.aabb.min(), a.aabb.max()), (b.aabb.min(), b.aabb.max()));
+        }
+        for (a, b) in serial.primitives.iter().zip(&parallel.primitives) {
+            assert!(Arc::ptr_eq(a, b));
+        }
     }
 
+    #[test]
+    fn test_refit()
+    {
+        // 每个球在 [0, 10] 时间内做直线运动，每一帧只截取其中的一段
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let unit: Arc<dyn Hittable + Send + Sync> = Arc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 0.3, mat.clone()));
+        let translate = |offset: Vec3| glm::ext::translate(&Mat4::one(), offset);
+        let motions: Vec<MovingTransform> = (0..300).map(|_| {
+            let start = vec3(random::<Float>(), random::<Float>(), random::<Float>()) * 20.0;
+            let end = start + rand_unit_vec() * 2.0;
+            MovingTransform::new(unit.clone(), translate(start), 0.0, translate(end), 10.0)
+        }).collect();
+        let frame = |k: usize| -> Vec<Arc<dyn Hittable + Send + Sync>> {
+            motions.iter().map(|m| Arc::new(m.window(k as Float, k as Float + 1.0)) as Arc<dyn Hittable + Send + Sync>).collect()
+        };
 
-    fn bounding_box(&self) -> Option<AABB> {
-        Some(self.aabb.clone())
+        let mut bvh = BVHNode::new_sah(&frame(0), 4);
+        let nodes = bvh.nodes.len();
+        for k in 1..10 {
+            let objects = frame(k);
+            let rebuilt = bvh.refit_or_rebuild(|id, _| Some(objects[id].clone()), 1.5);
+            assert!(!rebuilt, "frame {}: {}", k, bvh.stats());
+            assert_eq!(bvh.nodes.len(), nodes);
+
+            // refit 之后的 BVH 和逐个求交的结果相同
+            let mut list = HittableList::default();
+            for obj in &objects {
+                list.add(obj.clone());
+            }
+            for _ in 0..200 {
+                let ray = Ray::new_d(rand_unit_vec() * 5.0 + vec3(10.0, 10.0, 10.0), rand_unit_vec())
+                    .with_time(k as Float + random::<Float>());
+                assert_eq!(bvh.hit(&ray, (0.001, Float::INFINITY)).map(|payload| payload.t()),
+                           list.hit(&ray, (0.001, Float::INFINITY)).map(|payload| payload.t()));
+            }
+        }
+
+        // 物体被打乱之后，refit 得到的树质量很差，需要重新构建
+        let targets: Vec<Vec3> = (0..motions.len()).map(|_| vec3(random::<Float>(), random::<Float>(), random::<Float>()) * 20.0).collect();
+        let rebuilt = bvh.refit_or_rebuild(|id, _| Some(Arc::new(Sphere::new(targets[id], 0.3, mat.clone())) as Arc<dyn Hittable + Send + Sync>), 1.5);
+        assert!(rebuilt);
+        let cost = bvh.stats().sah_cost;
+
+        // 重新构建后物体的编号保持不变
+        bvh.refit(|id, obj| {
+            assert!(glm::length(obj.bounding_box().unwrap().centroid() - targets[id]) < 1e-4);
+            None
+        });
+        assert_eq!(bvh.stats().sah_cost, cost);
+    }
+
+    #[test]
+    fn test_refit_unbounded()
+    {
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let spheres: Vec<Arc<dyn Hittable + Send + Sync>> = (0..20)
+            .map(|i| Arc::new(Sphere::new(vec3(i as Float * 2.0, 0.0, 0.0), 0.5, mat.clone())) as Arc<dyn Hittable + Send + Sync>)
+            .collect();
+        let plane: Arc<dyn Hittable + Send + Sync> = Arc::new(Plane::new(vec3(0.0, -3.0, 0.0), vec3(0.0, 1.0, 0.0), mat));
+        let mut bvh = BVHNode::new_sah(&spheres, 2);
+
+        // 球被替换为无限大的平面，树结构无法容纳，只能重新构建
+        let rebuilt = bvh.refit(|id, _| if id == 7 { Some(plane.clone()) } else { None });
+        assert!(rebuilt);
+        assert_eq!((bvh.primitives.len(), bvh.unbounded.len()), (19, 1));
+        let down = Ray::new_d(vec3(100.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0));
+        assert_eq!(bvh.hit(&down, (0.001, Float::INFINITY)).map(|payload| payload.t()), Some(3.0));
+        let sphere = Ray::new_d(vec3(14.0, 5.0, 0.0), vec3(0.0, -1.0, 0.0));
+        assert_eq!(bvh.hit(&sphere, (0.001, Float::INFINITY)).map(|payload| payload.t()), Some(8.0));
+
+        // 编号保持不变，平面换回球之后，再次重新构建
+        let mut visited = vec![false; spheres.len()];
+        let rebuilt = bvh.refit(|id, obj| {
+            visited[id] = true;
+            assert_eq!(id == 7, Arc::ptr_eq(obj, &plane));
+            if id == 7 { Some(spheres[7].clone()) } else { None }
+        });
+        assert!(rebuilt && visited.iter().all(|&v| v));
+        assert_eq!((bvh.primitives.len(), bvh.unbounded.len()), (20, 0));
+        assert_eq!(bvh.hit(&sphere, (0.001, Float::INFINITY)).map(|payload| payload.t()), Some(4.5));
+        assert!(bvh.hit(&down, (0.001, Float::INFINITY)).is_none());
+
+        // 有界的物体保持有界时，只进行 refit
+        assert!(!bvh.refit(|_, _| None));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_occluded()
+    {
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let cube: Arc<dyn Hittable + Send + Sync> = Arc::new(Cube::new(vec3(-0.5, -0.5, -0.5), vec3(0.5, 0.5, 0.5), mat.clone()));
+        let objects: Vec<Arc<dyn Hittable + Send + Sync>> = vec![
+            Arc::new(Sphere::new(vec3(0.0, 1.0, 0.0), 0.5, mat.clone())),
+            Arc::new(MovingSphere::new(vec3(2.0, 1.0, 0.0), 0.0, vec3(2.0, 2.0, 0.0), 1.0, 0.5, mat.clone())),
+            Arc::new(Quad::new(vec3(-3.0, 0.0, -1.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 1.0), mat.clone())),
+            Arc::new(AxisRect::new(vec2(-1.0, -1.0), vec2(1.0, 1.0), 3.0, mat.clone(), Axis::Z)),
+            Arc::new(Disk::new(vec3(0.0, -2.0, 0.0), 1.0, mat.clone())),
+            Arc::new(Cylinder::new(vec3(3.0, -1.0, 2.0), 0.5, 1.0, true, mat.clone())),
+            Arc::new(Cone::new(vec3(-2.0, -1.0, 2.0), 0.5, 1.0, true, mat.clone())),
+            Arc::new(Torus::new(vec3(0.0, 2.5, -2.0), 0.8, 0.2, mat.clone())),
+            Arc::new(Triangle::new(vec3(1.0, -1.0, -3.0), vec3(3.0, -1.0, -3.0), vec3(2.0, 1.0, -3.0), mat.clone())),
+            Arc::new(Translate::new(cube.clone(), vec3(-3.0, 2.0, 0.0))),
+            Arc::new(RotateY::new(Arc::new(Translate::new(cube.clone(), vec3(3.0, 2.0, -1.0))), 30.0)),
+            Arc::new(Transform::new(cube, glm::ext::translate(&Mat4::one(), vec3(0.0, 0.0, 3.5)))),
+            Arc::new(Heightfield::new_noise(&Perlin::new(), 2.0, (9, 9), vec3(-4.0, -4.0, -4.0), vec3(8.0, 1.0, 8.0), mat.clone())),
+            Arc::new(Plane::new(vec3(0.0, -5.0, 0.0), vec3(0.0, 1.0, 0.0), mat)),
+        ];
+        let bvh = BVHNode::new_sah(&objects, 2);
+
+        // 任意范围内，遮挡检测的结果都和是否存在交点一致
+        for _ in 0..5000 {
+            let ray = Ray::new_d(rand_unit_vec() * 6.0, rand_unit_vec()).with_time(random::<Float>());
+            let t_max = if random::<Float>() < 0.2 { Float::INFINITY } else { random::<Float>() * 12.0 + 0.01 };
+            let t_range = (0.001, t_max);
+
+            for obj in &objects {
+                assert_eq!(obj.occluded(&ray, t_range), obj.hit(&ray, t_range).is_some());
+            }
+            assert_eq!(bvh.occluded(&ray, t_range), bvh.hit(&ray, t_range).is_some());
+        }
+    }
+}
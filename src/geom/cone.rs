@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use crate::geom::aabb::AABB;
+use crate::geom::disk::{azimuth_u, disk_payload, intersect_disk};
+use crate::hit::{HitPayload, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::{check_and, gamma};
+use crate::float::{Float, Vec3, vec2, vec3};
+
+
+/// 圆锥，底面圆心为 base，顶点位于 base 上方 height 处
+///
+/// capped 为 false 时没有底面
+pub struct Cone
+{
+    base: Vec3,
+    radius: Float,
+    height: Float,
+    capped: bool,
+    mat: Arc<dyn Material + Send + Sync>,
+}
+
+
+impl Cone
+{
+    pub fn new(base: Vec3, radius: Float, height: Float, capped: bool, mat: Arc<dyn Material + Send + Sync>) -> Cone
+    {
+        debug_assert!(check_and(&base, Float::is_finite));
+        debug_assert!(radius.is_finite() && radius > 0.0);
+        debug_assert!(height.is_finite() && height > 0.0);
+
+        Cone { base, radius, height, capped, mat }
+    }
+
+
+    /// 和侧面求交
+    fn hit_side(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload>
+    {
+        let root = self.side_root(ray, t_range)?;
+        let p = ray.at(root) - self.base;
+        let k2 = (self.radius / self.height) * (self.radius / self.height);
+
+        // 隐式方程的梯度就是法线方向，在顶点处退化
+        let normal = vec3(p.x, k2 * (self.height - p.y), p.z);
+        let len = glm::length(normal);
+        let normal = if len > 0.0 { normal / len } else { vec3(0.0, 1.0, 0.0) };
+
+        let uv = vec2(azimuth_u(p.x, p.z), p.y / self.height);
+        let mut payload = HitPayload::new(ray, root, normal, self.mat.clone(), uv);
+
+        // 保持高度不变，将交点投影回侧面上，误差只和交点的坐标有关，和 t 的误差无关
+        let scale = self.radius * (self.height - p.y) / self.height / Float::sqrt(p.x * p.x + p.z * p.z);
+        if scale.is_finite() {
+            let local = vec3(p.x * scale, p.y, p.z * scale);
+            let world = local + self.base;
+            payload.set_hit_point(world, vec3(local.x.abs(), 0.0, local.z.abs()) * gamma(7) + glm::abs(world) * gamma(1));
+        }
+        Some(payload)
+    }
+
+
+    /// 光线和侧面最近的交点在 t_range 范围内的 t
+    ///
+    /// 侧面满足 x^2 + z^2 = k^2 (h - y)^2，其中 k = r / h，坐标相对于底面圆心
+    fn side_root(&self, ray: &Ray, t_range: (Float, Float)) -> Option<Float>
+    {
+        let o = *ray.orig() - self.base;
+        let d = *ray.dir();
+        let k2 = (self.radius / self.height) * (self.radius / self.height);
+        let h = self.height - o.y;
+
+        let a = d.x * d.x + d.z * d.z - k2 * d.y * d.y;
+        let half_b = o.x * d.x + o.z * d.z + k2 * h * d.y;
+        let c = o.x * o.x + o.z * o.z - k2 * h * h;
+
+        // a 为 0 时光线和侧面的母线平行，方程退化为一次方程
+        let mut roots = [Float::NAN, Float::NAN];
+        if a.abs() < 1e-8 {
+            if half_b != 0.0 {
+                roots[0] = -c / (2.0 * half_b);
+            }
+        } else {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrtd = discriminant.sqrt();
+            roots = [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a];
+            if roots[0] > roots[1] {
+                roots.swap(0, 1);
+            }
+        }
+
+        for root in roots {
+            // NaN 也会在这里被排除
+            if !(root > t_range.0 && root < t_range.1) {
+                continue;
+            }
+            // 只保留 [0, height] 范围内的一支，排除顶点上方的镜像圆锥
+            let p = ray.at(root) - self.base;
+            if p.y < 0.0 || p.y > self.height {
+                continue;
+            }
+            return Some(root);
+        }
+        None
+    }
+}
+
+
+impl Hittable for Cone
+{
+    /// 侧面的纹理坐标：u 是方位角，v 是高度；底面的纹理坐标和 `Disk` 相同
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let side = self.hit_side(ray, t_range);
+        if !self.capped {
+            return side;
+        }
+
+        let t_max = side.as_ref().map_or(t_range.1, |payload| payload.t());
+        match intersect_disk(ray, &self.base, self.radius, (t_range.0, t_max)) {
+            None => side,
+            Some((t, offset)) => Some(disk_payload(ray, t, &self.base, &offset, self.radius, -1.0, &self.mat)),
+        }
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.side_root(ray, t_range).is_some()
+            || (self.capped && intersect_disk(ray, &self.base, self.radius, t_range).is_some())
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::new(self.base - vec3(self.radius, 0.0, self.radius),
+                       self.base + vec3(self.radius, self.height, self.radius)))
+    }
+}
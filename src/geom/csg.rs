@@ -0,0 +1,258 @@
+use std::sync::Arc;
+use crate::geom::aabb::AABB;
+use crate::hit::{HitPayload, Hittable};
+use crate::ray::Ray;
+use crate::float::Float;
+
+
+/// 一条光线和一个物体最多记录的交点数量，避免退化的情况下无限循环
+const MAX_HITS: usize = 64;
+
+
+/// CSG 的布尔运算
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsgOp
+{
+    /// 并集：位于 a 或者 b 的内部
+    Union,
+
+    /// 交集：同时位于 a 和 b 的内部
+    Intersection,
+
+    /// 差集：位于 a 的内部，但是不在 b 的内部
+    Difference,
+}
+
+
+impl CsgOp
+{
+    #[inline(always)]
+    fn inside(&self, inside_a: bool, inside_b: bool) -> bool
+    {
+        match self {
+            CsgOp::Union => inside_a || inside_b,
+            CsgOp::Intersection => inside_a && inside_b,
+            CsgOp::Difference => inside_a && !inside_b,
+        }
+    }
+}
+
+
+/// 构造实体几何（Constructive Solid Geometry），对两个封闭的物体进行布尔运算
+///
+/// 沿着光线依次遍历两个物体的所有交点，根据 front_face 得到光线在每个物体内外的状态，
+/// 组合后的状态发生变化的位置就是 CSG 的表面。
+/// 表面的材质来自产生这个交点的物体，例如差集中被切掉的部分，使用 b 的材质。
+/// CSG 本身也是封闭的，因此可以嵌套，也可以作为 `ConstantMedium` 的包围体
+pub struct Csg
+{
+    op: CsgOp,
+    a: Arc<dyn Hittable + Send + Sync>,
+    b: Arc<dyn Hittable + Send + Sync>,
+    aabb: Option<AABB>,
+}
+
+
+impl Csg
+{
+    /// a 和 b 都需要是封闭的物体，front_face 为 true 表示光线从外部进入物体
+    pub fn new(op: CsgOp, a: Arc<dyn Hittable + Send + Sync>, b: Arc<dyn Hittable + Send + Sync>) -> Csg
+    {
+        let box_a = a.bounding_box();
+        let box_b = b.bounding_box();
+
+        let aabb = match op {
+            CsgOp::Union => match (box_a, box_b) {
+                (Some(box_a), Some(box_b)) => Some(AABB::combine(&box_a, &box_b)),
+                _ => None,
+            },
+            CsgOp::Intersection => match (box_a, box_b) {
+                (Some(box_a), Some(box_b)) => {
+                    let min = glm::max(*box_a.min(), *box_b.min());
+                    let max = glm::min(*box_a.max(), *box_b.max());
+
+                    // 交集为空时，物体不会被击中，保留 a 的 AABB 即可
+                    if min.x <= max.x && min.y <= max.y && min.z <= max.z {
+                        Some(AABB::new(min, max))
+                    } else {
+                        Some(box_a)
+                    }
+                }
+                (box_a, box_b) => box_a.or(box_b),
+            },
+            CsgOp::Difference => box_a,
+        };
+
+        Csg { op, a, b, aabb }
+    }
+
+
+    pub fn op(&self) -> CsgOp { self.op }
+}
+
+
+/// 光线和物体在 t_range 范围内的所有交点，按照 t 从小到大排列
+fn all_hits(obj: &dyn Hittable, ray: &Ray, t_range: (Float, Float)) -> Vec<HitPayload>
+{
+    let mut hits = Vec::new();
+    let mut t_min = t_range.0;
+
+    while hits.len() < MAX_HITS && t_min < t_range.1 {
+        match obj.hit(ray, (t_min, t_range.1)) {
+            None => break,
+            Some(payload) => {
+                // 跳过当前的交点，继续寻找下一个
+                t_min = payload.t_next(ray);
+                hits.push(payload);
+            }
+        }
+    }
+
+    hits
+}
+
+
+/// 在 t_range 的起点处，光线是否位于物体的内部，hits 是 t_range 内的所有交点
+fn inside_at_start(obj: &dyn Hittable, ray: &Ray, t_range: (Float, Float), hits: &[HitPayload]) -> bool
+{
+    // 第一个交点是离开物体的交点，说明起点位于物体内部
+    if let Some(first) = hits.first() {
+        return !first.front_face();
+    }
+
+    // 范围内没有交点，需要看范围之外的下一个交点
+    if t_range.1 == Float::INFINITY {
+        return false;
+    }
+    obj.hit(ray, (t_range.1, Float::INFINITY)).is_some_and(|payload| !payload.front_face())
+}
+
+
+impl Hittable for Csg
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let hits_a = all_hits(self.a.as_ref(), ray, t_range);
+        let mut inside_a = inside_at_start(self.a.as_ref(), ray, t_range, &hits_a);
+
+        // 交集和差集都需要位于 a 的内部
+        if self.op != CsgOp::Union && hits_a.is_empty() && !inside_a {
+            return None;
+        }
+
+        let hits_b = all_hits(self.b.as_ref(), ray, t_range);
+        let mut inside_b = inside_at_start(self.b.as_ref(), ray, t_range, &hits_b);
+
+        // 按照 t 的顺序合并两个物体的交点，找到第一个使组合状态发生变化的交点
+        let mut inside = self.op.inside(inside_a, inside_b);
+        let mut iter_a = hits_a.into_iter().peekable();
+        let mut iter_b = hits_b.into_iter().peekable();
+
+        loop {
+            let from_a = match (iter_a.peek(), iter_b.peek()) {
+                (None, None) => return None,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(a), Some(b)) => a.t() <= b.t(),
+            };
+
+            let mut payload = if from_a { iter_a.next()? } else { iter_b.next()? };
+            if from_a {
+                inside_a = payload.front_face();
+            } else {
+                inside_b = payload.front_face();
+            }
+
+            let now_inside = self.op.inside(inside_a, inside_b);
+            if now_inside != inside {
+                // 法线始终和光线相对，只需要根据 CSG 的内外状态重新设置 front_face
+                // 例如差集中 b 的表面：离开 b 时进入 CSG，物体的实际法线需要翻转
+                payload.set_normal(*payload.normal(), now_inside);
+                return Some(payload);
+            }
+            inside = now_inside;
+        }
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.aabb.clone()
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::float::{Vec3, vec3};
+    use crate::geom::Sphere;
+    use crate::geom::volumn::ConstantMedium;
+    use crate::material::{Lambertian, Material};
+
+    fn sphere(center: Vec3, radius: Float) -> Arc<dyn Hittable + Send + Sync>
+    {
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        Arc::new(Sphere::new(center, radius, mat))
+    }
+
+    #[test]
+    fn test_csg_hit()
+    {
+        let origin = vec3(-10.0, 0.0, 0.0);
+        let ray = Ray::new(origin, vec3(0.0, 0.0, 0.0));
+        let t_range = (0.001, Float::INFINITY);
+
+        // 空心球：外侧的表面朝外，内侧的表面朝向球心
+        let shell = Arc::new(Csg::new(CsgOp::Difference, sphere(vec3(0.0, 0.0, 0.0), 2.0), sphere(vec3(0.0, 0.0, 0.0), 1.0)));
+        let payload = shell.hit(&ray, t_range).unwrap();
+        assert!((payload.t() - 8.0).abs() < 1e-4 && payload.front_face());
+        let payload = shell.hit(&ray, (8.5, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 9.0).abs() < 1e-4 && !payload.front_face());
+        assert!(glm::length(payload.obj_normal() - vec3(1.0, 0.0, 0.0)) < 1e-4);
+        let payload = shell.hit(&ray, (9.5, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 11.0).abs() < 1e-4 && payload.front_face());
+        assert!(glm::length(payload.obj_normal() - vec3(-1.0, 0.0, 0.0)) < 1e-4);
+
+        // 两个球的交集构成透镜
+        let lens = Arc::new(Csg::new(CsgOp::Intersection, sphere(vec3(-1.5, 0.0, 0.0), 2.0), sphere(vec3(1.5, 0.0, 0.0), 2.0)));
+        let payload = lens.hit(&ray, t_range).unwrap();
+        assert!((payload.t() - 9.5).abs() < 1e-4 && payload.front_face());
+        assert!(glm::length(*lens.bounding_box().unwrap().min() - vec3(-0.5, -2.0, -2.0)) < 1e-5);
+
+        // 嵌套：空心球和另一个透镜的并集，透镜填满了空心的部分，内部的表面被消除
+        let lens = Arc::new(Csg::new(CsgOp::Intersection, sphere(vec3(-0.8, 0.0, 0.0), 2.0), sphere(vec3(0.8, 0.0, 0.0), 2.0)));
+        let nested = Csg::new(CsgOp::Union, shell.clone(), lens);
+        let payload = nested.hit(&ray, t_range).unwrap();
+        assert!((payload.t() - 8.0).abs() < 1e-4);
+        let payload = nested.hit(&ray, (8.5, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 12.0).abs() < 1e-4 && !payload.front_face());
+
+        // 起点位于物体内部
+        let payload = shell.hit(&Ray::new(vec3(1.5, 0.0, 0.0), vec3(3.0, 0.0, 0.0)), t_range).unwrap();
+        assert!((payload.t() - 0.5).abs() < 1e-4 && !payload.front_face());
+    }
+
+    #[test]
+    fn test_csg_medium()
+    {
+        // 以空心球为包围体的烟雾，散射只会发生在球壳中
+        let shell = Arc::new(Csg::new(CsgOp::Difference, sphere(vec3(0.0, 0.0, 0.0), 2.0), sphere(vec3(0.0, 0.0, 0.0), 1.0)));
+        let medium = ConstantMedium::new_c(shell, 0.5, vec3(1.0, 1.0, 1.0));
+
+        let ray = Ray::new(vec3(-10.0, 0.0, 0.0), vec3(0.0, 0.0, 0.0));
+        let mut count = 0;
+        for _ in 0..1000 {
+            if let Some(payload) = medium.hit(&ray, (0.001, Float::INFINITY)) {
+                let x = ray.at(payload.t()).x.abs();
+                assert!((1.0 - 1e-3..=2.0 + 1e-3).contains(&x), "x = {}", x);
+                count += 1;
+            }
+        }
+
+        // 穿过的总长度为 2，不发生散射的概率为 exp(-1)
+        let expected = 1000.0 * (1.0 - Float::exp(-1.0));
+        assert!((count as Float - expected).abs() < 60.0, "count = {}", count);
+    }
+}
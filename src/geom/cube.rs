@@ -7,33 +7,34 @@ use crate::hit::{HitPayload, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::utility::check_and;
+use crate::float::{Float, Vec3, vec2};
 
 
 /// 轴对齐的长方体
 pub struct Cube
 {
-    box_min: glm::Vec3,
-    box_max: glm::Vec3,
+    box_min: Vec3,
+    box_max: Vec3,
     sides: HittableList,
 }
 
 
 impl Cube
 {
-    pub fn new(p0: glm::Vec3, p1: glm::Vec3, mat: Arc<dyn Material + Sync + Send>) -> Cube
+    pub fn new(p0: Vec3, p1: Vec3, mat: Arc<dyn Material + Sync + Send>) -> Cube
     {
-        debug_assert!(check_and(&p0, f32::is_finite));
-        debug_assert!(check_and(&p1, f32::is_finite));
+        debug_assert!(check_and(&p0, Float::is_finite));
+        debug_assert!(check_and(&p1, Float::is_finite));
         debug_assert!(p0.x < p1.x && p0.y < p1.y && p0.z < p1.z);
 
-        let front = AxisRect::new(glm::vec2(p0.x, p0.y), glm::vec2(p1.x, p1.y), p1.z, mat.clone(), Axis::Z);
-        let back = AxisRect::new(glm::vec2(p0.x, p0.y), glm::vec2(p1.x, p1.y), p0.z, mat.clone(), Axis::Z);
+        let front = AxisRect::new(vec2(p0.x, p0.y), vec2(p1.x, p1.y), p1.z, mat.clone(), Axis::Z);
+        let back = AxisRect::new(vec2(p0.x, p0.y), vec2(p1.x, p1.y), p0.z, mat.clone(), Axis::Z);
 
-        let up = AxisRect::new(glm::vec2(p0.x, p0.z), glm::vec2(p1.x, p1.z), p1.y, mat.clone(), Axis::Y);
-        let down = AxisRect::new(glm::vec2(p0.x, p0.z), glm::vec2(p1.x, p1.z), p0.y, mat.clone(), Axis::Y);
+        let up = AxisRect::new(vec2(p0.x, p0.z), vec2(p1.x, p1.z), p1.y, mat.clone(), Axis::Y);
+        let down = AxisRect::new(vec2(p0.x, p0.z), vec2(p1.x, p1.z), p0.y, mat.clone(), Axis::Y);
 
-        let right = AxisRect::new(glm::vec2(p0.y, p0.z), glm::vec2(p1.y, p1.z), p1.x, mat.clone(), Axis::X);
-        let left = AxisRect::new(glm::vec2(p0.y, p0.z), glm::vec2(p1.y, p1.z), p0.x, mat.clone(), Axis::X);
+        let right = AxisRect::new(vec2(p0.y, p0.z), vec2(p1.y, p1.z), p1.x, mat.clone(), Axis::X);
+        let left = AxisRect::new(vec2(p0.y, p0.z), vec2(p1.y, p1.z), p0.x, mat.clone(), Axis::X);
 
 
         let mut sides = HittableList::default();
@@ -51,10 +52,14 @@ impl Cube
 
 impl Hittable for Cube
 {
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload> {
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
         self.sides.hit(ray, t_range)
     }
 
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.sides.occluded(ray, t_range)
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
         Some(AABB::new(self.box_min, self.box_max))
     }
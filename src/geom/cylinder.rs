@@ -0,0 +1,253 @@
+use std::sync::Arc;
+use num::traits::FloatConst;
+use rand::Rng;
+use crate::geom::aabb::AABB;
+use crate::geom::disk::{azimuth_u, disk_payload, intersect_disk, rand_in_disk};
+use crate::hit::{HitPayload, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::{check_and, gamma, rng};
+use crate::float::{Float, Vec3, vec2, vec3};
+
+
+/// 圆柱体，底面圆心为 base，沿着 +Y 方向延伸 height
+///
+/// capped 为 false 时是没有上下底面的圆柱面
+pub struct Cylinder
+{
+    base: Vec3,
+    radius: Float,
+    height: Float,
+    capped: bool,
+    mat: Arc<dyn Material + Send + Sync>,
+}
+
+
+impl Cylinder
+{
+    pub fn new(base: Vec3, radius: Float, height: Float, capped: bool, mat: Arc<dyn Material + Send + Sync>) -> Cylinder
+    {
+        debug_assert!(check_and(&base, Float::is_finite));
+        debug_assert!(radius.is_finite() && radius > 0.0);
+        debug_assert!(height.is_finite() && height > 0.0);
+
+        Cylinder { base, radius, height, capped, mat }
+    }
+
+
+    fn side_area(&self) -> Float { 2.0 * Float::PI() * self.radius * self.height }
+    fn cap_area(&self) -> Float { Float::PI() * self.radius * self.radius }
+
+    pub fn area(&self) -> Float
+    {
+        if self.capped { self.side_area() + 2.0 * self.cap_area() } else { self.side_area() }
+    }
+
+
+    /// 和侧面求交
+    fn hit_side(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload>
+    {
+        let root = self.side_root(ray, t_range)?;
+        let p = ray.at(root) - self.base;
+
+        let normal = glm::normalize(vec3(p.x, 0.0, p.z));
+        let uv = vec2(azimuth_u(p.x, p.z), p.y / self.height);
+        let mut payload = HitPayload::new(ray, root, normal, self.mat.clone(), uv);
+
+        // 保持高度不变，将交点投影回侧面上，误差只和交点的坐标有关，和 t 的误差无关
+        let scale = self.radius / Float::sqrt(p.x * p.x + p.z * p.z);
+        if scale.is_finite() {
+            let local = vec3(p.x * scale, p.y, p.z * scale);
+            let world = local + self.base;
+            payload.set_hit_point(world, vec3(local.x.abs(), 0.0, local.z.abs()) * gamma(5) + glm::abs(world) * gamma(1));
+        }
+        Some(payload)
+    }
+
+
+    /// 光线和侧面最近的交点在 t_range 范围内的 t
+    fn side_root(&self, ray: &Ray, t_range: (Float, Float)) -> Option<Float>
+    {
+        let oc = *ray.orig() - self.base;
+        let d = *ray.dir();
+
+        let a = d.x * d.x + d.z * d.z;
+        let half_b = oc.x * d.x + oc.z * d.z;
+        let c = oc.x * oc.x + oc.z * oc.z - self.radius * self.radius;
+
+        // 光线和轴线平行
+        if a == 0.0 {
+            return None;
+        }
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrtd = discriminant.sqrt();
+
+        // 依次检查两个根，交点还需要位于圆柱的高度范围内
+        for root in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+            if root <= t_range.0 || root >= t_range.1 {
+                continue;
+            }
+            let p = ray.at(root) - self.base;
+            if p.y < 0.0 || p.y > self.height {
+                continue;
+            }
+            return Some(root);
+        }
+        None
+    }
+}
+
+
+impl Hittable for Cylinder
+{
+    /// 侧面的纹理坐标：u 是方位角，v 是高度；底面的纹理坐标和 `Disk` 相同
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let mut closest = self.hit_side(ray, t_range);
+        if !self.capped {
+            return closest;
+        }
+
+        let caps = [(self.base, -1.0), (self.base + vec3(0.0, self.height, 0.0), 1.0)];
+        for (center, normal_y) in caps {
+            let t_max = closest.as_ref().map_or(t_range.1, |payload| payload.t());
+            if t_range.0 >= t_max {
+                break;
+            }
+            if let Some((t, offset)) = intersect_disk(ray, &center, self.radius, (t_range.0, t_max)) {
+                closest = Some(disk_payload(ray, t, &center, &offset, self.radius, normal_y, &self.mat));
+            }
+        }
+        closest
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        if self.side_root(ray, t_range).is_some() {
+            return true;
+        }
+
+        self.capped && [self.base, self.base + vec3(0.0, self.height, 0.0)].iter()
+            .any(|center| intersect_disk(ray, center, self.radius, t_range).is_some())
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(AABB::new(self.base - vec3(self.radius, 0.0, self.radius),
+                       self.base + vec3(self.radius, self.height, self.radius)))
+    }
+
+
+    /// 在表面上均匀采样，一个方向可能对应表面上的两个点，因此 pdf 是这些点的 pdf 之和
+    fn pdf(&self, _ray: &Ray) -> Float {
+        let mut pdf = 0.0;
+        let mut t_min = 0.0;
+
+        // 一条直线和圆柱（无论是否有底面）最多有两个交点
+        for _ in 0..2 {
+            let payload = match self.hit(_ray, (t_min, Float::INFINITY)) {
+                None => break,
+                Some(payload) => payload,
+            };
+
+            let cosine = glm::dot(*_ray.dir(), *payload.normal()).abs();
+            if cosine > 0.0 {
+                pdf += payload.t() * payload.t() / (cosine * self.area());
+            }
+            t_min = payload.t_next(_ray);
+        }
+        pdf
+    }
+
+
+    fn rand_dir(&self, origin: &Vec3) -> Option<(Vec3, Float)> {
+        let mut rng = rng();
+
+        for _ in 0..5 {
+            // 按照面积的比例选择侧面或者底面
+            let s = rng.gen::<Float>() * self.area();
+            let point = if s < self.side_area() {
+                let phi = 2.0 * Float::PI() * rng.gen::<Float>();
+                self.base + vec3(self.radius * phi.cos(), self.height * rng.gen::<Float>(), self.radius * phi.sin())
+            } else if s < self.side_area() + self.cap_area() {
+                self.base + rand_in_disk(self.radius)
+            } else {
+                self.base + vec3(0.0, self.height, 0.0) + rand_in_disk(self.radius)
+            };
+
+            let ray = Ray::new(*origin, point);
+            let pdf = self.pdf(&ray);
+            if pdf > 0.0 {
+                return Some((*ray.dir(), pdf));
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::geom::disk::Disk;
+    use crate::material::Lambertian;
+    use crate::utility::{rand_unit_vec, seed_rng};
+
+    /// pdf 在整个球面上的积分，应该为 1
+    fn pdf_integral(obj: &dyn Hittable, origin: Vec3) -> Float
+    {
+        let n = 200000;
+        let sum: Float = (0..n).map(|_| obj.pdf(&Ray::new_d(origin, rand_unit_vec()))).sum();
+        sum / n as Float * 4.0 * Float::PI()
+    }
+
+    #[test]
+    fn test_cylinder_hit()
+    {
+        let cylinder = Cylinder::new(vec3(0.0, 1.0, 0.0), 1.0, 2.0, true, Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))));
+
+        let payload = cylinder.hit(&Ray::new(vec3(-5.0, 2.5, 0.0), vec3(0.0, 2.5, 0.0)), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 4.0).abs() < 1e-4);
+        assert!(glm::length(*payload.normal() - vec3(-1.0, 0.0, 0.0)) < 1e-5);
+        assert!((payload.uv().y - 0.75).abs() < 1e-4);
+
+        // 从上方击中顶面
+        let payload = cylinder.hit(&Ray::new(vec3(0.2, 5.0, 0.3), vec3(0.2, 0.0, 0.3)), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 2.0).abs() < 1e-4);
+        assert!(payload.front_face());
+
+        // 没有底面时，会击中内侧的侧面
+        let open = Cylinder::new(vec3(0.0, 1.0, 0.0), 1.0, 2.0, false, Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))));
+        let payload = open.hit(&Ray::new(vec3(0.0, 5.0, 0.0), vec3(0.9, 2.0, 0.0)), (0.001, Float::INFINITY)).unwrap();
+        assert!(!payload.front_face());
+    }
+
+    #[test]
+    fn test_cylinder_disk_pdf()
+    {
+        seed_rng(11);
+        let mat = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let origin = vec3(2.0, 0.5, -1.5);
+
+        let objects: [Box<dyn Hittable>; 3] = [
+            Box::new(Disk::new(vec3(0.0, 3.0, 0.0), 1.5, mat.clone())),
+            Box::new(Cylinder::new(vec3(0.0, 0.0, 0.0), 1.0, 2.0, false, mat.clone())),
+            Box::new(Cylinder::new(vec3(0.0, 0.0, 0.0), 1.0, 2.0, true, mat.clone())),
+        ];
+
+        for obj in &objects {
+            let integral = pdf_integral(obj.as_ref(), origin);
+            assert!((integral - 1.0).abs() < 0.05, "integral = {}", integral);
+
+            for _ in 0..100 {
+                let (dir, pdf) = obj.rand_dir(&origin).unwrap();
+                assert!((pdf - obj.pdf(&Ray::new_d(origin, dir))).abs() < 1e-3 * pdf);
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+use num::traits::FloatConst;
+use num::Zero;
+use rand::Rng;
+use crate::geom::aabb::AABB;
+use crate::hit::{HitPayload, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::{check_and, rng};
+use crate::float::{Float, Vec2, Vec3, vec2, vec3};
+
+
+/// 圆盘，位于经过 center 的水平面上，法线朝向 +Y
+pub struct Disk
+{
+    center: Vec3,
+    radius: Float,
+    mat: Arc<dyn Material + Send + Sync>,
+}
+
+
+impl Disk
+{
+    pub fn new(center: Vec3, radius: Float, mat: Arc<dyn Material + Send + Sync>) -> Disk
+    {
+        debug_assert!(check_and(&center, Float::is_finite));
+        debug_assert!(radius.is_finite() && radius > 0.0);
+
+        Disk { center, radius, mat }
+    }
+
+
+    pub fn area(&self) -> Float { Float::PI() * self.radius * self.radius }
+}
+
+
+/// 绕 Y 轴的方位角对应的纹理坐标，和 `Sphere::get_uv` 的 u 保持一致
+pub(crate) fn azimuth_u(x: Float, z: Float) -> Float
+{
+    (Float::atan2(-z, x) + Float::PI()) / (2.0 * Float::PI())
+}
+
+
+/// 光线和水平圆盘求交，返回 t 以及交点相对圆心的偏移
+pub(crate) fn intersect_disk(ray: &Ray, center: &Vec3, radius: Float, t_range: (Float, Float)) -> Option<(Float, Vec3)>
+{
+    let t = (center.y - ray.orig().y) / ray.dir().y;
+    if t <= t_range.0 || t >= t_range.1 || !t.is_finite() {
+        return None;
+    }
+
+    let offset = ray.at(t) - *center;
+    if offset.x * offset.x + offset.z * offset.z > radius * radius {
+        return None;
+    }
+    Some((t, offset))
+}
+
+
+/// 圆盘上的交点，offset 是交点相对圆心的偏移，normal_y 是法线的 y 分量（1 或者 -1）
+///
+/// 交点的 y 坐标就是圆盘所在的高度，是精确的；x, z 方向的误差只会让交点在圆盘所在的平面内移动，
+/// 不影响沿着法线的偏移，因此误差上界为 0，参考 pbrt-v3 3.9.4
+pub(crate) fn disk_payload(ray: &Ray, t: Float, center: &Vec3, offset: &Vec3, radius: Float, normal_y: Float,
+                           mat: &Arc<dyn Material + Send + Sync>) -> HitPayload
+{
+    let mut payload = HitPayload::new(ray, t, vec3(0.0, normal_y, 0.0), mat.clone(), disk_uv(offset, radius));
+    payload.set_hit_point(vec3(center.x + offset.x, center.y, center.z + offset.z), Vec3::zero());
+    payload
+}
+
+
+/// 圆盘的纹理坐标：u 是方位角，v 是到圆心的距离
+pub(crate) fn disk_uv(offset: &Vec3, radius: Float) -> Vec2
+{
+    let r = Float::sqrt(offset.x * offset.x + offset.z * offset.z);
+    vec2(azimuth_u(offset.x, offset.z), (r / radius).min(1.0))
+}
+
+
+/// 在半径为 radius 的圆盘上均匀地取一点，返回相对圆心的偏移
+pub(crate) fn rand_in_disk(radius: Float) -> Vec3
+{
+    let mut rng = rng();
+    let r = radius * Float::sqrt(rng.gen::<Float>());
+    let phi = 2.0 * Float::PI() * rng.gen::<Float>();
+
+    vec3(r * phi.cos(), 0.0, r * phi.sin())
+}
+
+
+impl Hittable for Disk
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let (t, offset) = intersect_disk(ray, &self.center, self.radius, t_range)?;
+        Some(disk_payload(ray, t, &self.center, &offset, self.radius, 1.0, &self.mat))
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        intersect_disk(ray, &self.center, self.radius, t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        // 确保 AABB 是有体积的
+        let extent = vec3(self.radius, 0.0001, self.radius);
+        Some(AABB::new(self.center - extent, self.center + extent))
+    }
+
+
+    fn pdf(&self, _ray: &Ray) -> Float {
+        match self.hit(_ray, (0.0, Float::INFINITY)) {
+            None => 0.0,
+
+            // 在圆盘上均匀选择一个点，转换为关于立体角的概率密度
+            Some(hit_payload) => {
+                let cosine = _ray.dir().y.abs();
+                if cosine <= 0.0 {
+                    return 0.0;
+                }
+
+                hit_payload.t() * hit_payload.t() / (cosine * self.area())
+            }
+        }
+    }
+
+
+    fn rand_dir(&self, origin: &Vec3) -> Option<(Vec3, Float)> {
+        for _ in 0..5 {
+            let ray = Ray::new(*origin, self.center + rand_in_disk(self.radius));
+            let pdf = self.pdf(&ray);
+
+            if pdf > 0.0 {
+                return Some((*ray.dir(), pdf));
+            }
+        }
+        None
+    }
+}
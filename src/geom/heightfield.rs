@@ -0,0 +1,356 @@
+use std::sync::Arc;
+use stb_image::image as stbi;
+use crate::geom::aabb::AABB;
+use crate::geom::triangle::{intersect_triangle, triangle_point};
+use crate::hit::{HitPayload, Hittable};
+use crate::material::Material;
+use crate::noise::Perlin;
+use crate::ray::Ray;
+use crate::utility::check_and;
+use crate::float::{Float, Vec2, Vec3, vec2, vec3};
+
+
+/// 格子中的交点：(t, 几何法线, 着色法线, 交点, 交点的误差上界)
+type CellHit = (Float, Vec3, Vec3, Vec3, Vec3);
+
+
+/// 高度场地形，由规则网格上的高度采样组成，每个格子被分为两个三角形
+///
+/// 地形在 xz 平面上的范围是 [corner.x, corner.x + size.x] x [corner.z, corner.z + size.z]，
+/// 高度的取值范围是 [corner.y, corner.y + size.y]。
+/// 求交时，在 xz 平面上沿着光线逐个遍历格子（DDA），跳过高度范围和光线不重叠的格子，
+/// 因此不需要为每个三角形单独存储数据，也不需要 BVH
+pub struct Heightfield
+{
+    /// 世界空间的高度，按行存储，共 res.1 行（z 方向），每行 res.0 个（x 方向）
+    heights: Vec<Float>,
+
+    /// 顶点法线，用于平滑着色
+    normals: Vec<Vec3>,
+
+    /// 两个方向上的采样数量，至少为 2
+    res: (usize, usize),
+
+    corner: Vec3,
+    size: Vec3,
+
+    /// 格子的尺寸
+    cell: Vec2,
+
+    mat: Arc<dyn Material + Send + Sync>,
+    aabb: AABB,
+}
+
+
+impl Heightfield
+{
+    /// heights 是 [0, 1] 范围内的高度，按行存储，共 res.1 行，每行 res.0 个
+    ///
+    /// 第 j 行第 i 个采样点位于 (corner.x + size.x * i / (res.0 - 1), corner.z + size.z * j / (res.1 - 1))
+    pub fn new(heights: Vec<Float>, res: (usize, usize), corner: Vec3, size: Vec3, mat: Arc<dyn Material + Send + Sync>) -> Heightfield
+    {
+        debug_assert!(res.0 >= 2 && res.1 >= 2);
+        debug_assert!(heights.len() == res.0 * res.1);
+        debug_assert!(check_and(&corner, Float::is_finite));
+        debug_assert!(size.x > 0.0 && size.y >= 0.0 && size.z > 0.0);
+
+        let heights: Vec<Float> = heights.iter().map(|h| corner.y + h * size.y).collect();
+        let cell = vec2(size.x / (res.0 - 1) as Float, size.z / (res.1 - 1) as Float);
+
+        // 使用中心差分计算顶点法线，边界上使用单侧差分
+        let height = |i: usize, j: usize| heights[j * res.0 + i];
+        let mut normals = Vec::with_capacity(heights.len());
+        for j in 0..res.1 {
+            for i in 0..res.0 {
+                let (i0, i1) = (i.saturating_sub(1), (i + 1).min(res.0 - 1));
+                let (j0, j1) = (j.saturating_sub(1), (j + 1).min(res.1 - 1));
+                let dx = (height(i1, j) - height(i0, j)) / ((i1 - i0) as Float * cell.x);
+                let dz = (height(i, j1) - height(i, j0)) / ((j1 - j0) as Float * cell.y);
+                normals.push(glm::normalize(vec3(-dx, 1.0, -dz)));
+            }
+        }
+
+        // 确保 AABB 是有体积的
+        let min_height = heights.iter().cloned().fold(Float::INFINITY, Float::min);
+        let max_height = heights.iter().cloned().fold(Float::NEG_INFINITY, Float::max);
+        let aabb = AABB::new(vec3(corner.x, min_height - 0.0001, corner.z),
+                             vec3(corner.x + size.x, max_height + 0.0001, corner.z + size.z));
+
+        Heightfield { heights, normals, res, corner, size, cell, mat, aabb }
+    }
+
+
+    /// 从灰度图中读取高度，每个像素是一个采样点，彩色图片使用三个通道的平均值
+    ///
+    /// 图片的上方对应 z 较大的一侧，和 `ImageTexture` 的纹理坐标一致，因此可以使用同一张图片作为纹理
+    pub fn load(filename: &str, corner: Vec3, size: Vec3, mat: Arc<dyn Material + Send + Sync>) -> Result<Heightfield, String>
+    {
+        let img = match stbi::load(filename) {
+            stbi::LoadResult::Error(msg) => return Err(format!("error load image({}): {}", filename, msg)),
+            stbi::LoadResult::ImageF32(_) => return Err(format!("currently not support f32 image: {}", filename)),
+            stbi::LoadResult::ImageU8(img) => img,
+        };
+        if img.width < 2 || img.height < 2 {
+            return Err(format!("heightfield image is too small: {}", filename));
+        }
+
+        let channels = img.depth.min(3);
+        let mut heights = Vec::with_capacity(img.width * img.height);
+        for j in 0..img.height {
+            let row = img.height - 1 - j;
+            for i in 0..img.width {
+                let idx = (row * img.width + i) * img.depth;
+                let sum: Float = img.data[idx..idx + channels].iter().map(|&c| c as Float).sum();
+                heights.push(sum / (channels as Float * 255.0));
+            }
+        }
+
+        Ok(Heightfield::new(heights, (img.width, img.height), corner, size, mat))
+    }
+
+
+    /// 使用 Perlin 噪声的湍流生成高度，frequency 是地形范围内噪声的频率，高度会被归一化到 [0, 1]
+    pub fn new_noise(noise: &Perlin, frequency: Float, res: (usize, usize), corner: Vec3, size: Vec3,
+                     mat: Arc<dyn Material + Send + Sync>) -> Heightfield
+    {
+        debug_assert!(res.0 >= 2 && res.1 >= 2);
+
+        let mut heights = Vec::with_capacity(res.0 * res.1);
+        for j in 0..res.1 {
+            for i in 0..res.0 {
+                let u = i as Float / (res.0 - 1) as Float;
+                let v = j as Float / (res.1 - 1) as Float;
+                heights.push(noise.turb(&vec3(u * frequency, 0.0, v * frequency), None));
+            }
+        }
+
+        let min = heights.iter().cloned().fold(Float::INFINITY, Float::min);
+        let max = heights.iter().cloned().fold(Float::NEG_INFINITY, Float::max);
+        if max > min {
+            for h in &mut heights {
+                *h = (*h - min) / (max - min);
+            }
+        }
+
+        Heightfield::new(heights, res, corner, size, mat)
+    }
+
+
+    pub fn resolution(&self) -> (usize, usize) { self.res }
+
+
+    #[inline(always)]
+    fn vertex(&self, i: usize, j: usize) -> Vec3
+    {
+        vec3(self.corner.x + i as Float * self.cell.x, self.heights[j * self.res.0 + i], self.corner.z + j as Float * self.cell.y)
+    }
+
+
+    /// 和格子 (i, j) 中的两个三角形求交，返回 (t, 几何法线, 着色法线, 交点, 交点的误差上界)
+    ///
+    /// 交点通过重心坐标插值得到，见 `triangle_point`
+    fn hit_cell(&self, ray: &Ray, i: usize, j: usize, t_range: (Float, Float)) -> Option<CellHit>
+    {
+        let idx = [(i, j), (i, j + 1), (i + 1, j + 1), (i + 1, j)];
+        let p = idx.map(|(i, j)| self.vertex(i, j));
+
+        // 两个三角形的顶点顺序都使得几何法线朝向 +Y
+        let mut res = None;
+        let mut t_max = t_range.1;
+        for tri in [[0, 1, 2], [0, 2, 3]] {
+            let tri_p = tri.map(|k| p[k]);
+            if let Some((t, b)) = intersect_triangle(ray, &tri_p, (t_range.0, t_max)) {
+                let normal = glm::normalize(glm::cross(tri_p[1] - tri_p[0], tri_p[2] - tri_p[0]));
+
+                let n = tri.map(|k| self.normals[idx[k].1 * self.res.0 + idx[k].0]);
+                let shading_normal = glm::normalize(n[0] * b.x + n[1] * b.y + n[2] * b.z);
+
+                let (point, p_error) = triangle_point(&tri_p, &b);
+                t_max = t;
+                res = Some((t, normal, shading_normal, point, p_error));
+            }
+        }
+        res
+    }
+
+
+    /// 使用 DDA 按照光线经过的顺序遍历格子，返回第一个交点，见 `hit_cell`
+    fn intersect(&self, ray: &Ray, t_range: (Float, Float)) -> Option<CellHit>
+    {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let (t_enter, t_exit) = self.aabb.clip(ray, t_range)?;
+        let orig = *ray.orig();
+        let dir = *ray.dir();
+
+        // 光线进入地形时所在的格子
+        let p = ray.at(t_enter);
+        let cell_index = |x: Float, min: Float, size: Float, n: usize| (((x - min) / size).floor().max(0.0) as usize).min(n - 2);
+        let mut i = cell_index(p.x, self.corner.x, self.cell.x, self.res.0);
+        let mut j = cell_index(p.z, self.corner.z, self.cell.y, self.res.1);
+
+        // DDA：光线到达下一个 x 方向、z 方向的格子边界时的 t，以及穿过一个格子的 t 的增量
+        let next_boundary = |d: Float, o: Float, min: Float, size: Float, k: usize| {
+            if d > 0.0 {
+                ((min + (k + 1) as Float * size - o) / d, size / d)
+            } else if d < 0.0 {
+                ((min + k as Float * size - o) / d, -size / d)
+            } else {
+                (Float::INFINITY, Float::INFINITY)
+            }
+        };
+        let (mut t_next_x, dt_x) = next_boundary(dir.x, orig.x, self.corner.x, self.cell.x, i);
+        let (mut t_next_z, dt_z) = next_boundary(dir.z, orig.z, self.corner.z, self.cell.y, j);
+
+        let mut t = t_enter;
+        loop {
+            let t_cell_end = t_next_x.min(t_next_z).min(t_exit);
+
+            // 光线在这个格子中的高度范围和地形的高度范围不重叠时，跳过这个格子
+            let (y0, y1) = (orig.y + dir.y * t, orig.y + dir.y * t_cell_end);
+            let corners = [self.heights[j * self.res.0 + i], self.heights[j * self.res.0 + i + 1],
+                           self.heights[(j + 1) * self.res.0 + i], self.heights[(j + 1) * self.res.0 + i + 1]];
+            let cell_min = corners.iter().cloned().fold(Float::INFINITY, Float::min);
+            let cell_max = corners.iter().cloned().fold(Float::NEG_INFINITY, Float::max);
+            let eps = 1e-4 * (1.0 + t_cell_end.abs());
+
+            if y0.max(y1) >= cell_min - eps && y0.min(y1) <= cell_max + eps {
+                if let Some(res) = self.hit_cell(ray, i, j, t_range) {
+                    return Some(res);
+                }
+            }
+
+            if t_cell_end >= t_exit {
+                return None;
+            }
+
+            // 前往下一个格子
+            if t_next_x < t_next_z {
+                if dir.x > 0.0 {
+                    if i + 2 >= self.res.0 { return None; }
+                    i += 1;
+                } else {
+                    if i == 0 { return None; }
+                    i -= 1;
+                }
+                t = t_next_x;
+                t_next_x += dt_x;
+            } else {
+                if dir.z > 0.0 {
+                    if j + 2 >= self.res.1 { return None; }
+                    j += 1;
+                } else {
+                    if j == 0 { return None; }
+                    j -= 1;
+                }
+                t = t_next_z;
+                t_next_z += dt_z;
+            }
+        }
+    }
+}
+
+
+impl Hittable for Heightfield
+{
+    /// 纹理坐标：u 对应 x 方向，v 对应 z 方向，覆盖整个地形的范围
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let (t, normal, shading_normal, p, p_error) = self.intersect(ray, t_range)?;
+
+        let uv = vec2(((p.x - self.corner.x) / self.size.x).clamp(0.0, 1.0),
+                      ((p.z - self.corner.z) / self.size.z).clamp(0.0, 1.0));
+
+        let mut payload = HitPayload::new(ray, t, normal, self.mat.clone(), uv);
+        payload.set_shading_normal(shading_normal);
+        payload.set_hit_point(p, p_error);
+        Some(payload)
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.intersect(ray, t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.aabb.clone())
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_heightfield()
+    {
+        // 斜坡：高度 y = x / 4，范围 [0, 4] x [0, 4]
+        let res = (9, 5);
+        let mut heights = Vec::new();
+        for _ in 0..res.1 {
+            for i in 0..res.0 {
+                heights.push(i as Float / (res.0 - 1) as Float);
+            }
+        }
+        let mat = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let field = Heightfield::new(heights, res, vec3(0.0, 0.0, 0.0), vec3(4.0, 1.0, 4.0), mat);
+        let expected_normal = glm::normalize(vec3(-0.25, 1.0, 0.0));
+
+        // 垂直向下
+        let payload = field.hit(&Ray::new(vec3(1.0, 5.0, 3.0), vec3(1.0, 0.0, 3.0)), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 4.75).abs() < 1e-4);
+        assert!((payload.uv().x - 0.25).abs() < 1e-4 && (payload.uv().y - 0.75).abs() < 1e-4);
+        assert!(glm::length(*payload.normal() - expected_normal) < 1e-4);
+        assert!(glm::length(*payload.shading_normal() - expected_normal) < 1e-4);
+
+        // 倾斜的光线，穿过多个格子后击中斜坡：从 (-1, 1, 0.3) 出发，沿 (1, -0.1, 0.5) 方向
+        let dir = glm::normalize(vec3(1.0, -0.1, 0.5));
+        let orig = vec3(-1.0, 1.0, 0.3);
+        let payload = field.hit(&Ray::new_d(orig, dir), (0.001, Float::INFINITY)).unwrap();
+        let p = *payload.hit_point();
+        assert!((p.y - p.x / 4.0).abs() < 1e-4, "p = {:?}", p);
+        assert!(p.x > 0.0 && p.x < 4.0 && p.z > 0.0 && p.z < 4.0);
+
+        // 沿 -x 方向，从斜坡的高处掠过，不会击中
+        assert!(field.hit(&Ray::new(vec3(5.0, 1.2, 2.0), vec3(0.0, 1.2, 2.0)), (0.001, Float::INFINITY)).is_none());
+
+        // 从下方击中，得到背面
+        let payload = field.hit(&Ray::new(vec3(2.0, -1.0, 2.0), vec3(2.0, 0.0, 2.0)), (0.001, Float::INFINITY)).unwrap();
+        assert!(!payload.front_face());
+    }
+
+    #[test]
+    fn test_heightfield_noise()
+    {
+        // 任意一条竖直的光线都会击中地形，交点的高度和双线性插值的范围一致
+        let noise = Perlin::new();
+        let mat = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let field = Heightfield::new_noise(&noise, 4.0, (33, 17), vec3(-8.0, 1.0, -4.0), vec3(16.0, 3.0, 8.0), mat);
+
+        let aabb = field.bounding_box().unwrap();
+        assert!((aabb.min().y - 1.0).abs() < 1e-3 && (aabb.max().y - 4.0).abs() < 1e-3);
+
+        for k in 0..100 {
+            let x = -8.0 + 16.0 * (k as Float + 0.5) / 100.0;
+            let z = -4.0 + 8.0 * ((k * 37 % 100) as Float + 0.5) / 100.0;
+            let payload = field.hit(&Ray::new(vec3(x, 10.0, z), vec3(x, 0.0, z)), (0.001, Float::INFINITY)).unwrap();
+            assert!(payload.front_face());
+
+            // 斜向的光线指向同一个点，DDA 遍历得到的交点和逐个格子求交的最近交点相同，不会跳过更近的格子
+            let ray = Ray::new(*payload.hit_point() + vec3(-5.0, 5.0, 3.0), *payload.hit_point());
+            let brute = (0..field.res.1 - 1).flat_map(|j| (0..field.res.0 - 1).map(move |i| (i, j)))
+                .filter_map(|(i, j)| field.hit_cell(&ray, i, j, (0.001, Float::INFINITY)))
+                .map(|(t, ..)| t)
+                .reduce(Float::min);
+            let t = field.hit(&ray, (0.001, Float::INFINITY)).map(|payload| payload.t());
+            assert_eq!(t.is_some(), brute.is_some());
+            if let (Some(t), Some(brute)) = (t, brute) {
+                // 交点位于两个格子的公共边上时，两侧的三角形给出的 t 只有舍入误差的区别
+                assert!((t - brute).abs() <= 1e-5 * t, "t = {}, brute force t = {}", t, brute);
+            }
+        }
+    }
+}
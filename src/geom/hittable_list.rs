@@ -1,9 +1,11 @@
 use std::sync::Arc;
-use glm::Vec3;
 use rand::Rng;
 use crate::geom::aabb::AABB;
 use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
 use crate::ray::Ray;
+use crate::utility::rng;
+use crate::float::{Float, Vec3};
 
 
 pub struct HittableList
@@ -33,7 +35,7 @@ impl HittableList
 
 impl Hittable for HittableList
 {
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload> {
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
         // 目前最近的交点
         let mut closest_so_far = t_range.1;
         let mut res: Option<HitPayload> = None;
@@ -51,15 +53,28 @@ impl Hittable for HittableList
     }
 
 
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        for object in &self.objects {
+            object.hit_packet(packet, t_range, hits);
+        }
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.objects.iter().any(|object| object.occluded(ray, t_range))
+    }
+
+
     fn bounding_box(&self) -> Option<AABB> {
         if self.objects.is_empty() { return None; }
 
         let mut aabb: AABB = AABB::new_default();
 
-        let first_box = true;
+        let mut first_box = true;
         for obj in &self.objects {
             if let Some(obj_box) = obj.bounding_box() {
                 aabb = if first_box { obj_box } else { AABB::combine(&obj_box, &aabb) };
+                first_box = false;
             } else {
                 return None;
             }
@@ -68,8 +83,8 @@ impl Hittable for HittableList
     }
 
 
-    fn pdf(&self, _ray: &Ray) -> f32 {
-        let weight = 1.0 / self.objects.len() as f32;  // 结果是 NaN 也不影响
+    fn pdf(&self, _ray: &Ray) -> Float {
+        let weight = 1.0 / self.objects.len() as Float;  // 结果是 NaN 也不影响
         let mut sum = 0.0;
 
         for obj in &self.objects {
@@ -79,12 +94,12 @@ impl Hittable for HittableList
         sum
     }
 
-    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, f32)> {
+    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, Float)> {
         if self.objects.is_empty() {
             return None;
         }
 
-        let mut rng = rand::thread_rng();
-        self.objects[rng.gen_range(0, self.objects.len()) as usize].rand_dir(_origin)
+        let mut rng = rng();
+        self.objects[rng.gen_range(0, self.objects.len())].rand_dir(_origin)
     }
 }
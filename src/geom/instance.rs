@@ -0,0 +1,131 @@
+use std::sync::Arc;
+use crate::geom::aabb::AABB;
+use crate::geom::transform::Transform;
+use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::float::{Float, Vec3, Mat4};
+
+
+/// 物体的实例：将同一个原型（例如一个网格，或者一棵 BVH 子树）通过不同的变换放置在场景中的多个位置
+///
+/// 所有的实例共享原型的几何数据，每个实例只保存自己的变换矩阵，以及可选的材质
+pub struct Instance
+{
+    transform: Transform,
+
+    /// 覆盖原型的材质，None 表示使用原型自身的材质
+    mat: Option<Arc<dyn Material + Send + Sync>>,
+}
+
+
+impl Instance
+{
+    /// matrix 是原型所在空间到世界空间的仿射变换，需要是可逆的
+    pub fn new(prototype: Arc<dyn Hittable + Send + Sync>, matrix: Mat4, mat: Option<Arc<dyn Material + Send + Sync>>) -> Instance
+    {
+        Instance { transform: Transform::new(prototype, matrix), mat }
+    }
+
+
+    /// 使用相同的原型和材质，放置在新的位置
+    pub fn with_matrix(&self, matrix: Mat4) -> Instance
+    {
+        Instance::new(self.prototype().clone(), matrix, self.mat.clone())
+    }
+
+
+    pub fn prototype(&self) -> &Arc<dyn Hittable + Send + Sync> { self.transform.object() }
+    pub fn matrix(&self) -> &Mat4 { self.transform.matrix() }
+    pub fn material(&self) -> Option<&Arc<dyn Material + Send + Sync>> { self.mat.as_ref() }
+}
+
+
+impl Hittable for Instance
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let mut payload = self.transform.hit(ray, t_range)?;
+        if let Some(mat) = &self.mat {
+            payload.set_material(mat.clone());
+        }
+        Some(payload)
+    }
+
+
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        let t_max = packet.t_max(hits, t_range.1);
+        self.transform.hit_packet(packet, t_range, hits);
+
+        // 只有新的交点才比原来的 t 的上界更近
+        if let Some(mat) = &self.mat {
+            for (lane, hit) in hits.iter_mut().enumerate() {
+                if let Some(payload) = hit.as_mut().filter(|payload| payload.t() < t_max[lane]) {
+                    payload.set_material(mat.clone());
+                }
+            }
+        }
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.transform.occluded(ray, t_range)
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.transform.bounding_box()
+    }
+
+
+    fn pdf(&self, _ray: &Ray) -> Float {
+        self.transform.pdf(_ray)
+    }
+
+
+    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, Float)> {
+        self.transform.rand_dir(_origin)
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::float::vec3;
+    use num::One;
+    use crate::geom::bvh::BVHNode;
+    use crate::geom::mesh::TriangleMesh;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_instance()
+    {
+        // 原型：位于 y = 0 平面的正方形网格
+        let positions = vec![vec3(-1.0, 0.0, -1.0), vec3(1.0, 0.0, -1.0),
+                             vec3(1.0, 0.0, 1.0), vec3(-1.0, 0.0, 1.0)];
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let mesh = Arc::new(TriangleMesh::new(positions, vec![[0, 2, 1], [0, 3, 2]], mat.clone()));
+        let prototype: Arc<dyn Hittable + Send + Sync> = Arc::new(mesh.to_bvh());
+
+        let red: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.8, 0.1, 0.1)));
+        let identity = Mat4::one();
+        let instances: Vec<Arc<dyn Hittable + Send + Sync>> = vec![
+            Arc::new(Instance::new(prototype.clone(), glm::ext::translate(&identity, vec3(0.0, 1.0, 0.0)), None)),
+            Arc::new(Instance::new(prototype.clone(), glm::ext::translate(&identity, vec3(5.0, 2.0, 0.0)), Some(red.clone()))),
+        ];
+        let world = BVHNode::new(&instances);
+
+        // 几何数据没有被复制
+        assert_eq!(Arc::strong_count(&mesh), 3);
+
+        let payload = world.hit(&Ray::new(vec3(0.5, 5.0, 0.5), vec3(0.5, 0.0, 0.5)), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.hit_point().y - 1.0).abs() < 1e-4);
+        assert!(Arc::ptr_eq(payload.material(), &mat));
+
+        let payload = world.hit(&Ray::new(vec3(5.5, 5.0, 0.5), vec3(5.5, 0.0, 0.5)), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.hit_point().y - 2.0).abs() < 1e-4);
+        assert!(Arc::ptr_eq(payload.material(), &red));
+    }
+}
@@ -0,0 +1,290 @@
+use std::sync::Arc;
+use crate::geom::aabb::AABB;
+use crate::geom::bvh::BVHNode;
+use crate::geom::triangle::{intersect_triangle, intersect_triangle_packet, rand_barycentric, triangle_bounding_box, triangle_point};
+use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::check_and;
+use crate::float::{Float, Vec2, Vec3, vec2, vec3};
+
+
+/// 带索引的三角形网格
+///
+/// 顶点位置、法线、纹理坐标只存储一份，所有三角形通过索引共享；整个网格也只持有一个材质
+pub struct TriangleMesh
+{
+    positions: Vec<Vec3>,
+
+    /// 顶点法线，用于平滑着色；为空表示没有顶点法线，使用几何法线着色
+    normals: Vec<Vec3>,
+
+    /// 顶点的纹理坐标；为空表示没有纹理坐标
+    uvs: Vec<Vec2>,
+
+    /// 顶点颜色，可以通过 `VertexColorTexture` 使用；为空表示没有顶点颜色
+    colors: Vec<Vec3>,
+
+    /// 每个三角形的三个顶点索引
+    indices: Vec<[u32; 3]>,
+
+    mat: Arc<dyn Material + Send + Sync>,
+}
+
+
+impl TriangleMesh
+{
+    pub fn new(positions: Vec<Vec3>, indices: Vec<[u32; 3]>, mat: Arc<dyn Material + Send + Sync>) -> TriangleMesh
+    {
+        debug_assert!(positions.iter().all(|p| check_and(p, Float::is_finite)));
+        debug_assert!(indices.iter().flatten().all(|&i| (i as usize) < positions.len()));
+
+        TriangleMesh { positions, normals: Vec::new(), uvs: Vec::new(), colors: Vec::new(), indices, mat }
+    }
+
+
+    /// 设置顶点法线，数量需要和顶点数量相同
+    pub fn set_normals(&mut self, normals: Vec<Vec3>)
+    {
+        debug_assert!(normals.is_empty() || normals.len() == self.positions.len());
+        self.normals = normals;
+    }
+
+
+    /// 设置顶点的纹理坐标，数量需要和顶点数量相同
+    pub fn set_uvs(&mut self, uvs: Vec<Vec2>)
+    {
+        debug_assert!(uvs.is_empty() || uvs.len() == self.positions.len());
+        self.uvs = uvs;
+    }
+
+
+    /// 设置顶点颜色，数量需要和顶点数量相同
+    pub fn set_colors(&mut self, colors: Vec<Vec3>)
+    {
+        debug_assert!(colors.is_empty() || colors.len() == self.positions.len());
+        self.colors = colors;
+    }
+
+
+    /// 使用面法线的面积加权平均，计算平滑的顶点法线
+    pub fn compute_normals(&mut self)
+    {
+        let mut normals = vec![vec3(0.0, 0.0, 0.0); self.positions.len()];
+
+        for idx in &self.indices {
+            let [p0, p1, p2] = self.vertices(idx);
+            // 叉乘的长度就是面积的两倍，因此直接累加叉乘结果即可得到面积加权
+            let face_normal = glm::cross(p1 - p0, p2 - p0);
+            for &i in idx {
+                normals[i as usize] = normals[i as usize] + face_normal;
+            }
+        }
+
+        for n in &mut normals {
+            let len = glm::length(*n);
+            *n = if len > 0.0 { *n / len } else { vec3(0.0, 1.0, 0.0) };
+        }
+
+        self.normals = normals;
+    }
+
+
+    pub fn positions(&self) -> &[Vec3] { &self.positions }
+    pub fn indices(&self) -> &[[u32; 3]] { &self.indices }
+    pub fn normals(&self) -> &[Vec3] { &self.normals }
+    pub fn uvs(&self) -> &[Vec2] { &self.uvs }
+    pub fn colors(&self) -> &[Vec3] { &self.colors }
+    pub fn triangle_count(&self) -> usize { self.indices.len() }
+    pub fn material(&self) -> &Arc<dyn Material + Send + Sync> { &self.mat }
+
+
+    #[inline(always)]
+    fn vertices(&self, idx: &[u32; 3]) -> [Vec3; 3]
+    {
+        [self.positions[idx[0] as usize], self.positions[idx[1] as usize], self.positions[idx[2] as usize]]
+    }
+
+
+    /// 网格中的所有三角形，每个三角形只是对网格数据的引用
+    pub fn triangles(self: &Arc<Self>) -> Vec<Arc<dyn Hittable + Send + Sync>>
+    {
+        (0..self.indices.len())
+            .filter(|&i| {
+                // 跳过退化的三角形
+                let [p0, p1, p2] = self.vertices(&self.indices[i]);
+                glm::length(glm::cross(p1 - p0, p2 - p0)) > 0.0
+            })
+            .map(|i| Arc::new(MeshTriangle { mesh: self.clone(), idx: i as u32 }) as Arc<dyn Hittable + Send + Sync>)
+            .collect()
+    }
+
+
+    /// 为网格中的三角形构建 BVH，确保网格中至少有一个非退化的三角形
+    pub fn to_bvh(self: &Arc<Self>) -> BVHNode
+    {
+        BVHNode::new(&self.triangles())
+    }
+}
+
+
+/// 网格中的一个三角形
+pub struct MeshTriangle
+{
+    mesh: Arc<TriangleMesh>,
+    idx: u32,
+}
+
+
+impl MeshTriangle
+{
+    #[inline(always)]
+    fn vertices(&self) -> [Vec3; 3]
+    {
+        self.mesh.vertices(&self.mesh.indices[self.idx as usize])
+    }
+
+    fn area(&self) -> Float
+    {
+        let [p0, p1, p2] = self.vertices();
+        0.5 * glm::length(glm::cross(p1 - p0, p2 - p0))
+    }
+
+
+    /// 通过重心坐标 b 插值顶点属性，p 是三角形的顶点
+    fn payload(&self, ray: &Ray, p: &[Vec3; 3], t: Float, b: &Vec3) -> HitPayload
+    {
+        let [i0, i1, i2] = self.mesh.indices[self.idx as usize].map(|i| i as usize);
+
+        let uv = if self.mesh.uvs.is_empty() {
+            vec2(b.y + b.z, b.z)
+        } else {
+            self.mesh.uvs[i0] * b.x + self.mesh.uvs[i1] * b.y + self.mesh.uvs[i2] * b.z
+        };
+
+        let normal = glm::normalize(glm::cross(p[1] - p[0], p[2] - p[0]));
+        let mut payload = HitPayload::new(ray, t, normal, self.mesh.mat.clone(), uv);
+        let (point, p_error) = triangle_point(p, b);
+        payload.set_hit_point(point, p_error);
+
+        // 通过顶点法线插值得到着色法线
+        if !self.mesh.normals.is_empty() {
+            let shading_normal = self.mesh.normals[i0] * b.x + self.mesh.normals[i1] * b.y + self.mesh.normals[i2] * b.z;
+            let len = glm::length(shading_normal);
+            if len > 0.0 && len.is_finite() {
+                payload.set_shading_normal(shading_normal / len);
+            }
+        }
+
+        if !self.mesh.colors.is_empty() {
+            payload.set_color(Some(self.mesh.colors[i0] * b.x + self.mesh.colors[i1] * b.y + self.mesh.colors[i2] * b.z));
+        }
+
+        payload
+    }
+}
+
+
+impl Hittable for MeshTriangle
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let p = self.vertices();
+        let (t, b) = intersect_triangle(ray, &p, t_range)?;
+        Some(self.payload(ray, &p, t, &b))
+    }
+
+
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        let p = self.vertices();
+        let (hit, t, b) = intersect_triangle_packet(packet, &p, t_range.0, packet.t_max(hits, t_range.1));
+        for (lane, ray) in packet.rays().iter().enumerate().filter(|&(lane, _)| hit.lane(lane)) {
+            hits[lane] = Some(self.payload(ray, &p, t[lane], &vec3(b[0][lane], b[1][lane], b[2][lane])));
+        }
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        intersect_triangle(ray, &self.vertices(), t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(triangle_bounding_box(&self.vertices()))
+    }
+
+
+    fn pdf(&self, _ray: &Ray) -> Float {
+        let p = self.vertices();
+        match intersect_triangle(_ray, &p, (0.0, Float::INFINITY)) {
+            None => 0.0,
+            Some((t, _)) => {
+                let normal = glm::normalize(glm::cross(p[1] - p[0], p[2] - p[0]));
+                let cosine = glm::dot(*_ray.dir(), normal).abs();
+                if cosine <= 0.0 {
+                    return 0.0;
+                }
+
+                t * t / (cosine * self.area())
+            }
+        }
+    }
+
+
+    fn rand_dir(&self, origin: &Vec3) -> Option<(Vec3, Float)> {
+        let p = self.vertices();
+
+        for _ in 0..5 {
+            let b = rand_barycentric();
+            let ray = Ray::new(*origin, p[0] * b.x + p[1] * b.y + p[2] * b.z);
+            let pdf = self.pdf(&ray);
+
+            if pdf > 0.0 {
+                return Some((*ray.dir(), pdf));
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use crate::material::Lambertian;
+    use super::*;
+    use num::Zero;
+
+    #[test]
+    fn test_mesh_shading_normal()
+    {
+        // 两个三角形组成的正方形，位于 y = 0 平面，顶点法线向外倾斜
+        let positions = vec![vec3(-1.0, 0.0, -1.0), vec3(1.0, 0.0, -1.0),
+                             vec3(1.0, 0.0, 1.0), vec3(-1.0, 0.0, 1.0)];
+        let indices = vec![[0, 2, 1], [0, 3, 2]];
+        let mut mesh = TriangleMesh::new(positions, indices, Arc::new(Lambertian::new(Vec3::zero())));
+        mesh.set_normals(vec![glm::normalize(vec3(-1.0, 1.0, -1.0)), glm::normalize(vec3(1.0, 1.0, -1.0)),
+                              glm::normalize(vec3(1.0, 1.0, 1.0)), glm::normalize(vec3(-1.0, 1.0, 1.0))]);
+        let bvh = Arc::new(mesh).to_bvh();
+
+        // 中心处的着色法线和几何法线一致
+        let ray = Ray::new(vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 0.0));
+        let payload = bvh.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+        assert!(glm::length(*payload.normal() - vec3(0.0, 1.0, 0.0)) < 1e-5);
+        assert!(glm::length(*payload.shading_normal() - vec3(0.0, 1.0, 0.0)) < 1e-3);
+
+        // 靠近边缘时着色法线向外倾斜，而几何法线不变
+        let ray = Ray::new(vec3(0.9, 1.0, 0.0), vec3(0.9, 0.0, 0.0));
+        let payload = bvh.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+        assert!(glm::length(*payload.normal() - vec3(0.0, 1.0, 0.0)) < 1e-5);
+        assert!(payload.shading_normal().x > 0.3);
+
+        // 从背面击中时，着色法线也要朝向光线的一侧
+        let ray = Ray::new(vec3(0.9, -1.0, 0.0), vec3(0.9, 0.0, 0.0));
+        let payload = bvh.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+        assert!(!payload.front_face());
+        assert!(payload.shading_normal().y < 0.0);
+    }
+}
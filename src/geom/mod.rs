@@ -1,4 +1,5 @@
 use rand::Rng;
+use crate::utility::rng;
 
 
 #[derive(Debug)]
@@ -14,7 +15,7 @@ impl Axis
     #[inline(always)]
     pub fn rand() -> Axis
     {
-        match rand::thread_rng().gen_range(0_i32, 3_i32) {
+        match rng().gen_range(0_i32, 3_i32) {
             0 => Self::X,
             1 => Self::Y,
             2 => Self::Z,
@@ -31,13 +32,26 @@ pub mod rect;
 pub mod volumn;
 
 
-pub use sphere::Sphere;
+pub use sphere::{MovingSphere, Sphere};
 
 
 pub mod cube;
 pub mod transform;
 pub mod hittable_list;
 pub mod onb;
+pub mod triangle;
+pub mod mesh;
+pub mod instance;
+pub mod quad;
+pub mod disk;
+pub mod cylinder;
+pub mod cone;
+pub mod torus;
+pub mod csg;
+pub mod sdf;
+pub mod heightfield;
+pub mod plane;
+pub mod tlas;
 
 
 #[cfg(test)]
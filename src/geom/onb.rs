@@ -1,41 +1,48 @@
 use std::ops::Index;
 use crate::utility::check_and;
+use crate::float::{Float, Vec3, vec3};
 
 /// 单位正交基
 pub struct ONB {
-    axis: [glm::Vec3; 3],
+    axis: [Vec3; 3],
 }
 
 
 impl ONB
 {
     /// 基于法向量建立局部坐标系
-    pub fn new(n: glm::Vec3) -> ONB
+    pub fn new(n: Vec3) -> ONB
     {
         let w = glm::normalize(n);
-        debug_assert!(check_and(&w, f32::is_finite));
+        debug_assert!(check_and(&w, Float::is_finite));
 
-        let a = if w.x.abs() > 0.9 { glm::vec3(0.0, 1.0, 0.0) } else { glm::vec3(1.0, 0.0, 0.0) };
+        let a = if w.x.abs() > 0.9 { vec3(0.0, 1.0, 0.0) } else { vec3(1.0, 0.0, 0.0) };
         let v = glm::normalize(glm::cross(w, a));
         let u = glm::cross(w, v);
 
         ONB { axis: [u, v, w] }
     }
 
-    pub fn u(&self) -> &glm::Vec3 { &self.axis[0] }
-    pub fn v(&self) -> &glm::Vec3 { &self.axis[1] }
-    pub fn w(&self) -> &glm::Vec3 { &self.axis[2] }
+    pub fn u(&self) -> &Vec3 { &self.axis[0] }
+    pub fn v(&self) -> &Vec3 { &self.axis[1] }
+    pub fn w(&self) -> &Vec3 { &self.axis[2] }
 
-    pub fn local(&self, v: &glm::Vec3) -> glm::Vec3
+    pub fn local(&self, v: &Vec3) -> Vec3
     {
         self.axis[0] * v.x + self.axis[1] * v.y + self.axis[2] * v.z
     }
+
+    /// `local` 的逆变换：将世界坐标系中的向量变换到局部坐标系
+    pub fn to_local(&self, v: &Vec3) -> Vec3
+    {
+        vec3(glm::dot(*v, self.axis[0]), glm::dot(*v, self.axis[1]), glm::dot(*v, self.axis[2]))
+    }
 }
 
 
 impl Index<usize> for ONB
 {
-    type Output = glm::Vec3;
+    type Output = Vec3;
 
     fn index(&self, index: usize) -> &Self::Output {
         debug_assert!(index < 3);
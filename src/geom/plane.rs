@@ -0,0 +1,155 @@
+use std::sync::Arc;
+use crate::geom::aabb::AABB;
+use crate::hit::{HitPayload, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::{check_and, gamma};
+use crate::float::{Float, Vec3, vec2, vec3};
+
+
+/// 无限大的平面，用于代替半径很大的球体作为地面
+///
+/// 平面没有包围盒，`BVHNode` 会将其放在 BVH 之外单独求交
+pub struct Plane
+{
+    /// 平面上的一点，也是纹理坐标的原点
+    point: Vec3,
+
+    /// 平面的法线，单位向量
+    normal: Vec3,
+
+    /// 纹理坐标的两个方向，和法线构成右手系
+    u: Vec3,
+    v: Vec3,
+
+    /// 纹理重复的周期
+    tile: Float,
+
+    mat: Arc<dyn Material + Send + Sync>,
+}
+
+
+impl Plane
+{
+    pub fn new(point: Vec3, normal: Vec3, mat: Arc<dyn Material + Send + Sync>) -> Plane
+    {
+        Self::new_uv(point, normal, 1.0, mat)
+    }
+
+
+    /// 纹理坐标每隔 tile 的距离重复一次
+    ///
+    /// u 方向是世界坐标的 X 轴在平面上的投影（平面和 X 轴接近垂直时使用 Z 轴），v = u x normal，
+    /// 因此水平的地面上 u, v 分别对应 X, Z 方向
+    pub fn new_uv(point: Vec3, normal: Vec3, tile: Float, mat: Arc<dyn Material + Send + Sync>) -> Plane
+    {
+        debug_assert!(check_and(&point, Float::is_finite));
+        debug_assert!(glm::length(normal) > 0.0);
+        debug_assert!(tile > 0.0);
+
+        let normal = glm::normalize(normal);
+        let axis = if normal.x.abs() > 0.9 { vec3(0.0, 0.0, 1.0) } else { vec3(1.0, 0.0, 0.0) };
+        let u = glm::normalize(axis - normal * glm::dot(axis, normal));
+        let v = glm::cross(u, normal);
+
+        Plane { point, normal, u, v, tile, mat }
+    }
+
+
+    /// 光线和平面的交点在 t_range 范围内的 t
+    fn intersect(&self, ray: &Ray, t_range: (Float, Float)) -> Option<Float>
+    {
+        debug_assert!(t_range.0 < t_range.1);
+
+        // 光线和平面平行
+        let denom = glm::dot(self.normal, *ray.dir());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = glm::dot(self.normal, self.point - *ray.orig()) / denom;
+        if t <= t_range.0 || t >= t_range.1 || !t.is_finite() {
+            return None;
+        }
+        Some(t)
+    }
+}
+
+
+impl Hittable for Plane
+{
+    /// uv 是交点在 u, v 方向上的坐标除以 tile 之后的小数部分
+    ///
+    /// 交点通过平面内的坐标重新计算：point + u * a + v * b，误差只和 point 以及交点到 point 的距离有关，
+    /// 和光线起点的距离无关；u, v 和法线之间存在舍入误差，因此比 `Quad` 多留了一些余量。
+    /// 法线和坐标轴平行时，交点在这个轴上的坐标就是 point 的坐标，是精确的
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let t = self.intersect(ray, t_range)?;
+
+        let planar = ray.at(t) - self.point;
+        let (a, b) = (glm::dot(planar, self.u), glm::dot(planar, self.v));
+        let uv = vec2(a / self.tile, b / self.tile);
+        let uv = vec2(uv.x - uv.x.floor(), uv.y - uv.y.floor());
+
+        let mut payload = HitPayload::new(ray, t, self.normal, self.mat.clone(), uv);
+        let (u, v) = (self.u * a, self.v * b);
+        payload.set_hit_point(self.point + u + v, (glm::abs(self.point) + glm::abs(u) + glm::abs(v)) * gamma(5));
+        for axis in 0..3 {
+            if self.u[axis] == 0.0 && self.v[axis] == 0.0 {
+                payload.snap_to_plane(axis, self.point[axis]);
+            }
+        }
+        Some(payload)
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.intersect(ray, t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::geom::bvh::BVHNode;
+    use crate::geom::Sphere;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_plane()
+    {
+        let mat = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let plane = Arc::new(Plane::new_uv(vec3(0.0, -1.0, 0.0), vec3(0.0, 2.0, 0.0), 4.0, mat.clone()));
+
+        let payload = plane.hit(&Ray::new(vec3(5.0, 3.0, -1.0), vec3(5.0, 0.0, -1.0)), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 4.0).abs() < 1e-5 && payload.front_face());
+        assert!((payload.uv().x - 0.25).abs() < 1e-5 && (payload.uv().y - 0.75).abs() < 1e-5);
+        assert!(plane.hit(&Ray::new(vec3(0.0, 3.0, 0.0), vec3(1.0, 3.0, 0.0)), (0.001, Float::INFINITY)).is_none());
+
+        // 平面不放入 BVH，但是依然可以被击中，并且会遮挡 BVH 中更远的物体
+        let objects: Vec<Arc<dyn Hittable + Send + Sync>> = vec![
+            plane,
+            Arc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 0.5, mat.clone())),
+            Arc::new(Sphere::new(vec3(0.0, -3.0, 0.0), 0.5, mat)),
+        ];
+        let bvh = BVHNode::new(&objects);
+        assert!(bvh.bounding_box().is_none());
+
+        let ray = Ray::new(vec3(0.0, 5.0, 0.0), vec3(0.0, 0.0, 0.0));
+        let payload = bvh.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 4.5).abs() < 1e-5);
+        let payload = bvh.hit(&ray, (5.6, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 6.0).abs() < 1e-5);
+
+        // 只有平面时也可以构建 BVH
+        let bvh = BVHNode::new(&objects[..1]);
+        assert!(bvh.hit(&ray, (0.001, Float::INFINITY)).is_some());
+    }
+}
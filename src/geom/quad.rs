@@ -0,0 +1,203 @@
+use std::sync::Arc;
+use rand::Rng;
+use crate::geom::aabb::AABB;
+use crate::hit::{HitPayload, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::{check_and, gamma, rng};
+use crate::float::{Float, Vec2, Vec3, vec2};
+
+
+/// 任意朝向的平行四边形，由一个顶点 q 以及两条边 u, v 确定，法线方向为 u x v
+///
+/// https://raytracing.github.io/books/RayTracingTheNextWeek.html#quadrilaterals
+pub struct Quad
+{
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+
+    /// 平面的法线，单位向量
+    normal: Vec3,
+
+    /// 平面方程 dot(normal, p) = d
+    d: Float,
+
+    /// w = n / dot(n, n)，其中 n = u x v，用于计算交点在 u, v 方向上的坐标
+    w: Vec3,
+
+    area: Float,
+
+    mat: Arc<dyn Material + Send + Sync>,
+}
+
+
+impl Quad
+{
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, mat: Arc<dyn Material + Send + Sync>) -> Quad
+    {
+        debug_assert!(check_and(&q, Float::is_finite));
+        debug_assert!(check_and(&u, Float::is_finite));
+        debug_assert!(check_and(&v, Float::is_finite));
+
+        let n = glm::cross(u, v);
+        let area = glm::length(n);
+        debug_assert!(area > 0.0, "degenerate quad");
+
+        let normal = n / area;
+        Quad { q, u, v, normal, d: glm::dot(normal, q), w: n / glm::dot(n, n), area, mat }
+    }
+
+
+    pub fn area(&self) -> Float { self.area }
+
+
+    /// 返回交点的 t 以及在 u, v 方向上的坐标
+    fn intersect(&self, ray: &Ray, t_range: (Float, Float)) -> Option<(Float, Vec2)>
+    {
+        debug_assert!(t_range.0 < t_range.1);
+
+        // 光线和平面平行
+        let denom = glm::dot(self.normal, *ray.dir());
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - glm::dot(self.normal, *ray.orig())) / denom;
+        if t <= t_range.0 || t >= t_range.1 || !t.is_finite() {
+            return None;
+        }
+
+        // 交点在 u, v 方向上的坐标，都在 [0, 1] 之间时才在平行四边形内部
+        let planar = ray.at(t) - self.q;
+        let alpha = glm::dot(self.w, glm::cross(planar, self.v));
+        let beta = glm::dot(self.w, glm::cross(self.u, planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some((t, vec2(alpha, beta)))
+    }
+}
+
+
+impl Hittable for Quad
+{
+    /// uv 的起点是 q，u 方向和 v 方向分别对应两条边
+    ///
+    /// 交点通过 uv 重新计算，只有 q + u * alpha + v * beta 的舍入误差，和光线起点的距离无关。
+    /// 边和某个坐标轴垂直时，交点在这个轴上的坐标就是 q 的坐标，是精确的
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let (t, uv) = self.intersect(ray, t_range)?;
+
+        let mut payload = HitPayload::new(ray, t, self.normal, self.mat.clone(), uv);
+        let (u, v) = (self.u * uv.x, self.v * uv.y);
+        payload.set_hit_point(self.q + u + v, (glm::abs(self.q) + glm::abs(u) + glm::abs(v)) * gamma(3));
+        for axis in 0..3 {
+            if self.u[axis] == 0.0 && self.v[axis] == 0.0 {
+                payload.snap_to_plane(axis, self.q[axis]);
+            }
+        }
+        Some(payload)
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.intersect(ray, t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let corners = [self.q, self.q + self.u, self.q + self.v, self.q + self.u + self.v];
+        let mut min = corners[0];
+        let mut max = corners[0];
+        for p in &corners[1..] {
+            min = glm::min(min, *p);
+            max = glm::max(max, *p);
+        }
+
+        // 确保 AABB 是有体积的
+        for i in 0..3 {
+            if max[i] - min[i] < 0.0001 {
+                min[i] -= 0.0001;
+                max[i] += 0.0001;
+            }
+        }
+
+        Some(AABB::new(min, max))
+    }
+
+
+    fn pdf(&self, _ray: &Ray) -> Float {
+        match self.hit(_ray, (0.0, Float::INFINITY)) {
+            None => 0.0,
+
+            // 在平行四边形上均匀选择一个点，转换为关于立体角的概率密度
+            Some(hit_payload) => {
+                let distance_squared = hit_payload.t() * hit_payload.t();
+                let cosine = glm::dot(*_ray.dir(), self.normal).abs();
+                if cosine <= 0.0 {
+                    return 0.0;
+                }
+
+                distance_squared / (cosine * self.area)
+            }
+        }
+    }
+
+
+    fn rand_dir(&self, origin: &Vec3) -> Option<(Vec3, Float)> {
+        let mut rng = rng();
+
+        for _ in 0..5 {
+            let rand_point = self.q + self.u * rng.gen::<Float>() + self.v * rng.gen::<Float>();
+
+            let ray = Ray::new(*origin, rand_point);
+            let pdf = self.pdf(&ray);
+
+            if pdf > 0.0 {
+                return Some((*ray.dir(), pdf));
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::float::vec3;
+    use num::traits::FloatConst;
+    use crate::material::Lambertian;
+    use crate::utility::{rand_unit_vec, seed_rng};
+
+    #[test]
+    fn test_quad()
+    {
+        // 倾斜的平行四边形
+        let quad = Quad::new(vec3(-1.0, 2.0, -1.0), vec3(2.0, 0.5, 0.0), vec3(0.5, 0.5, 2.0),
+                             Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))));
+
+        let target = vec3(-1.0, 2.0, -1.0) + vec3(2.0, 0.5, 0.0) * 0.25 + vec3(0.5, 0.5, 2.0) * 0.75;
+        let payload = quad.hit(&Ray::new(vec3(0.0, 0.0, 0.0), target), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.uv().x - 0.25).abs() < 1e-4 && (payload.uv().y - 0.75).abs() < 1e-4);
+
+        let aabb = quad.bounding_box().unwrap();
+        assert!(glm::length(*aabb.max() - vec3(1.5, 3.0, 1.0)) < 1e-5);
+
+        // pdf 在整个球面上的积分应该为 1，并且和 rand_dir 给出的 pdf 一致
+        let origin = vec3(0.3, 0.0, 0.2);
+        seed_rng(3);
+        let n = 200000;
+        let sum: Float = (0..n).map(|_| quad.pdf(&Ray::new_d(origin, rand_unit_vec()))).sum();
+        let integral = sum / n as Float * 4.0 * Float::PI();
+        assert!((integral - 1.0).abs() < 0.05, "integral = {}", integral);
+
+        for _ in 0..100 {
+            let (dir, pdf) = quad.rand_dir(&origin).unwrap();
+            assert!((pdf - quad.pdf(&Ray::new_d(origin, dir))).abs() < 1e-3 * pdf);
+        }
+    }
+}
@@ -6,6 +6,8 @@ use crate::geom::Axis;
 use crate::hit::{HitPayload, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::utility::rng;
+use crate::float::{Float, Vec2, Vec3, vec2, vec3};
 
 
 /// 轴对齐的矩形
@@ -14,13 +16,13 @@ pub struct AxisRect
     mat: Arc<dyn Material + Send + Sync>,
 
     /// 矩形的两个顶点
-    p0: glm::Vec2,
-    p1: glm::Vec2,
+    p0: Vec2,
+    p1: Vec2,
 
     /// 矩形在垂直方向上的位置
-    k: f32,
+    k: Float,
 
-    normal: glm::Vec3,
+    normal: Vec3,
 
     /// 矩形平面的两个方向
     idx0: usize,
@@ -30,13 +32,13 @@ pub struct AxisRect
     idx_axis: usize,
 
     /// 矩形的面积
-    area: f32,
+    area: Float,
 }
 
 
 impl AxisRect
 {
-    pub fn new(p0: glm::Vec2, p1: glm::Vec2, k: f32, mat: Arc<dyn Material + Send + Sync>, dir: Axis) -> AxisRect
+    pub fn new(p0: Vec2, p1: Vec2, k: Float, mat: Arc<dyn Material + Send + Sync>, dir: Axis) -> AxisRect
     {
         debug_assert!(p0.x.is_finite() && p0.y.is_finite());
         debug_assert!(p1.x.is_finite() && p1.y.is_finite());
@@ -50,19 +52,19 @@ impl AxisRect
 
         match dir {
             Axis::X => {
-                normal = glm::vec3(1.0, 0.0, 0.0);
+                normal = vec3(1.0, 0.0, 0.0);
                 idx0 = 1;
                 idx1 = 2;
                 idx_axis = 0;
             }
             Axis::Y => {
-                normal = glm::vec3(0.0, 1.0, 0.0);
+                normal = vec3(0.0, 1.0, 0.0);
                 idx0 = 0;
                 idx1 = 2;
                 idx_axis = 1;
             }
             Axis::Z => {
-                normal = glm::vec3(0.0, 0.0, 1.0);
+                normal = vec3(0.0, 0.0, 1.0);
                 idx0 = 0;
                 idx1 = 1;
                 idx_axis = 2;
@@ -84,15 +86,11 @@ impl AxisRect
             area,
         }
     }
-}
 
 
-impl Hittable for AxisRect
-{
-    /// 是否命中轴对齐矩形
-    ///
-    /// uv 的起点是 minimum 点
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload> {
+    /// 返回交点的 t 以及交点在矩形平面上的坐标
+    fn intersect(&self, ray: &Ray, t_range: (Float, Float)) -> Option<(Float, Vec2)>
+    {
         debug_assert!(t_range.0 < t_range.1);
 
         let t = (self.k - ray.orig()[self.idx_axis]) / ray.dir()[self.idx_axis];
@@ -106,16 +104,41 @@ impl Hittable for AxisRect
             return None;
         }
 
-        let uv = (glm::vec2(a, b) - self.p0) / (self.p1 - self.p0);
+        Some((t, vec2(a, b)))
+    }
+}
+
+
+impl Hittable for AxisRect
+{
+    /// 是否命中轴对齐矩形
+    ///
+    /// uv 的起点是 minimum 点。交点垂直方向的坐标就是 k，是精确的；
+    /// 矩形平面内的误差不影响沿着法线的偏移，因此误差上界为 0，和 `disk_payload` 相同
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let (t, p) = self.intersect(ray, t_range)?;
+
+        let uv = (p - self.p0) / (self.p1 - self.p0);
         debug_assert!(uv.x >= 0.0 && uv.y >= 0.0);
 
-        Some(HitPayload::new(ray, t, self.normal, self.mat.clone(), uv))
+        let mut point = Vec3::zero();
+        point[self.idx0] = p.x;
+        point[self.idx1] = p.y;
+        point[self.idx_axis] = self.k;
+
+        let mut payload = HitPayload::new(ray, t, self.normal, self.mat.clone(), uv);
+        payload.set_hit_point(point, Vec3::zero());
+        Some(payload)
+    }
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.intersect(ray, t_range).is_some()
     }
 
     fn bounding_box(&self) -> Option<AABB> {
         // 确保 AABB 是有体积的
-        let mut min = glm::Vec3::zero() + (self.k - 0.0001);
-        let mut max = glm::Vec3::zero() + (self.k + 0.0001);
+        let mut min = Vec3::zero() + (self.k - 0.0001);
+        let mut max = Vec3::zero() + (self.k + 0.0001);
 
         min[self.idx0] = self.p0[0];
         min[self.idx1] = self.p0[1];
@@ -126,8 +149,8 @@ impl Hittable for AxisRect
     }
 
 
-    fn pdf(&self, _ray: &Ray) -> f32 {
-        match self.hit(_ray, (0.001, f32::INFINITY)) {
+    fn pdf(&self, _ray: &Ray) -> Float {
+        match self.hit(_ray, (0.0, Float::INFINITY)) {
             None => 0.0,
 
             // 在矩形中均匀选择一个点，该点和光线原点的组成的方向作为随机变量，
@@ -146,9 +169,9 @@ impl Hittable for AxisRect
     }
 
 
-    fn rand_dir(&self, origin: &glm::Vec3) -> Option<(glm::Vec3, f32)> {
-        let mut rng = rand::thread_rng();
-        let mut rand_point = glm::vec3(self.k, self.k, self.k);
+    fn rand_dir(&self, origin: &Vec3) -> Option<(Vec3, Float)> {
+        let mut rng = rng();
+        let mut rand_point = vec3(self.k, self.k, self.k);
 
         for _ in 0..5 {
             rand_point[self.idx0] = rng.gen_range(self.p0[0], self.p1[0]);
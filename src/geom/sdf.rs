@@ -0,0 +1,462 @@
+use std::sync::Arc;
+use crate::geom::aabb::AABB;
+use crate::hit::{HitPayload, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::check_and;
+use crate::float::{Float, Vec3, vec2, vec3};
+
+
+/// 有向距离场（signed distance field）：空间中一点到物体表面的距离，物体内部为负数
+///
+/// 距离可以是保守的估计（不大于真实的距离），此时光线步进会慢一些，但是不会穿过表面
+pub trait Sdf
+{
+    fn distance(&self, p: &Vec3) -> Float;
+}
+
+
+/// 可以直接使用闭包作为距离场
+impl<F> Sdf for F
+    where F: Fn(&Vec3) -> Float
+{
+    fn distance(&self, p: &Vec3) -> Float { self(p) }
+}
+
+
+/// 球体的距离场
+pub struct SdfSphere
+{
+    center: Vec3,
+    radius: Float,
+}
+
+
+impl SdfSphere
+{
+    pub fn new(center: Vec3, radius: Float) -> SdfSphere
+    {
+        debug_assert!(radius.is_finite() && radius > 0.0);
+
+        SdfSphere { center, radius }
+    }
+}
+
+
+impl Sdf for SdfSphere
+{
+    fn distance(&self, p: &Vec3) -> Float { glm::length(*p - self.center) - self.radius }
+}
+
+
+/// 轴对齐立方体的距离场，half 是立方体尺寸的一半
+pub struct SdfBox
+{
+    center: Vec3,
+    half: Vec3,
+}
+
+
+impl SdfBox
+{
+    pub fn new(center: Vec3, half: Vec3) -> SdfBox
+    {
+        debug_assert!(check_and(&half, |x| x > 0.0));
+
+        SdfBox { center, half }
+    }
+}
+
+
+impl Sdf for SdfBox
+{
+    fn distance(&self, p: &Vec3) -> Float {
+        let q = glm::abs(*p - self.center) - self.half;
+        let outside = glm::length(glm::max(q, vec3(0.0, 0.0, 0.0)));
+        let inside = Float::min(Float::max(q.x, Float::max(q.y, q.z)), 0.0);
+        outside + inside
+    }
+}
+
+
+/// 圆环的距离场，绕 Y 轴旋转而成
+pub struct SdfTorus
+{
+    center: Vec3,
+    major_radius: Float,
+    minor_radius: Float,
+}
+
+
+impl SdfTorus
+{
+    pub fn new(center: Vec3, major_radius: Float, minor_radius: Float) -> SdfTorus
+    {
+        debug_assert!(minor_radius > 0.0 && major_radius > 0.0);
+
+        SdfTorus { center, major_radius, minor_radius }
+    }
+}
+
+
+impl Sdf for SdfTorus
+{
+    fn distance(&self, p: &Vec3) -> Float {
+        let p = *p - self.center;
+        let ring = Float::sqrt(p.x * p.x + p.z * p.z) - self.major_radius;
+        Float::sqrt(ring * ring + p.y * p.y) - self.minor_radius
+    }
+}
+
+
+/// Mandelbulb 分形，位于原点附近半径约为 1.2 的范围内
+///
+/// 距离是估计值，参考：http://blog.hvidtfeldts.net/index.php/2011/09/distance-estimated-3d-fractals-v-the-mandelbulb-different-de-approximations/
+pub struct Mandelbulb
+{
+    power: Float,
+    iterations: u32,
+}
+
+
+impl Mandelbulb
+{
+    pub fn new(power: Float, iterations: u32) -> Mandelbulb
+    {
+        debug_assert!(power > 1.0 && iterations > 0);
+
+        Mandelbulb { power, iterations }
+    }
+}
+
+
+impl Sdf for Mandelbulb
+{
+    fn distance(&self, p: &Vec3) -> Float {
+        let mut z = *p;
+        let mut dr = 1.0;
+        let mut r = glm::length(z);
+
+        for _ in 0..self.iterations {
+            if r > 2.0 {
+                break;
+            }
+
+            // 在球坐标系下，半径取 power 次方，角度乘以 power
+            let theta = Float::acos((z.y / r.max(1e-8)).clamp(-1.0, 1.0)) * self.power;
+            let phi = Float::atan2(z.z, z.x) * self.power;
+            dr = r.powf(self.power - 1.0) * self.power * dr + 1.0;
+
+            let zr = r.powf(self.power);
+            z = vec3(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin()) * zr + *p;
+            r = glm::length(z);
+        }
+
+        0.5 * r.max(1e-8).ln() * r / dr
+    }
+}
+
+
+/// 平滑的并集，k 是过渡区域的大小
+pub struct SmoothUnion
+{
+    a: Arc<dyn Sdf + Send + Sync>,
+    b: Arc<dyn Sdf + Send + Sync>,
+    k: Float,
+}
+
+
+impl SmoothUnion
+{
+    pub fn new(a: Arc<dyn Sdf + Send + Sync>, b: Arc<dyn Sdf + Send + Sync>, k: Float) -> SmoothUnion
+    {
+        debug_assert!(k > 0.0);
+
+        SmoothUnion { a, b, k }
+    }
+}
+
+
+impl Sdf for SmoothUnion
+{
+    /// 多项式形式的 smooth min：https://iquilezles.org/articles/smin/
+    fn distance(&self, p: &Vec3) -> Float {
+        let d1 = self.a.distance(p);
+        let d2 = self.b.distance(p);
+
+        let h = (0.5 + 0.5 * (d2 - d1) / self.k).clamp(0.0, 1.0);
+        d2 + (d1 - d2) * h - self.k * h * (1.0 - h)
+    }
+}
+
+
+/// 差集：从 a 中挖去 b
+pub struct Subtraction
+{
+    a: Arc<dyn Sdf + Send + Sync>,
+    b: Arc<dyn Sdf + Send + Sync>,
+}
+
+
+impl Subtraction
+{
+    pub fn new(a: Arc<dyn Sdf + Send + Sync>, b: Arc<dyn Sdf + Send + Sync>) -> Subtraction
+    {
+        Subtraction { a, b }
+    }
+}
+
+
+impl Sdf for Subtraction
+{
+    fn distance(&self, p: &Vec3) -> Float { Float::max(self.a.distance(p), -self.b.distance(p)) }
+}
+
+
+/// 无限重复：以 period 为周期，在原点附近的格子中重复物体
+///
+/// period 的某个分量为 0 时，这个方向上不重复；物体需要位于一个格子的内部，否则距离会不准确
+pub struct Repeat
+{
+    sdf: Arc<dyn Sdf + Send + Sync>,
+    period: Vec3,
+}
+
+
+impl Repeat
+{
+    pub fn new(sdf: Arc<dyn Sdf + Send + Sync>, period: Vec3) -> Repeat
+    {
+        debug_assert!(check_and(&period, |x| x >= 0.0));
+
+        Repeat { sdf, period }
+    }
+}
+
+
+impl Sdf for Repeat
+{
+    fn distance(&self, p: &Vec3) -> Float {
+        let mut q = *p;
+        for i in 0..3 {
+            if self.period[i] > 0.0 {
+                q[i] -= self.period[i] * (q[i] / self.period[i]).round();
+            }
+        }
+        self.sdf.distance(&q)
+    }
+}
+
+
+/// 绕 Y 轴扭曲，每单位高度旋转 rate 弧度
+pub struct Twist
+{
+    sdf: Arc<dyn Sdf + Send + Sync>,
+    rate: Float,
+}
+
+
+impl Twist
+{
+    pub fn new(sdf: Arc<dyn Sdf + Send + Sync>, rate: Float) -> Twist
+    {
+        debug_assert!(rate.is_finite());
+
+        Twist { sdf, rate }
+    }
+}
+
+
+impl Sdf for Twist
+{
+    /// 扭曲会拉伸空间，距离半径为 r 的地方，拉伸的比例约为 sqrt(1 + (rate * r)^2)，用它来缩小距离，保证步进不会穿过表面
+    fn distance(&self, p: &Vec3) -> Float {
+        let angle = self.rate * p.y;
+        let (sin, cos) = angle.sin_cos();
+        let q = vec3(cos * p.x + sin * p.z, p.y, -sin * p.x + cos * p.z);
+
+        let r = Float::sqrt(p.x * p.x + p.z * p.z);
+        self.sdf.distance(&q) / Float::sqrt(1.0 + self.rate * self.rate * r * r)
+    }
+}
+
+
+/// 步进的最大次数
+const MAX_STEPS: u32 = 512;
+
+
+/// 由距离场定义的物体，使用 sphere tracing 求交
+///
+/// bound 是用户给出的包围盒，需要包含整个物体，光线只在包围盒的内部进行步进
+pub struct SdfShape
+{
+    sdf: Arc<dyn Sdf + Send + Sync>,
+    bound: AABB,
+    mat: Arc<dyn Material + Send + Sync>,
+
+    /// 距离小于 epsilon 时认为到达了表面，和包围盒的尺寸成比例
+    epsilon: Float,
+}
+
+
+impl SdfShape
+{
+    pub fn new(sdf: Arc<dyn Sdf + Send + Sync>, bound: AABB, mat: Arc<dyn Material + Send + Sync>) -> SdfShape
+    {
+        let epsilon = 1e-5 * glm::length(*bound.max() - *bound.min());
+        debug_assert!(epsilon > 0.0);
+
+        SdfShape { sdf, bound, mat, epsilon }
+    }
+
+
+    /// 距离场的梯度方向，作为表面的法线
+    fn normal(&self, p: &Vec3) -> Vec3
+    {
+        // 使用四面体的四个顶点进行差分，只需要计算四次距离
+        let h = self.epsilon;
+        let k = [vec3(1.0, -1.0, -1.0), vec3(-1.0, -1.0, 1.0), vec3(-1.0, 1.0, -1.0), vec3(1.0, 1.0, 1.0)];
+
+        let mut n = vec3(0.0, 0.0, 0.0);
+        for k in k {
+            n = n + k * self.sdf.distance(&(*p + k * h));
+        }
+        n
+    }
+
+
+    /// 球体追踪（sphere tracing），返回光线到达表面时的 t
+    fn march(&self, ray: &Ray, t_range: (Float, Float)) -> Option<Float>
+    {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let (mut t, t_max) = self.bound.clip(ray, t_range)?;
+        let mut d = self.sdf.distance(&ray.at(t));
+
+        // 起点紧贴着表面时（例如从表面散射出的光线），先离开表面，避免和自身相交
+        let mut leave_steps = 0;
+        while d.abs() < self.epsilon && leave_steps < 16 {
+            t += self.epsilon;
+            if t >= t_max {
+                return None;
+            }
+            d = self.sdf.distance(&ray.at(t));
+            leave_steps += 1;
+        }
+
+        // 每次前进的距离等于到表面的距离，保证不会穿过表面；起点在物体内部时距离为负数，同样适用
+        for _ in 0..MAX_STEPS {
+            if d.abs() < self.epsilon {
+                return Some(t);
+            }
+
+            t += d.abs();
+            if t >= t_max {
+                return None;
+            }
+            d = self.sdf.distance(&ray.at(t));
+        }
+        None
+    }
+}
+
+
+impl Hittable for SdfShape
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let t = self.march(ray, t_range)?;
+
+        let n = self.normal(&ray.at(t));
+        let len = glm::length(n);
+        let normal = if len > 0.0 && len.is_finite() { n / len } else { -*ray.dir() };
+
+        // 到达表面的判定条件是距离小于 epsilon，交点的误差也需要包括 epsilon
+        let mut payload = HitPayload::new(ray, t, normal, self.mat.clone(), vec2(0.0, 0.0));
+        let p_error = *payload.p_error() + self.epsilon;
+        payload.set_hit_point(ray.at(t), p_error);
+        Some(payload)
+    }
+
+
+    /// 遮挡检测只需要步进到表面，不需要计算法线
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.march(ray, t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.bound.clone())
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::geom::Sphere;
+    use crate::material::Lambertian;
+
+    fn mat() -> Arc<dyn Material + Send + Sync> { Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))) }
+
+    #[test]
+    fn test_sdf_sphere()
+    {
+        // 和解析的球体比较
+        let center = vec3(1.0, 2.0, 3.0);
+        let sphere = Sphere::new(center, 1.5, mat());
+        let shape = SdfShape::new(Arc::new(SdfSphere::new(center, 1.5)),
+                                  AABB::new(center - vec3(2.0, 2.0, 2.0), center + vec3(2.0, 2.0, 2.0)), mat());
+
+        for target in [vec3(1.0, 2.0, 3.0), vec3(1.5, 2.5, 2.5), vec3(0.0, 1.2, 3.5)] {
+            let ray = Ray::new(vec3(-4.0, 0.5, -2.0), target);
+            let expected = sphere.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+            let payload = shape.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+            assert!((payload.t() - expected.t()).abs() < 1e-3);
+            assert!(glm::length(*payload.normal() - *expected.normal()) < 1e-2);
+
+            // 从表面出发，穿过球体内部，击中另一侧
+            let inner = Ray::new_d(*payload.hit_point(), *ray.dir());
+            let expected = sphere.hit(&inner, (0.001, Float::INFINITY)).unwrap();
+            let payload = shape.hit(&inner, (0.001, Float::INFINITY)).unwrap();
+            assert!((payload.t() - expected.t()).abs() < 1e-3);
+            assert!(!payload.front_face());
+        }
+
+        // 不会击中包围盒外面的部分
+        let ray = Ray::new(vec3(-4.0, 0.5, -2.0), center);
+        assert!(shape.hit(&ray, (0.001, 2.0)).is_none());
+    }
+
+    #[test]
+    fn test_sdf_combinator()
+    {
+        let a: Arc<dyn Sdf + Send + Sync> = Arc::new(SdfSphere::new(vec3(-0.5, 0.0, 0.0), 1.0));
+        let b: Arc<dyn Sdf + Send + Sync> = Arc::new(SdfSphere::new(vec3(0.5, 0.0, 0.0), 1.0));
+
+        // 平滑的并集比普通的并集更大一些
+        let p = vec3(0.0, 1.0, 0.0);
+        let smooth = SmoothUnion::new(a.clone(), b.clone(), 0.5);
+        assert!(smooth.distance(&p) < Float::min(a.distance(&p), b.distance(&p)));
+        assert!((smooth.distance(&vec3(-3.0, 0.0, 0.0)) - 1.5).abs() < 1e-5);
+
+        assert!(Subtraction::new(a.clone(), b.clone()).distance(&vec3(-0.2, 0.0, 0.0)) > 0.0);
+
+        // 闭包形式的距离场，以及无限重复
+        let ball = Arc::new(|p: &Vec3| glm::length(*p) - 0.3);
+        let grid = Repeat::new(ball, vec3(1.0, 0.0, 1.0));
+        assert!((grid.distance(&vec3(3.0, 0.0, -5.0)) + 0.3).abs() < 1e-5);
+        assert!((grid.distance(&vec3(2.0, 1.0, 0.0)) - 0.7).abs() < 1e-5);
+
+        // 扭曲不改变 Y 轴上的距离
+        let twist = Twist::new(Arc::new(SdfBox::new(vec3(0.0, 0.0, 0.0), vec3(0.5, 2.0, 0.5))), 1.0);
+        assert!((twist.distance(&vec3(0.0, 1.0, 0.0)) + 0.5).abs() < 1e-5);
+
+        // 在重复的物体中步进，击中第二个球
+        let shape = SdfShape::new(Arc::new(grid), AABB::new(vec3(-0.5, -0.5, -10.0), vec3(10.0, 0.5, 10.0)), mat());
+        let ray = Ray::new_d(vec3(0.5, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+        let payload = shape.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 0.2).abs() < 1e-3);
+        assert!(glm::length(*payload.normal() - vec3(-1.0, 0.0, 0.0)) < 1e-2);
+    }
+}
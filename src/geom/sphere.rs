@@ -4,15 +4,18 @@ use num::traits::FloatConst;
 use crate::geom::aabb::AABB;
 use crate::geom::onb::ONB;
 use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
+use crate::simd::FloatX4;
 use crate::ray::Ray;
 use crate::material::Material;
-use crate::utility::{check_and, is_normalized, rand_in_cone};
+use crate::utility::{check_and, gamma, is_normalized, rand_in_cone};
+use crate::float::{Float, Vec2, Vec3, vec2};
 
 
 pub struct Sphere
 {
-    center: glm::Vec3,
-    radius: f32,
+    center: Vec3,
+    radius: Float,
     mat: Arc<dyn Material + Send + Sync>,
 }
 
@@ -20,9 +23,9 @@ pub struct Sphere
 impl Sphere
 {
     /// 创建球体，允许负数半径
-    pub fn new(center: glm::Vec3, radius: f32, mat: Arc<dyn Material + Send + Sync>) -> Sphere
+    pub fn new(center: Vec3, radius: Float, mat: Arc<dyn Material + Send + Sync>) -> Sphere
     {
-        debug_assert!(check_and(&center, f32::is_finite));
+        debug_assert!(check_and(&center, Float::is_finite));
         debug_assert!(radius.is_finite() && radius != 0.0);
 
         Sphere { center, radius, mat }
@@ -31,15 +34,15 @@ impl Sphere
     /// 通过等距柱状投影得到球体的纹理坐标
     /// u = phi / (2 * pi), v = theta / pi
     /// p 是单位球上的一个点，确保到原点的距离为 1
-    pub fn get_uv(p: &glm::Vec3) -> glm::Vec2
+    pub fn get_uv(p: &Vec3) -> Vec2
     {
         // 确保 p 位于单位球上
         debug_assert!(is_normalized(p));
 
-        let theta = f32::acos(-p.y);
-        let phi = f32::atan2(-p.z, p.x) + f32::pi();
+        let theta = Float::acos(-p.y);
+        let phi = Float::atan2(-p.z, p.x) + Float::pi();
 
-        glm::vec2(phi / (2.0 * f32::pi()), theta / f32::pi())
+        vec2(phi / (2.0 * Float::pi()), theta / Float::pi())
     }
 }
 
@@ -47,45 +50,19 @@ impl Sphere
 impl Hittable for Sphere
 {
     /// 光线是否和球相交
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload>
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload>
     {
-
-        // 从光线起点指向球心的向量
-        let oc = *ray.orig() - self.center;
-
-        let a = glm::dot(*ray.dir(), *ray.dir());
-        let half_b = glm::dot(oc, *ray.dir());
-        let c = glm::dot(oc, oc) - self.radius * self.radius;
-
-        // 这就是一元二次方程的判别式和求根公式
-        let discriminant = half_b * half_b - a * c;
-
-        // 没有根，当然没有交点
-        if discriminant < 0.0 { return None; }
-
-        // 找到最近的符合条件的交点
-        let sqrtd = glm::sqrt(discriminant);
-
-        let mut root: f32;
-        loop {
-            // 优先选择 t 更小的那一个交点
-            root = (-half_b - sqrtd) / a;
-            if root > t_range.0 && root < t_range.1 { break; }
-            root = (-half_b + sqrtd) / a;
-            if root > t_range.0 && root < t_range.1 { break; }
-
-            // 两个根都不在合适的范围内
-            return None;
-        };
+        hit_sphere(&self.center, self.radius, &self.mat, ray, t_range)
+    }
 
 
-        let p = ray.at(root);
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        hit_sphere_packet(&[self.center; PACKET_SIZE], self.radius, &self.mat, packet, t_range, hits)
+    }
 
-        // 注：使用 (p - self.center) / self.radius 表示法线，可以将球的半径设为负数，对应的法线指向内侧
-        let obj_normal = glm::normalize((p - self.center) / self.radius);
 
-        Some(HitPayload::new(&ray, root, obj_normal, self.mat.clone(),
-                             Sphere::get_uv(&obj_normal)))
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        sphere_root(&self.center, self.radius, ray, t_range).is_some()
     }
 
 
@@ -97,24 +74,24 @@ impl Hittable for Sphere
     }
 
 
-    fn pdf(&self, _ray: &Ray) -> f32 {
+    fn pdf(&self, _ray: &Ray) -> Float {
         // 均匀采样，因此 pdf 是常数
-        match self.hit(_ray, (0.001, f32::INFINITY)) {
+        match self.hit(_ray, (0.0, Float::INFINITY)) {
             None => 0.0,
             Some(hit_payload) => {
                 if !hit_payload.front_face() {
                     return 0.0;
                 }
                 let distance = glm::length(self.center - *_ray.orig());
-                let cos_theta_max = f32::sqrt(1.0 - self.radius * self.radius / (distance * distance));
-                1.0 / (2.0 * f32::PI() * (1.0 - cos_theta_max))
+                let cos_theta_max = Float::sqrt(1.0 - self.radius * self.radius / (distance * distance));
+                1.0 / (2.0 * Float::PI() * (1.0 - cos_theta_max))
             }
         }
     }
 
 
     /// 以 origin 为顶点，构成一个与球体相切的圆锥，在圆锥范围内随机采样一个方向
-    fn rand_dir(&self, _origin: &glm::Vec3) -> Option<(glm::Vec3, f32)> {
+    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, Float)> {
         // 圆锥轴线的方向
         let cone_axis = self.center - *_origin;
         let distance = glm::length(cone_axis);
@@ -125,31 +102,197 @@ impl Hittable for Sphere
         }
 
         let sin_theta_max = self.radius / distance;
-        let cos_theta_max = f32::sqrt(1.0 - sin_theta_max * sin_theta_max);
+        let cos_theta_max = Float::sqrt(1.0 - sin_theta_max * sin_theta_max);
 
         let local_coord = ONB::new(cone_axis);
         let res_dir = local_coord.local(&rand_in_cone(cos_theta_max));
 
         // 因为是均匀采样，因此 pdf 是常数，和那片区域的立体角有关
-        let pdf = 1.0 / (2.0 * f32::PI() * (1.0 - cos_theta_max));
+        let pdf = 1.0 / (2.0 * Float::PI() * (1.0 - cos_theta_max));
 
         Some((res_dir, pdf))
     }
 }
 
 
+/// 运动的球体，球心在 time0 时刻位于 center0，在 time1 时刻位于 center1，中间做匀速直线运动
+///
+/// 超出 [time0, time1] 的时刻，球心会沿着同样的速度继续运动
+pub struct MovingSphere
+{
+    center0: Vec3,
+    time0: Float,
+
+    /// 球心在单位时间内的位移
+    velocity: Vec3,
+
+    radius: Float,
+    mat: Arc<dyn Material + Send + Sync>,
+
+    /// 包含了 [time0, time1] 整个运动过程的 AABB
+    aabb: AABB,
+}
+
+
+impl MovingSphere
+{
+    pub fn new(center0: Vec3, time0: Float, center1: Vec3, time1: Float, radius: Float, mat: Arc<dyn Material + Send + Sync>) -> MovingSphere
+    {
+        debug_assert!(check_and(&center0, Float::is_finite) && check_and(&center1, Float::is_finite));
+        debug_assert!(time0.is_finite() && time1.is_finite() && time0 < time1);
+        debug_assert!(radius.is_finite() && radius != 0.0);
+
+        let velocity = (center1 - center0) / (time1 - time0);
+
+        let r = radius.abs();
+        let aabb = AABB::combine(&AABB::new(center0 - r, center0 + r), &AABB::new(center1 - r, center1 + r));
+
+        MovingSphere { center0, time0, velocity, radius, mat, aabb }
+    }
+
+
+    /// 某个时刻的球心位置
+    pub fn center(&self, time: Float) -> Vec3
+    {
+        self.center0 + self.velocity * (time - self.time0)
+    }
+}
+
+
+impl Hittable for MovingSphere
+{
+    /// 使用光线所在时刻的球心求交
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload>
+    {
+        hit_sphere(&self.center(ray.time()), self.radius, &self.mat, ray, t_range)
+    }
+
+
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        let mut centers = [self.center0; PACKET_SIZE];
+        for (center, ray) in centers.iter_mut().zip(packet.rays()) {
+            *center = self.center(ray.time());
+        }
+        hit_sphere_packet(&centers, self.radius, &self.mat, packet, t_range, hits)
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        sphere_root(&self.center(ray.time()), self.radius, ray, t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(self.aabb.clone())
+    }
+}
+
+
+/// 光线和球求交，球心为 center
+fn hit_sphere(center: &Vec3, radius: Float, mat: &Arc<dyn Material + Send + Sync>, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload>
+{
+    let root = sphere_root(center, radius, ray, t_range)?;
+    Some(sphere_payload(center, radius, mat, ray, root))
+}
+
+
+fn sphere_payload(center: &Vec3, radius: Float, mat: &Arc<dyn Material + Send + Sync>, ray: &Ray, root: Float) -> HitPayload
+{
+    let p = ray.at(root);
+
+    // 注：使用 (p - *center) / radius 表示法线，可以将球的半径设为负数，对应的法线指向内侧
+    let obj_normal = glm::normalize((p - *center) / radius);
+
+    let mut payload = HitPayload::new(ray, root, obj_normal, mat.clone(), Sphere::get_uv(&obj_normal));
+
+    // 将交点投影回球面上，误差只和球的半径有关，不受 t 的误差影响，参考 pbrt-v3 3.9.4
+    let local = obj_normal * radius;
+    let world = local + *center;
+    payload.set_hit_point(world, glm::abs(local) * gamma(5) + glm::abs(world) * gamma(1));
+    payload
+}
+
+
+/// 光线包中的所有光线同时和球求交，centers[lane] 是第 lane 条光线所在时刻的球心
+///
+/// 求根的过程和 `sphere_root` 相同，对所有 lane 执行相同的标量运算，通过 lane 掩码选择较近的根；
+/// 只有更近的交点才会构造 `HitPayload`
+fn hit_sphere_packet(centers: &[Vec3; PACKET_SIZE], radius: Float, mat: &Arc<dyn Material + Send + Sync>,
+                     packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE])
+{
+    let (t_min, t_max) = (FloatX4::splat(t_range.0), packet.t_max(hits, t_range.1));
+    let (orig, d) = (packet.orig(), packet.dir());
+    let oc = [0, 1, 2].map(|axis| orig[axis] - FloatX4::gather(centers, axis));
+
+    let a = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+    let half_b = oc[0] * d[0] + oc[1] * d[1] + oc[2] * d[2];
+    let c = oc[0] * oc[0] + oc[1] * oc[1] + oc[2] * oc[2] - FloatX4::splat(radius * radius);
+
+    // 判别式小于 0 时 sqrt 得到 NaN，两个根都不满足范围的条件
+    let sqrtd = (half_b * half_b - a * c).sqrt();
+    let near = (-half_b - sqrtd) / a;
+    let far = (-half_b + sqrtd) / a;
+
+    // 无效的 lane 的 t_max 是负无穷，不会满足条件
+    let near_hit = near.gt(t_min) & near.lt(t_max);
+    let far_hit = far.gt(t_min) & far.lt(t_max);
+    let roots = near_hit.select(near, far);
+
+    let hit = near_hit | far_hit;
+    for (lane, ray) in packet.rays().iter().enumerate().filter(|&(lane, _)| hit.lane(lane)) {
+        hits[lane] = Some(sphere_payload(&centers[lane], radius, mat, ray, roots[lane]));
+    }
+}
+
+
+/// 光线和球最近的交点在 t_range 范围内的 t
+fn sphere_root(center: &Vec3, radius: Float, ray: &Ray, t_range: (Float, Float)) -> Option<Float>
+{
+    // 从光线起点指向球心的向量
+    let oc = *ray.orig() - *center;
+
+    let a = glm::dot(*ray.dir(), *ray.dir());
+    let half_b = glm::dot(oc, *ray.dir());
+    let c = glm::dot(oc, oc) - radius * radius;
+
+    // 这就是一元二次方程的判别式和求根公式
+    let discriminant = half_b * half_b - a * c;
+
+    // 没有根，当然没有交点
+    if discriminant < 0.0 { return None; }
+
+    // 找到最近的符合条件的交点
+    let sqrtd = glm::sqrt(discriminant);
+
+    let mut root: Float;
+    loop {
+        // 优先选择 t 更小的那一个交点
+        root = (-half_b - sqrtd) / a;
+        if root > t_range.0 && root < t_range.1 { break; }
+        root = (-half_b + sqrtd) / a;
+        if root > t_range.0 && root < t_range.1 { break; }
+
+        // 两个根都不在合适的范围内
+        return None;
+    };
+
+    Some(root)
+}
+
+
 #[cfg(test)]
 mod test
 {
     use crate::material::Lambertian;
     use super::*;
+    use crate::float::vec3;
     use num::Zero;
 
     #[test]
     fn test_sphere_rand()
     {
-        let p = glm::vec3(3.0, 4.0, 5.0);
-        let sphere = Sphere::new(glm::Vec3::zero(), 4.0, Arc::new(Lambertian::new(glm::Vec3::zero())));
+        let p = vec3(3.0, 4.0, 5.0);
+        let sphere = Sphere::new(Vec3::zero(), 4.0, Arc::new(Lambertian::new(Vec3::zero())));
         let distance = glm::length(p - sphere.center);
         let axis_dir = glm::normalize(sphere.center - p);
 
@@ -166,4 +309,22 @@ mod test
             println!("sin: {}, sin max: {}", sin_theta, sin_theta_max);
         }
     }
+
+    #[test]
+    fn test_moving_sphere()
+    {
+        let sphere = MovingSphere::new(vec3(0.0, 0.0, 0.0), 0.0, vec3(4.0, 0.0, 0.0), 1.0, 1.0,
+                                       Arc::new(Lambertian::new(Vec3::zero())));
+
+        let aabb = sphere.bounding_box().unwrap();
+        assert!(glm::length(*aabb.min() - vec3(-1.0, -1.0, -1.0)) < 1e-6);
+        assert!(glm::length(*aabb.max() - vec3(5.0, 1.0, 1.0)) < 1e-6);
+
+        // 同一条光线，在不同的时刻得到不同的结果
+        let ray = Ray::new(vec3(2.0, 5.0, 0.0), vec3(2.0, 0.0, 0.0));
+        assert!(sphere.hit(&ray, (0.001, Float::INFINITY)).is_none());
+
+        let payload = sphere.hit(&ray.with_time(0.5), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 4.0).abs() < 1e-5);
+    }
 }
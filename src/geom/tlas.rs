@@ -0,0 +1,156 @@
+use std::ops::Range;
+use std::sync::Arc;
+use crate::geom::aabb::AABB;
+use crate::geom::bvh::{BVHNode, BvhStats};
+use crate::geom::instance::Instance;
+use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
+use crate::ray::Ray;
+use crate::float::{Float, Mat4};
+
+
+/// 两层的加速结构：顶层（TLAS）是实例的 BVH，底层（BLAS）是每个原型自己的 BVH
+///
+/// BLAS 就是普通的 `BVHNode`，例如 `TriangleMesh::to_bvh` 或者 `BVHNode::new_with_list`，只需要构建一次，
+/// 通过 `Instance` 放置在场景中。物体移动时只需要修改实例的变换矩阵，然后重新构建顶层的 BVH，
+/// 顶层的物体数量很少，并且每个实例的包围盒已经预先计算好了，因此重新构建的代价很低
+pub struct Tlas
+{
+    instances: Vec<Arc<Instance>>,
+    bvh: BVHNode,
+}
+
+
+impl Tlas
+{
+    pub fn new(instances: Vec<Instance>) -> Tlas
+    {
+        let instances: Vec<Arc<Instance>> = instances.into_iter().map(Arc::new).collect();
+        let bvh = Self::build(&instances);
+        Tlas { instances, bvh }
+    }
+
+
+    pub fn instances(&self) -> &[Arc<Instance>] { &self.instances }
+
+
+    /// 顶层 BVH 的统计信息
+    pub fn stats(&self) -> BvhStats { self.bvh.stats() }
+
+
+    /// 添加一个实例，返回它的编号
+    ///
+    /// 每次添加都会重新构建整个顶层 BVH，代价是 O(N log N)，逐个添加 N 个实例的总代价是 O(N^2 log N)，
+    /// 需要添加多个实例时应该使用 `extend`
+    pub fn add(&mut self, instance: Instance) -> usize
+    {
+        self.extend([instance]).start
+    }
+
+
+    /// 添加多个实例，只重新构建一次顶层 BVH，返回它们的编号范围
+    pub fn extend<I: IntoIterator<Item=Instance>>(&mut self, instances: I) -> Range<usize>
+    {
+        let start = self.instances.len();
+        self.instances.extend(instances.into_iter().map(Arc::new));
+        self.bvh = Self::build(&self.instances);
+        start..self.instances.len()
+    }
+
+
+    /// 修改一个实例的变换矩阵
+    pub fn set_matrix(&mut self, index: usize, matrix: Mat4)
+    {
+        self.set_matrices([(index, matrix)]);
+    }
+
+
+    /// 同时修改多个实例的变换矩阵，只重新构建一次顶层 BVH，BLAS 保持不变
+    pub fn set_matrices<I: IntoIterator<Item=(usize, Mat4)>>(&mut self, matrices: I)
+    {
+        for (index, matrix) in matrices {
+            self.instances[index] = Arc::new(self.instances[index].with_matrix(matrix));
+        }
+        self.bvh = Self::build(&self.instances);
+    }
+
+
+    /// 和实例求交需要变换光线，再遍历 BLAS，代价比较高，因此每个叶节点只放一个实例
+    fn build(instances: &[Arc<Instance>]) -> BVHNode
+    {
+        let objects: Vec<Arc<dyn Hittable + Send + Sync>> = instances.iter()
+            .map(|instance| instance.clone() as Arc<dyn Hittable + Send + Sync>)
+            .collect();
+        BVHNode::new_sah(&objects, 1)
+    }
+}
+
+
+impl Hittable for Tlas
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        self.bvh.hit(ray, t_range)
+    }
+
+
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        self.bvh.hit_packet(packet, t_range, hits)
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.bvh.occluded(ray, t_range)
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.bvh.bounding_box()
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::float::vec3;
+    use num::One;
+    use crate::geom::mesh::TriangleMesh;
+    use crate::material::{Lambertian, Material};
+
+    #[test]
+    fn test_tlas()
+    {
+        // BLAS：位于 y = 0 平面的正方形网格
+        let positions = vec![vec3(-1.0, 0.0, -1.0), vec3(1.0, 0.0, -1.0),
+                             vec3(1.0, 0.0, 1.0), vec3(-1.0, 0.0, 1.0)];
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let mesh = Arc::new(TriangleMesh::new(positions, vec![[0, 2, 1], [0, 3, 2]], mat));
+        let blas: Arc<dyn Hittable + Send + Sync> = Arc::new(mesh.to_bvh());
+
+        let translate = |x: Float, y: Float| glm::ext::translate(&Mat4::one(), vec3(x, y, 0.0));
+        let mut tlas = Tlas::new((0..10).map(|i| Instance::new(blas.clone(), translate(3.0 * i as Float, 0.0), None)).collect());
+        assert_eq!(tlas.stats().primitives, 10);
+
+        let down = |x: Float| Ray::new(vec3(x, 5.0, 0.5), vec3(x, 0.0, 0.5));
+        assert!(tlas.hit(&down(6.5), (0.001, Float::INFINITY)).is_some());
+        assert!(tlas.hit(&down(-5.0), (0.001, Float::INFINITY)).is_none());
+
+        // 移动两个实例，只有顶层被重新构建，BLAS 没有被复制
+        tlas.set_matrices([(2, translate(-5.0, 1.0)), (3, translate(-5.0, 2.0))]);
+        assert!(tlas.hit(&down(6.5), (0.001, Float::INFINITY)).is_none());
+        let payload = tlas.hit(&down(-5.0), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.hit_point().y - 2.0).abs() < 1e-4);
+        assert!(Arc::ptr_eq(tlas.instances()[3].prototype(), &blas));
+        assert_eq!(Arc::strong_count(&blas), 11);
+
+        let index = tlas.add(Instance::new(blas.clone(), translate(100.0, 0.0), None));
+        assert_eq!(index, 10);
+        assert!(tlas.hit(&down(100.0), (0.001, Float::INFINITY)).is_some());
+
+        let range = tlas.extend((0..5).map(|i| Instance::new(blas.clone(), translate(200.0 + 3.0 * i as Float, 0.0), None)));
+        assert_eq!(range, 11..16);
+        assert_eq!(tlas.stats().primitives, 16);
+        assert!(tlas.hit(&down(212.5), (0.001, Float::INFINITY)).is_some());
+    }
+}
@@ -0,0 +1,222 @@
+use std::sync::Arc;
+use num::traits::FloatConst;
+use crate::geom::aabb::AABB;
+use crate::geom::disk::azimuth_u;
+use crate::hit::{HitPayload, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::check_and;
+use crate::float::{Float, Vec3, vec2, vec3, to_f64};
+
+
+/// 圆环，中心为 center，绕 Y 轴旋转而成
+///
+/// major_radius 是圆环中心线的半径，minor_radius 是截面圆的半径
+pub struct Torus
+{
+    center: Vec3,
+    major_radius: Float,
+    minor_radius: Float,
+    mat: Arc<dyn Material + Send + Sync>,
+}
+
+
+impl Torus
+{
+    pub fn new(center: Vec3, major_radius: Float, minor_radius: Float, mat: Arc<dyn Material + Send + Sync>) -> Torus
+    {
+        debug_assert!(check_and(&center, Float::is_finite));
+        debug_assert!(major_radius.is_finite() && minor_radius.is_finite());
+        debug_assert!(minor_radius > 0.0 && major_radius > minor_radius);
+
+        Torus { center, major_radius, minor_radius, mat }
+    }
+
+
+    /// 圆环满足 (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2)，代入光线方程得到关于 t 的四次方程，返回 t_range 范围内最小的根
+    fn root(&self, ray: &Ray, t_range: (Float, Float)) -> Option<Float>
+    {
+        debug_assert!(t_range.0 < t_range.1);
+
+        // 先将光线的起点移动到包围球附近，减小四次方程系数的数量级差异，提高精度
+        let o = *ray.orig() - self.center;
+        let d = *ray.dir();
+        let bound = to_f64(self.major_radius + self.minor_radius);
+        let (o, d) = (glm::to_dvec3(o), glm::to_dvec3(d));
+        let od = glm::dot(o, d);
+        let discriminant = od * od - (glm::dot(o, o) - bound * bound);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let t_shift = f64::max(0.0, -od - discriminant.sqrt() - 1e-3);
+        let o = o + d * t_shift;
+
+        let big_r2 = to_f64(self.major_radius) * to_f64(self.major_radius);
+        let small_r2 = to_f64(self.minor_radius) * to_f64(self.minor_radius);
+        let e = glm::dot(o, o) + big_r2 - small_r2;
+        let f = glm::dot(o, d);
+
+        let c3 = 4.0 * f;
+        let c2 = 4.0 * f * f + 2.0 * e - 4.0 * big_r2 * (d.x * d.x + d.z * d.z);
+        let c1 = 4.0 * e * f - 8.0 * big_r2 * (o.x * d.x + o.z * d.z);
+        let c0 = e * e - 4.0 * big_r2 * (o.x * o.x + o.z * o.z);
+
+        solve_quartic(c3, c2, c1, c0)
+            .into_iter()
+            .map(|t| (t + t_shift) as Float)
+            .filter(|&t| t > t_range.0 && t < t_range.1)
+            .fold(None, |closest: Option<Float>, t| Some(closest.map_or(t, |c| c.min(t))))
+    }
+}
+
+
+impl Hittable for Torus
+{
+    /// 纹理坐标：u 是绕 Y 轴的方位角，v 是截面圆上的角度
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let root = self.root(ray, t_range)?;
+
+        // 法线方向：从截面圆的圆心指向交点
+        let p = ray.at(root) - self.center;
+        let ring_dir = glm::normalize(vec3(p.x, 0.0, p.z));
+        let tube = p - ring_dir * self.major_radius;
+        let normal = glm::normalize(tube);
+
+        let v = Float::atan2(tube.y, glm::dot(tube, ring_dir)) / (2.0 * Float::PI()) + 0.5;
+        let uv = vec2(azimuth_u(p.x, p.z), v);
+
+        Some(HitPayload::new(ray, root, normal, self.mat.clone(), uv))
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.root(ray, t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        let extent = vec3(self.major_radius + self.minor_radius, self.minor_radius, self.major_radius + self.minor_radius);
+        Some(AABB::new(self.center - extent, self.center + extent))
+    }
+}
+
+
+/// 求解 x^2 + b x + c = 0 的实根
+fn solve_quadratic(b: f64, c: f64) -> Vec<f64>
+{
+    let discriminant = b * b - 4.0 * c;
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+
+    // 避免两个相近的数相减造成的精度损失
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    if q == 0.0 {
+        return vec![0.0, 0.0];
+    }
+    vec![q, c / q]
+}
+
+
+/// 求解 x^3 + a x^2 + b x + c = 0 的最大实根
+fn solve_cubic_max(a: f64, b: f64, c: f64) -> f64
+{
+    let q = (a * a - 3.0 * b) / 9.0;
+    let r = (2.0 * a * a * a - 9.0 * a * b + 27.0 * c) / 54.0;
+
+    if r * r < q * q * q {
+        // 三个实根，使用三角函数的解法
+        let theta = (r / (q * q * q).sqrt()).clamp(-1.0, 1.0).acos();
+        -2.0 * q.sqrt() * (theta / 3.0).cos() - a / 3.0
+    } else {
+        // 一个实根，使用 Cardano 公式
+        let s = -r.signum() * (r.abs() + (r * r - q * q * q).sqrt()).cbrt();
+        let t = if s == 0.0 { 0.0 } else { q / s };
+        s + t - a / 3.0
+    }
+}
+
+
+/// 求解 x^4 + c3 x^3 + c2 x^2 + c1 x + c0 = 0 的实根，使用 Ferrari 方法，再用牛顿迭代修正
+pub(crate) fn solve_quartic(c3: f64, c2: f64, c1: f64, c0: f64) -> Vec<f64>
+{
+    // 代换 x = y - c3 / 4，得到 y^4 + p y^2 + q y + r = 0
+    let a2 = c3 * c3;
+    let p = c2 - 3.0 * a2 / 8.0;
+    let q = c1 - c3 * c2 / 2.0 + a2 * c3 / 8.0;
+    let r = c0 - c3 * c1 / 4.0 + a2 * c2 / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut roots = Vec::new();
+    if q.abs() < 1e-12 {
+        // 双二次方程
+        for z in solve_quadratic(p, r) {
+            if z >= 0.0 {
+                roots.push(z.sqrt());
+                roots.push(-z.sqrt());
+            }
+        }
+    } else {
+        // 预解三次方程 m^3 + p m^2 + (p^2 / 4 - r) m - q^2 / 8 = 0 的正根
+        let m = solve_cubic_max(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let s = (2.0 * m).sqrt();
+        roots.extend(solve_quadratic(-s, p / 2.0 + m + q / (2.0 * s)));
+        roots.extend(solve_quadratic(s, p / 2.0 + m - q / (2.0 * s)));
+    }
+
+    roots.into_iter()
+        .map(|y| {
+            let mut x = y - c3 / 4.0;
+            for _ in 0..2 {
+                let f = (((x + c3) * x + c2) * x + c1) * x + c0;
+                let df = ((4.0 * x + 3.0 * c3) * x + 2.0 * c2) * x + c1;
+                if df != 0.0 {
+                    x -= f / df;
+                }
+            }
+            x
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::material::Lambertian;
+
+    #[test]
+    fn test_quartic()
+    {
+        // (x - 1)(x - 2)(x + 3)(x - 0.5) = x^4 - 0.5 x^3 - 7 x^2 + 9.5 x - 3
+        let mut roots = solve_quartic(-0.5, -7.0, 9.5, -3.0);
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let expected = [-3.0, 0.5, 1.0, 2.0];
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_torus_hit()
+    {
+        let torus = Torus::new(vec3(0.0, 1.0, 0.0), 2.0, 0.5, Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))));
+
+        // 沿 x 轴穿过圆环，先击中外侧
+        let payload = torus.hit(&Ray::new(vec3(-10.0, 1.0, 0.0), vec3(0.0, 1.0, 0.0)), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 7.5).abs() < 1e-4);
+        assert!(glm::length(*payload.normal() - vec3(-1.0, 0.0, 0.0)) < 1e-4);
+
+        // 从中间的孔穿过，不会击中
+        assert!(torus.hit(&Ray::new(vec3(0.0, 10.0, 0.0), vec3(0.0, 0.0, 0.0)), (0.001, Float::INFINITY)).is_none());
+
+        // 从上方击中圆环的顶部
+        let payload = torus.hit(&Ray::new(vec3(0.0, 10.0, 2.0), vec3(0.0, 0.0, 2.0)), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 8.5).abs() < 1e-4);
+        assert!(glm::length(*payload.normal() - vec3(0.0, 1.0, 0.0)) < 1e-4);
+    }
+}
@@ -1,16 +1,18 @@
 use std::sync::Arc;
 use crate::geom::aabb::AABB;
 use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
 use crate::ray::Ray;
-use crate::utility::check_and;
+use crate::utility::{check_and, gamma};
+use crate::float::{Float, Vec3, Mat4, vec3, vec4};
 
 
 /// 在原 Hittable 物体的基础上，沿着世界坐标系的 Y 轴旋转
 pub struct RotateY
 {
     obj: Arc<dyn Hittable + Sync + Send>,
-    sin_theta: f32,
-    cos_theta: f32,
+    sin_theta: Float,
+    cos_theta: Float,
     aabb: Option<AABB>,
 }
 
@@ -18,37 +20,37 @@ pub struct RotateY
 /// https://raytracing.github.io/books/RayTracingTheNextWeek.html#instances/instancerotation
 impl RotateY
 {
-    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, rotate_degree: f32) -> RotateY
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, rotate_degree: Float) -> RotateY
     {
         debug_assert!(rotate_degree.is_finite());
 
         let rotate_radians = glm::radians(rotate_degree);
-        let sin_theta = f32::sin(rotate_radians);
-        let cos_theta = f32::cos(rotate_radians);
+        let sin_theta = Float::sin(rotate_radians);
+        let cos_theta = Float::cos(rotate_radians);
 
         let mut aabb = obj.bounding_box();
 
         // 如果内部的物体有 AABB，就生成新的 AABB
         if let Some(aabb) = &mut aabb {
-            let mut min = glm::vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
-            let mut max = glm::vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY);
+            let mut min = vec3(Float::INFINITY, Float::INFINITY, Float::INFINITY);
+            let mut max = vec3(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY);
 
             // 通过三重循环变量 AABB 的 8 个顶点，并分别对每个顶点进行选择变换
             for i in 0..2 {
                 for j in 0..2 {
                     for k in 0..2 {
-                        let x = aabb.max().x * i as f32 + aabb.min().x * (1 - i) as f32;
-                        let y = aabb.max().y * j as f32 + aabb.min().y * (1 - j) as f32;
-                        let z = aabb.max().z * k as f32 + aabb.min().z * (1 - k) as f32;
+                        let x = aabb.max().x * i as Float + aabb.min().x * (1 - i) as Float;
+                        let y = aabb.max().y * j as Float + aabb.min().y * (1 - j) as Float;
+                        let z = aabb.max().z * k as Float + aabb.min().z * (1 - k) as Float;
 
                         let newx = cos_theta * x + sin_theta * z;
                         let newz = -sin_theta * x + cos_theta * z;
 
-                        let tester = glm::vec3(newx, y, newz);
+                        let tester = vec3(newx, y, newz);
 
                         for c in 0..3 {
-                            min[c] = f32::min(min[c], tester[c]);
-                            max[c] = f32::max(max[c], tester[c]);
+                            min[c] = Float::min(min[c], tester[c]);
+                            max[c] = Float::max(max[c], tester[c]);
                         }
                     }
                 }
@@ -64,6 +66,20 @@ impl RotateY
             aabb,
         }
     }
+
+
+    /// 将世界坐标系中的向量变换到 obj 所在的坐标系中
+    fn to_obj(&self, v: &Vec3) -> Vec3
+    {
+        vec3(self.cos_theta * v.x - self.sin_theta * v.z, v.y, self.sin_theta * v.x + self.cos_theta * v.z)
+    }
+
+
+    /// 将 obj 所在坐标系中的向量变换到世界坐标系中
+    fn to_world(&self, v: &Vec3) -> Vec3
+    {
+        vec3(self.cos_theta * v.x + self.sin_theta * v.z, v.y, -self.sin_theta * v.x + self.cos_theta * v.z)
+    }
 }
 
 
@@ -71,34 +87,45 @@ impl Hittable for RotateY
 {
     /// 先将 ray 变换到 obj 所在的坐标系中
     /// 计算 hit 后，再将 normal 等变换到世界坐标系中
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload> {
-        let mut origin = *ray.orig();
-        let mut direction = *ray.dir();
-
-        origin[0] = self.cos_theta * ray.orig()[0] - self.sin_theta * ray.orig()[2];
-        origin[2] = self.sin_theta * ray.orig()[0] + self.cos_theta * ray.orig()[2];
-
-        direction[0] = self.cos_theta * ray.dir()[0] - self.sin_theta * ray.dir()[2];
-        direction[2] = self.sin_theta * ray.dir()[0] + self.cos_theta * ray.dir()[2];
-
-        let rotated_ray = Ray::new_d(origin, direction);
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let rotated_ray = Ray::new_d(self.to_obj(ray.orig()), self.to_obj(ray.dir())).with_time(ray.time());
 
         self.obj.hit(&rotated_ray, t_range).and_then(|payload| {
-            let mut normal = *payload.normal();
-
-            normal[0] = self.cos_theta * payload.normal()[0] + self.sin_theta * payload.normal()[2];
-            normal[2] = -self.sin_theta * payload.normal()[0] + self.cos_theta * payload.normal()[2];
-            if !payload.front_face() {
-                normal = -normal;
-            }
-
-            Some(HitPayload::new(&ray, payload.t(), normal, payload.material().clone(), *payload.uv()))
+            let normal = self.to_world(&payload.obj_normal());
+            let shading_normal = self.to_world(&payload.obj_shading_normal());
+
+            let mut res = HitPayload::new(&ray, payload.t(), normal, payload.material().clone(), *payload.uv());
+            res.set_shading_normal(shading_normal);
+            res.set_color(payload.color().copied());
+
+            // 旋转交点及其误差，旋转矩阵每一行只有两个非零元素
+            let (p, p_error) = (payload.hit_point(), payload.p_error());
+            let (c, s) = (self.cos_theta.abs(), self.sin_theta.abs());
+            let world_error = vec3(c * p_error.x + s * p_error.z, p_error.y, s * p_error.x + c * p_error.z) * (1.0 + gamma(3))
+                + vec3(c * p.x.abs() + s * p.z.abs(), 0.0, s * p.x.abs() + c * p.z.abs()) * gamma(3);
+            res.set_hit_point(self.to_world(p), world_error);
+            Some(res)
         })
     }
 
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.obj.occluded(&Ray::new_d(self.to_obj(ray.orig()), self.to_obj(ray.dir())).with_time(ray.time()), t_range)
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
         self.aabb.clone()
     }
+
+
+    /// 旋转不会改变立体角，因此 pdf 保持不变
+    fn pdf(&self, _ray: &Ray) -> Float {
+        self.obj.pdf(&Ray::new_d(self.to_obj(_ray.orig()), self.to_obj(_ray.dir())).with_time(_ray.time()))
+    }
+
+
+    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, Float)> {
+        self.obj.rand_dir(&self.to_obj(_origin)).map(|(dir, pdf)| (self.to_world(&dir), pdf))
+    }
 }
 
 
@@ -106,15 +133,15 @@ impl Hittable for RotateY
 pub struct Translate
 {
     obj: Arc<dyn Hittable + Sync + Send>,
-    offset: glm::Vec3,
+    offset: Vec3,
 }
 
 
 impl Translate
 {
-    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, offset: glm::Vec3) -> Translate
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, offset: Vec3) -> Translate
     {
-        debug_assert!(check_and(&offset, f32::is_finite));
+        debug_assert!(check_and(&offset, Float::is_finite));
 
         Translate { obj, offset }
     }
@@ -123,14 +150,25 @@ impl Translate
 
 impl Hittable for Translate
 {
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload> {
-        let moved_ray = Ray::new_d(*ray.orig() - self.offset, *ray.dir());
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let moved_ray = Ray::new_d(*ray.orig() - self.offset, *ray.dir()).with_time(ray.time());
 
         self.obj.hit(&moved_ray, t_range).and_then(|payload| {
-            Some(HitPayload::new(&ray, payload.t(), payload.obj_normal(), payload.material().clone(), *payload.uv()))
+            let mut res = HitPayload::new(&ray, payload.t(), payload.obj_normal(), payload.material().clone(), *payload.uv());
+            res.set_shading_normal(payload.obj_shading_normal());
+            res.set_color(payload.color().copied());
+
+            // 平移交点，加法会引入一次舍入误差
+            let p = *payload.hit_point() + self.offset;
+            res.set_hit_point(p, *payload.p_error() * (1.0 + gamma(1)) + glm::abs(p) * gamma(1));
+            Some(res)
         })
     }
 
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.obj.occluded(&Ray::new_d(*ray.orig() - self.offset, *ray.dir()).with_time(ray.time()), t_range)
+    }
+
     fn bounding_box(&self) -> Option<AABB>
     {
         self.obj.bounding_box().and_then(|aabb| {
@@ -138,6 +176,363 @@ impl Hittable for Translate
                            *aabb.max() + self.offset))
         })
     }
+
+
+    fn pdf(&self, _ray: &Ray) -> Float {
+        self.obj.pdf(&Ray::new_d(*_ray.orig() - self.offset, *_ray.dir()).with_time(_ray.time()))
+    }
+
+
+    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, Float)> {
+        self.obj.rand_dir(&(*_origin - self.offset))
+    }
+}
+
+
+/// 在原 Hittable 物体的基础上，进行任意的仿射变换（旋转、缩放、平移、错切等）
+///
+/// 光线会被变换到物体空间中求交，法线使用逆矩阵的转置变换回世界空间
+pub struct Transform
+{
+    obj: Arc<dyn Hittable + Sync + Send>,
+
+    /// 物体空间到世界空间的变换矩阵
+    matrix: Mat4,
+
+    /// 世界空间到物体空间的变换矩阵
+    inv_matrix: Mat4,
+
+    /// inv_matrix 线性部分的行列式的绝对值
+    inv_det: Float,
+
+    aabb: Option<AABB>,
+}
+
+
+impl Transform
+{
+    /// matrix 需要是可逆的仿射矩阵（最后一行为 0, 0, 0, 1）
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, matrix: Mat4) -> Transform
+    {
+        let inv_matrix = affine_inverse(&matrix).expect("transform matrix is not invertible");
+
+        let aabb = obj.bounding_box().map(|aabb| transform_aabb(&matrix, &aabb));
+
+        let inv_det = glm::dot(inv_matrix.c0.truncate(3), glm::cross(inv_matrix.c1.truncate(3), inv_matrix.c2.truncate(3))).abs();
+
+        Transform { obj, matrix, inv_matrix, inv_det, aabb }
+    }
+
+
+    pub fn object(&self) -> &Arc<dyn Hittable + Sync + Send> { &self.obj }
+    pub fn matrix(&self) -> &Mat4 { &self.matrix }
+    pub fn inv_matrix(&self) -> &Mat4 { &self.inv_matrix }
+
+
+    /// 物体空间中关于立体角的 pdf 转换为世界空间中关于立体角的 pdf
+    ///
+    /// 方向 w 经过线性变换 A 并归一化后，立体角的比例为 |det A| / |A w|^3，这里 A 是世界空间到物体空间的变换
+    fn pdf_to_world(&self, world_dir: &Vec3, obj_pdf: Float) -> Float
+    {
+        let len = glm::length(transform_vector(&self.inv_matrix, world_dir));
+
+        obj_pdf * self.inv_det / (len * len * len)
+    }
+}
+
+
+/// 仿射矩阵的逆矩阵，矩阵不可逆时返回 None
+///
+/// 对于仿射矩阵 [A t]，逆矩阵为 [A^-1  -A^-1 t]，其中 A^-1 由 A 的列向量的叉积得到
+pub(crate) fn affine_inverse(m: &Mat4) -> Option<Mat4>
+{
+    let c0 = m.c0.truncate(3);
+    let c1 = m.c1.truncate(3);
+    let c2 = m.c2.truncate(3);
+
+    let det = glm::dot(c0, glm::cross(c1, c2));
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+
+    // 逆矩阵的三行分别为 c1 x c2, c2 x c0, c0 x c1，再除以行列式
+    let r0 = glm::cross(c1, c2) / det;
+    let r1 = glm::cross(c2, c0) / det;
+    let r2 = glm::cross(c0, c1) / det;
+    if !check_and(&r0, Float::is_finite) || !check_and(&r1, Float::is_finite) || !check_and(&r2, Float::is_finite) {
+        return None;
+    }
+
+    let t = m.c3.truncate(3);
+    let inv_t = -vec3(glm::dot(r0, t), glm::dot(r1, t), glm::dot(r2, t));
+
+    Some(Mat4::new(vec4(r0.x, r1.x, r2.x, 0.0),
+                   vec4(r0.y, r1.y, r2.y, 0.0),
+                   vec4(r0.z, r1.z, r2.z, 0.0),
+                   vec4(inv_t.x, inv_t.y, inv_t.z, 1.0)))
+}
+
+
+/// 将世界空间的光线变换到物体空间，返回物体空间的光线，以及物体空间中 t 相对世界空间的缩放比例
+fn ray_to_obj(inv_m: &Mat4, ray: &Ray) -> (Ray, Float)
+{
+    let dir = transform_vector(inv_m, ray.dir());
+    let scale = glm::length(dir);
+
+    (Ray::new_d(transform_point(inv_m, ray.orig()), dir / scale).with_time(ray.time()), scale)
+}
+
+
+/// 在物体空间中求交，再将结果变换回世界空间，m 是物体空间到世界空间的变换，inv_m 是它的逆矩阵
+fn hit_transformed(obj: &dyn Hittable, m: &Mat4, inv_m: &Mat4, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload>
+{
+    let (obj_ray, scale) = ray_to_obj(inv_m, ray);
+
+    // 物体空间中的光线方向被归一化了，t 也需要跟着缩放
+    let obj_range = (t_range.0 * scale, t_range.1 * scale);
+    obj.hit(&obj_ray, obj_range).map(|payload| payload_to_world(m, inv_m, ray, scale, &payload))
+}
+
+
+/// 将物体空间中的交点变换回世界空间，scale 是 `ray_to_obj` 得到的 t 的缩放比例
+fn payload_to_world(m: &Mat4, inv_m: &Mat4, ray: &Ray, scale: Float, payload: &HitPayload) -> HitPayload
+{
+    let normal = transform_normal(inv_m, &payload.obj_normal());
+    let shading_normal = transform_normal(inv_m, &payload.obj_shading_normal());
+
+    let mut res = HitPayload::new(ray, payload.t() / scale, normal, payload.material().clone(), *payload.uv());
+    res.set_shading_normal(shading_normal);
+    res.set_color(payload.color().copied());
+
+    // 直接变换物体空间中的交点，保留物体给出的更精确的交点位置
+    res.set_hit_point(transform_point(m, payload.hit_point()), transform_point_error(m, payload.hit_point(), payload.p_error()));
+    res
+}
+
+
+/// 光线包在物体空间中求交，再将结果变换回世界空间
+///
+/// 每条光线在物体空间中的 t 的缩放比例不同，因此使用所有光线的范围的并集求交，再逐条光线检查范围。
+/// 得到的交点比某条光线的 t_min 更近时，这条光线在范围内可能还有更远的交点，此时单独使用 `hit_transformed` 求交，
+/// 结果和逐条光线调用 `hit` 相同。t_min 为 0 时（例如摄像机光线）不会出现这种情况
+fn hit_packet_transformed(obj: &dyn Hittable, m: &Mat4, inv_m: &Mat4, packet: &RayPacket, t_range: (Float, Float),
+                          hits: &mut [Option<HitPayload>; PACKET_SIZE])
+{
+    let t_max = packet.t_max(hits, t_range.1);
+    let (obj_rays, scales): (Vec<Ray>, Vec<Float>) = packet.rays().iter().map(|ray| ray_to_obj(inv_m, ray)).unzip();
+    let active: Vec<usize> = (0..packet.len()).filter(|&lane| t_range.0 < t_max[lane]).collect();
+    if active.is_empty() {
+        return;
+    }
+
+    let obj_min = active.iter().map(|&lane| t_range.0 * scales[lane]).fold(Float::INFINITY, Float::min);
+    let obj_max = active.iter().map(|&lane| t_max[lane] * scales[lane]).fold(Float::NEG_INFINITY, Float::max);
+    let mut obj_hits: [Option<HitPayload>; PACKET_SIZE] = Default::default();
+    obj.hit_packet(&RayPacket::new(obj_rays), (obj_min, obj_max), &mut obj_hits);
+
+    for lane in active {
+        let (ray, scale) = (packet.ray(lane), scales[lane]);
+        match &obj_hits[lane] {
+            Some(payload) if payload.t() <= t_range.0 * scale => {
+                if let Some(res) = hit_transformed(obj, m, inv_m, ray, (t_range.0, t_max[lane])) {
+                    hits[lane] = Some(res);
+                }
+            }
+            Some(payload) if payload.t() < t_max[lane] * scale => {
+                hits[lane] = Some(payload_to_world(m, inv_m, ray, scale, payload));
+            }
+            _ => {}
+        }
+    }
+}
+
+
+/// 在物体空间中检测遮挡，inv_m 是世界空间到物体空间的变换
+fn occluded_transformed(obj: &dyn Hittable, inv_m: &Mat4, ray: &Ray, t_range: (Float, Float)) -> bool
+{
+    let (obj_ray, scale) = ray_to_obj(inv_m, ray);
+    obj.occluded(&obj_ray, (t_range.0 * scale, t_range.1 * scale))
+}
+
+
+/// 变换 AABB 的 8 个顶点，得到新的 AABB
+fn transform_aabb(m: &Mat4, aabb: &AABB) -> AABB
+{
+    let mut min = vec3(Float::INFINITY, Float::INFINITY, Float::INFINITY);
+    let mut max = vec3(Float::NEG_INFINITY, Float::NEG_INFINITY, Float::NEG_INFINITY);
+
+    for i in 0..8 {
+        let corner = vec3(
+            if i & 1 == 0 { aabb.min().x } else { aabb.max().x },
+            if i & 2 == 0 { aabb.min().y } else { aabb.max().y },
+            if i & 4 == 0 { aabb.min().z } else { aabb.max().z },
+        );
+        let p = transform_point(m, &corner);
+        min = glm::min(min, p);
+        max = glm::max(max, p);
+    }
+
+    AABB::new(min, max)
+}
+
+
+/// 使用仿射矩阵变换一个点
+#[inline(always)]
+pub(crate) fn transform_point(m: &Mat4, p: &Vec3) -> Vec3
+{
+    (*m * p.extend(1.0)).truncate(3)
+}
+
+
+/// 使用仿射矩阵变换点 p 之后，结果的误差上界，p_error 是 p 本身的误差，参考 pbrt-v3 3.9.6
+pub(crate) fn transform_point_error(m: &Mat4, p: &Vec3, p_error: &Vec3) -> Vec3
+{
+    let mut res = vec3(0.0, 0.0, 0.0);
+    for row in 0..3 {
+        let (m0, m1, m2, m3) = (m.c0[row].abs(), m.c1[row].abs(), m.c2[row].abs(), m.c3[row].abs());
+        res[row] = gamma(3) * (m0 * p.x.abs() + m1 * p.y.abs() + m2 * p.z.abs() + m3)
+            + (1.0 + gamma(3)) * (m0 * p_error.x + m1 * p_error.y + m2 * p_error.z);
+    }
+    res
+}
+
+
+/// 使用仿射矩阵变换一个向量，不受平移的影响
+#[inline(always)]
+pub(crate) fn transform_vector(m: &Mat4, v: &Vec3) -> Vec3
+{
+    (*m * v.extend(0.0)).truncate(3)
+}
+
+
+/// 变换法线，inv_m 是变换矩阵的逆矩阵，法线使用逆矩阵的转置进行变换，结果已经正规化
+#[inline(always)]
+pub(crate) fn transform_normal(inv_m: &Mat4, n: &Vec3) -> Vec3
+{
+    let n = vec3(glm::dot(inv_m.c0.truncate(3), *n),
+                      glm::dot(inv_m.c1.truncate(3), *n),
+                      glm::dot(inv_m.c2.truncate(3), *n));
+    glm::normalize(n)
+}
+
+
+impl Hittable for Transform
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        hit_transformed(self.obj.as_ref(), &self.matrix, &self.inv_matrix, ray, t_range)
+    }
+
+
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        hit_packet_transformed(self.obj.as_ref(), &self.matrix, &self.inv_matrix, packet, t_range, hits)
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        occluded_transformed(self.obj.as_ref(), &self.inv_matrix, ray, t_range)
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.aabb.clone()
+    }
+
+
+    fn pdf(&self, _ray: &Ray) -> Float {
+        let (obj_ray, _) = ray_to_obj(&self.inv_matrix, _ray);
+        let obj_pdf = self.obj.pdf(&obj_ray);
+        if obj_pdf <= 0.0 {
+            return 0.0;
+        }
+
+        self.pdf_to_world(_ray.dir(), obj_pdf)
+    }
+
+
+    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, Float)> {
+        let obj_origin = transform_point(&self.inv_matrix, _origin);
+        let (obj_dir, obj_pdf) = self.obj.rand_dir(&obj_origin)?;
+
+        let dir = glm::normalize(transform_vector(&self.matrix, &obj_dir));
+        Some((dir, self.pdf_to_world(&dir, obj_pdf)))
+    }
+}
+
+
+/// 随时间变化的仿射变换，用于运动模糊
+///
+/// 在 time0 和 time1 两个时刻分别给出变换矩阵，中间时刻的矩阵由两者线性插值得到，范围之外的时刻保持端点的矩阵。
+/// 对于平移和缩放，插值是准确的；对于旋转，插值得到的矩阵会带有一些变形，角度较大时需要拆分为多段
+pub struct MovingTransform
+{
+    obj: Arc<dyn Hittable + Sync + Send>,
+
+    /// 两个时刻物体空间到世界空间的变换矩阵
+    matrix: (Mat4, Mat4),
+
+    time: (Float, Float),
+
+    /// 整个运动过程的 AABB
+    aabb: Option<AABB>,
+}
+
+
+impl MovingTransform
+{
+    /// matrix0 和 matrix1 分别是 time0 和 time1 时刻的变换矩阵，运动过程中的矩阵都需要是可逆的
+    pub fn new(obj: Arc<dyn Hittable + Sync + Send>, matrix0: Mat4, time0: Float, matrix1: Mat4, time1: Float) -> MovingTransform
+    {
+        debug_assert!(time0.is_finite() && time1.is_finite() && time0 <= time1);
+
+        // 插值后，物体上任意一点的位置都是两个端点位置的线性插值，因此两个时刻 AABB 的并集包含了整个运动过程
+        let aabb = obj.bounding_box().map(|aabb| {
+            AABB::combine(&transform_aabb(&matrix0, &aabb), &transform_aabb(&matrix1, &aabb))
+        });
+
+        MovingTransform { obj, matrix: (matrix0, matrix1), time: (time0, time1), aabb }
+    }
+
+
+    /// 某个时刻物体空间到世界空间的变换矩阵
+    pub fn matrix_at(&self, time: Float) -> Mat4
+    {
+        let span = self.time.1 - self.time.0;
+        let s = if span > 0.0 { ((time - self.time.0) / span).clamp(0.0, 1.0) } else { 0.0 };
+
+        self.matrix.0 * (1.0 - s) + self.matrix.1 * s
+    }
+
+
+    /// 截取 [time0, time1] 时间段内的运动，AABB 只包含这一段时间内的运动范围
+    ///
+    /// 渲染动画时，每一帧的快门时间只是整个运动的一小段，使用截取后的物体配合 `BVHNode::refit` 可以得到更紧的包围盒
+    pub fn window(&self, time0: Float, time1: Float) -> MovingTransform
+    {
+        MovingTransform::new(self.obj.clone(), self.matrix_at(time0), time0, self.matrix_at(time1), time1)
+    }
+}
+
+
+impl Hittable for MovingTransform
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        let matrix = self.matrix_at(ray.time());
+        let inv_matrix = affine_inverse(&matrix)?;
+        hit_transformed(self.obj.as_ref(), &matrix, &inv_matrix, ray, t_range)
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        match affine_inverse(&self.matrix_at(ray.time())) {
+            Some(inv_matrix) => occluded_transformed(self.obj.as_ref(), &inv_matrix, ray, t_range),
+            None => false,
+        }
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        self.aabb.clone()
+    }
 }
 
 
@@ -157,14 +552,116 @@ impl FlipFace
 
 impl Hittable for FlipFace
 {
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload> {
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
         self.obj.hit(ray, t_range).and_then(|mut payload| {
             payload.set_normal(*payload.normal(), !payload.front_face());
             Some(payload)
         })
     }
 
+    /// 翻转法线不影响可见性
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        self.obj.occluded(ray, t_range)
+    }
+
     fn bounding_box(&self) -> Option<AABB> {
         self.obj.bounding_box()
     }
-}
\ No newline at end of file
+
+
+    fn pdf(&self, _ray: &Ray) -> Float {
+        self.obj.pdf(_ray)
+    }
+
+
+    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, Float)> {
+        self.obj.rand_dir(_origin)
+    }
+}
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::float::vec2;
+    use num::One;
+    use num::traits::FloatConst;
+    use crate::geom::Axis;
+    use crate::geom::rect::AxisRect;
+    use crate::geom::Sphere;
+    use crate::material::Lambertian;
+    use crate::utility::{rand_unit_vec, seed_rng};
+
+    #[test]
+    fn test_transform_hit()
+    {
+        // 单位球在 x 方向拉伸为 2 倍，再平移到 (0, 0, 5)
+        let sphere = Arc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))));
+        let matrix = glm::ext::scale(&glm::ext::translate(&Mat4::one(), vec3(0.0, 0.0, 5.0)), vec3(2.0, 1.0, 1.0));
+        let obj = Transform::new(sphere, matrix);
+
+        let aabb = obj.bounding_box().unwrap();
+        assert!(glm::length(*aabb.min() - vec3(-2.0, -1.0, 4.0)) < 1e-5);
+        assert!(glm::length(*aabb.max() - vec3(2.0, 1.0, 6.0)) < 1e-5);
+
+        let ray = Ray::new(vec3(-10.0, 0.0, 5.0), vec3(0.0, 0.0, 5.0));
+        let payload = obj.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 8.0).abs() < 1e-4);
+        assert!(glm::length(*payload.normal() - vec3(-1.0, 0.0, 0.0)) < 1e-4);
+
+        // 椭球表面 (sqrt(2), sqrt(0.5), 5) 处的法线方向为 (x / 4, y, 0)
+        let target = vec3(Float::sqrt(2.0), Float::sqrt(0.5), 5.0);
+        let ray = Ray::new(target + vec3(0.0, 3.0, 0.0), target);
+        let payload = obj.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+        let expected = glm::normalize(vec3(target.x / 4.0, target.y, 0.0));
+        assert!(glm::length(*payload.normal() - expected) < 1e-4);
+    }
+
+    #[test]
+    fn test_transform_pdf()
+    {
+        // 倾斜并且非均匀缩放的矩形光源，pdf 在整个球面上的积分应该为 1
+        let rect = Arc::new(AxisRect::new(vec2(-1.0, -1.0), vec2(1.0, 1.0), 0.0,
+                                          Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5))), Axis::Y));
+        let matrix = glm::ext::rotate(&glm::ext::translate(&Mat4::one(), vec3(0.0, 2.0, 0.0)),
+                                      glm::radians(30.0), vec3(1.0, 0.0, 1.0));
+        let light = Transform::new(rect, glm::ext::scale(&matrix, vec3(1.5, 1.0, 0.5)));
+        let origin = vec3(0.2, 0.0, -0.3);
+
+        seed_rng(7);
+        let n = 200000;
+        let sum: Float = (0..n).map(|_| light.pdf(&Ray::new_d(origin, rand_unit_vec()))).sum();
+        let integral = sum / n as Float * 4.0 * Float::PI();
+        assert!((integral - 1.0).abs() < 0.05, "integral = {}", integral);
+
+        for _ in 0..100 {
+            let (dir, pdf) = light.rand_dir(&origin).unwrap();
+            let expected = light.pdf(&Ray::new_d(origin, dir));
+            assert!((pdf - expected).abs() < 1e-3 * pdf);
+        }
+    }
+
+    #[test]
+    fn test_moving_transform()
+    {
+        // 单位球从原点移动到 (4, 0, 0)，同时放大为 2 倍
+        let sphere = Arc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))));
+        let matrix1 = glm::ext::scale(&glm::ext::translate(&Mat4::one(), vec3(4.0, 0.0, 0.0)), vec3(2.0, 2.0, 2.0));
+        let obj = MovingTransform::new(sphere, Mat4::one(), 0.0, matrix1, 1.0);
+
+        let aabb = obj.bounding_box().unwrap();
+        assert!(glm::length(*aabb.min() - vec3(-1.0, -2.0, -2.0)) < 1e-5);
+        assert!(glm::length(*aabb.max() - vec3(6.0, 2.0, 2.0)) < 1e-5);
+
+        // 在 time = 0.5 时，球心位于 (2, 0, 0)，半径为 1.5
+        let ray = Ray::new(vec3(2.0, 10.0, 0.0), vec3(2.0, 0.0, 0.0));
+        assert!(obj.hit(&ray, (0.001, Float::INFINITY)).is_none());
+        let payload = obj.hit(&ray.with_time(0.5), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 8.5).abs() < 1e-4);
+        assert!(glm::length(*payload.normal() - vec3(0.0, 1.0, 0.0)) < 1e-4);
+
+        // 超出时间范围时，保持端点的变换
+        let payload = obj.hit(&Ray::new(vec3(4.0, 10.0, 0.0), vec3(4.0, 0.0, 0.0)).with_time(3.0), (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 8.0).abs() < 1e-4);
+    }
+}
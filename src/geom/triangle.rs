@@ -0,0 +1,377 @@
+use std::sync::Arc;
+use rand::Rng;
+use crate::geom::aabb::AABB;
+use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
+use crate::simd::{FloatX4, MaskX4};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::utility::{check_and, gamma, rng};
+use crate::float::{Float, Vec2, Vec3, vec2, vec3, to_f64};
+
+
+/// 三角形，顶点按照逆时针顺序排列时，法线朝向观察者
+pub struct Triangle
+{
+    p: [Vec3; 3],
+
+    /// 每个顶点的纹理坐标
+    uv: [Vec2; 3],
+
+    /// 几何法线，由顶点顺序决定
+    normal: Vec3,
+
+    area: Float,
+
+    mat: Arc<dyn Material + Send + Sync>,
+}
+
+
+impl Triangle
+{
+    /// 顶点的纹理坐标默认为 (0, 0), (1, 0), (1, 1)
+    pub fn new(p0: Vec3, p1: Vec3, p2: Vec3, mat: Arc<dyn Material + Send + Sync>) -> Triangle
+    {
+        Self::new_uv(p0, p1, p2, [vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0)], mat)
+    }
+
+
+    pub fn new_uv(p0: Vec3, p1: Vec3, p2: Vec3, uv: [Vec2; 3], mat: Arc<dyn Material + Send + Sync>) -> Triangle
+    {
+        debug_assert!(check_and(&p0, Float::is_finite));
+        debug_assert!(check_and(&p1, Float::is_finite));
+        debug_assert!(check_and(&p2, Float::is_finite));
+
+        let cross = glm::cross(p1 - p0, p2 - p0);
+        let area = 0.5 * glm::length(cross);
+        debug_assert!(area > 0.0, "degenerate triangle");
+
+        Triangle { p: [p0, p1, p2], uv, normal: glm::normalize(cross), area, mat }
+    }
+
+
+    pub fn area(&self) -> Float { self.area }
+
+
+    /// b 是交点的重心坐标
+    fn payload(&self, ray: &Ray, t: Float, b: &Vec3) -> HitPayload
+    {
+        let uv = self.uv[0] * b.x + self.uv[1] * b.y + self.uv[2] * b.z;
+        let mut payload = HitPayload::new(ray, t, self.normal, self.mat.clone(), uv);
+        let (p, p_error) = triangle_point(&self.p, b);
+        payload.set_hit_point(p, p_error);
+        payload
+    }
+}
+
+
+/// 光线和三角形求交，返回 (t, 重心坐标)，重心坐标对应三个顶点的权重
+///
+/// 使用 watertight 的求交方法：将三角形变换到以光线起点为原点、光线方向为 z 轴的坐标系中，
+/// 在 xy 平面上通过边函数判断交点是否在三角形内，相邻三角形的公共边不会出现漏洞。
+/// 参考 pbrt-v3 3.6.2
+pub(crate) fn intersect_triangle(ray: &Ray, p: &[Vec3; 3], t_range: (Float, Float)) -> Option<(Float, Vec3)>
+{
+    // 将顶点平移到以光线起点为原点的坐标系中
+    let mut p0t = p[0] - *ray.orig();
+    let mut p1t = p[1] - *ray.orig();
+    let mut p2t = p[2] - *ray.orig();
+
+    // 选择光线方向中绝对值最大的分量作为 z 轴，并重排坐标轴
+    let dir = *ray.dir();
+    let kz = if dir.x.abs() > dir.y.abs() {
+        if dir.x.abs() > dir.z.abs() { 0 } else { 2 }
+    } else if dir.y.abs() > dir.z.abs() { 1 } else { 2 };
+    let kx = (kz + 1) % 3;
+    let ky = (kx + 1) % 3;
+
+    let permute = |v: Vec3| vec3(v[kx], v[ky], v[kz]);
+    let d = permute(dir);
+    p0t = permute(p0t);
+    p1t = permute(p1t);
+    p2t = permute(p2t);
+
+    // 剪切变换，使光线方向和 z 轴对齐，z 分量的变换延迟到确定相交之后
+    let sx = -d.x / d.z;
+    let sy = -d.y / d.z;
+    let sz = 1.0 / d.z;
+    p0t.x += sx * p0t.z;
+    p0t.y += sy * p0t.z;
+    p1t.x += sx * p1t.z;
+    p1t.y += sy * p1t.z;
+    p2t.x += sx * p2t.z;
+    p2t.y += sy * p2t.z;
+
+    // 边函数
+    let e0 = p1t.x * p2t.y - p1t.y * p2t.x;
+    let e1 = p2t.x * p0t.y - p2t.y * p0t.x;
+    let e2 = p0t.x * p1t.y - p0t.y * p1t.x;
+
+    triangle_edges_hit([e0, e1, e2], [p0t, p1t, p2t], sz, t_range)
+}
+
+
+/// 根据边函数判断交点是否在三角形内部，并计算 t 以及重心坐标
+///
+/// pt 是经过平移、坐标轴重排以及剪切变换之后的顶点，z 分量还没有乘以 sz
+#[inline(always)]
+fn triangle_edges_hit(e: [Float; 3], pt: [Vec3; 3], sz: Float, t_range: (Float, Float)) -> Option<(Float, Vec3)>
+{
+    let [mut e0, mut e1, mut e2] = e;
+    let [p0t, p1t, p2t] = pt;
+
+    // 边函数恰好为 0 时，使用双精度重新计算，避免公共边上的误判
+    if e0 == 0.0 || e1 == 0.0 || e2 == 0.0 {
+        e0 = (to_f64(p1t.x) * to_f64(p2t.y) - to_f64(p1t.y) * to_f64(p2t.x)) as Float;
+        e1 = (to_f64(p2t.x) * to_f64(p0t.y) - to_f64(p2t.y) * to_f64(p0t.x)) as Float;
+        e2 = (to_f64(p0t.x) * to_f64(p1t.y) - to_f64(p0t.y) * to_f64(p1t.x)) as Float;
+    }
+
+    if (e0 < 0.0 || e1 < 0.0 || e2 < 0.0) && (e0 > 0.0 || e1 > 0.0 || e2 > 0.0) {
+        return None;
+    }
+    let det = e0 + e1 + e2;
+    if det == 0.0 {
+        return None;
+    }
+
+    // 通过重心坐标对 z 插值得到 t
+    let inv_det = 1.0 / det;
+    let t = (e0 * (p0t.z * sz) + e1 * (p1t.z * sz) + e2 * (p2t.z * sz)) * inv_det;
+    if !t.is_finite() || t <= t_range.0 || t >= t_range.1 {
+        return None;
+    }
+
+    // t 的误差上界，t 在误差范围内无法确定交点是否位于光线起点的前方，参考 pbrt-v3 3.9.6
+    if t_range.0 >= 0.0 {
+        let max_zt = Float::max((p0t.z * sz).abs(), Float::max((p1t.z * sz).abs(), (p2t.z * sz).abs()));
+        let max_xt = Float::max(p0t.x.abs(), Float::max(p1t.x.abs(), p2t.x.abs()));
+        let max_yt = Float::max(p0t.y.abs(), Float::max(p1t.y.abs(), p2t.y.abs()));
+        let delta_z = gamma(3) * max_zt;
+        let delta_x = gamma(5) * (max_xt + max_zt);
+        let delta_y = gamma(5) * (max_yt + max_zt);
+        let delta_e = 2.0 * (gamma(2) * max_xt * max_yt + delta_y * max_xt + delta_x * max_yt);
+        let max_e = Float::max(e0.abs(), Float::max(e1.abs(), e2.abs()));
+        let delta_t = 3.0 * (gamma(3) * max_e * max_zt + delta_e * max_zt + delta_z * max_e) * inv_det.abs();
+        if t <= delta_t {
+            return None;
+        }
+    }
+
+    Some((t, vec3(e0 * inv_det, e1 * inv_det, e2 * inv_det)))
+}
+
+
+/// 通过重心坐标 b 插值得到三角形上的交点，返回交点以及交点坐标的误差上界
+///
+/// 插值得到的交点不受 t 的误差影响，误差只和顶点坐标的大小有关，参考 pbrt-v3 3.9.4
+pub(crate) fn triangle_point(p: &[Vec3; 3], b: &Vec3) -> (Vec3, Vec3)
+{
+    let point = p[0] * b.x + p[1] * b.y + p[2] * b.z;
+    let p_error = (glm::abs(p[0] * b.x) + glm::abs(p[1] * b.y) + glm::abs(p[2] * b.z)) * gamma(7);
+    (point, p_error)
+}
+
+
+/// 光线包中的所有光线同时和三角形求交，t 的范围是 (t_min, t_max[lane])
+///
+/// 返回 (是否相交, t, 重心坐标)，只有相交的 lane 上的 t 和重心坐标是有效的。
+/// 计算过程和 `intersect_triangle` 以及 `triangle_edges_hit` 完全相同，每一步都对所有 lane 执行相同的运算，
+/// 每条光线的坐标轴重排和各种判断都通过 lane 掩码完成；只有边函数恰好为 0 时才回到逐个 lane 的双精度计算
+pub(crate) fn intersect_triangle_packet(packet: &RayPacket, p: &[Vec3; 3], t_min: Float, t_max: FloatX4)
+                                        -> (MaskX4, FloatX4, [FloatX4; 3])
+{
+    let orig = packet.orig();
+    let zero = FloatX4::splat(0.0);
+
+    // 平移到光线起点、重排坐标轴，然后进行剪切变换，z 分量的变换延迟到确定相交之后
+    let [sx, sy, sz] = *packet.shear();
+    let pt = p.map(|vertex| {
+        let [x, y, z] = packet.permute(&[FloatX4::splat(vertex.x) - orig[0], FloatX4::splat(vertex.y) - orig[1],
+                                         FloatX4::splat(vertex.z) - orig[2]]);
+        [x + sx * z, y + sy * z, z]
+    });
+
+    // 边函数
+    let mut e0 = pt[1][0] * pt[2][1] - pt[1][1] * pt[2][0];
+    let mut e1 = pt[2][0] * pt[0][1] - pt[2][1] * pt[0][0];
+    let mut e2 = pt[0][0] * pt[1][1] - pt[0][1] * pt[1][0];
+
+    // 无效的 lane 的 t_max 是负无穷
+    let active = t_max.gt(FloatX4::splat(t_min));
+
+    // 边函数恰好为 0 时，使用双精度重新计算，避免公共边上的误判
+    let on_edge = (e0.eq(zero) | e1.eq(zero) | e2.eq(zero)) & active;
+    if on_edge.any() {
+        for lane in (0..PACKET_SIZE).filter(|&lane| on_edge.lane(lane)) {
+            let v = |vertex: usize, axis: usize| to_f64(pt[vertex][axis][lane]);
+            e0.0[lane] = (v(1, 0) * v(2, 1) - v(1, 1) * v(2, 0)) as Float;
+            e1.0[lane] = (v(2, 0) * v(0, 1) - v(2, 1) * v(0, 0)) as Float;
+            e2.0[lane] = (v(0, 0) * v(1, 1) - v(0, 1) * v(1, 0)) as Float;
+        }
+    }
+
+    let negative = e0.lt(zero) | e1.lt(zero) | e2.lt(zero);
+    let positive = e0.gt(zero) | e1.gt(zero) | e2.gt(zero);
+    let det = e0 + e1 + e2;
+    let mut hit = active & !(negative & positive) & det.ne(zero);
+
+    // 通过重心坐标对 z 插值得到 t
+    let inv_det = FloatX4::splat(1.0) / det;
+    let [z0, z1, z2] = pt.map(|vertex| vertex[2] * sz);
+    let t = (e0 * z0 + e1 * z1 + e2 * z2) * inv_det;
+    hit = hit & t.is_finite() & !t.le(FloatX4::splat(t_min)) & !t.ge(t_max);
+
+    // t 的误差上界，见 `triangle_edges_hit`
+    if t_min >= 0.0 {
+        let max3 = |a: FloatX4, b: FloatX4, c: FloatX4| a.abs().max(b.abs().max(c.abs()));
+        let max_zt = max3(z0, z1, z2);
+        let max_xt = max3(pt[0][0], pt[1][0], pt[2][0]);
+        let max_yt = max3(pt[0][1], pt[1][1], pt[2][1]);
+        let delta_z = FloatX4::splat(gamma(3)) * max_zt;
+        let delta_x = FloatX4::splat(gamma(5)) * (max_xt + max_zt);
+        let delta_y = FloatX4::splat(gamma(5)) * (max_yt + max_zt);
+        let delta_e = FloatX4::splat(2.0) * (FloatX4::splat(gamma(2)) * max_xt * max_yt + delta_y * max_xt + delta_x * max_yt);
+        let max_e = max3(e0, e1, e2);
+        let delta_t = FloatX4::splat(3.0) * (FloatX4::splat(gamma(3)) * max_e * max_zt + delta_e * max_zt + delta_z * max_e) * inv_det.abs();
+        hit = hit & !t.le(delta_t);
+    }
+
+    (hit, t, [e0 * inv_det, e1 * inv_det, e2 * inv_det])
+}
+
+
+/// 在三角形上均匀地随机取一点，返回该点的重心坐标
+pub(crate) fn rand_barycentric() -> Vec3
+{
+    let mut rng = rng();
+    let su0 = Float::sqrt(rng.gen::<Float>());
+    let b0 = 1.0 - su0;
+    let b1 = rng.gen::<Float>() * su0;
+
+    vec3(b0, b1, 1.0 - b0 - b1)
+}
+
+
+/// 三角形的 AABB，对于与坐标轴平行的三角形，在该方向上稍微扩展，确保 AABB 是有体积的
+pub(crate) fn triangle_bounding_box(p: &[Vec3; 3]) -> AABB
+{
+    let mut min = glm::min(glm::min(p[0], p[1]), p[2]);
+    let mut max = glm::max(glm::max(p[0], p[1]), p[2]);
+
+    for i in 0..3 {
+        if max[i] - min[i] < 0.0001 {
+            min[i] -= 0.0001;
+            max[i] += 0.0001;
+        }
+    }
+
+    AABB::new(min, max)
+}
+
+
+impl Hittable for Triangle
+{
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        debug_assert!(t_range.0 < t_range.1);
+
+        let (t, b) = intersect_triangle(ray, &self.p, t_range)?;
+        Some(self.payload(ray, t, &b))
+    }
+
+
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE]) {
+        let (hit, t, b) = intersect_triangle_packet(packet, &self.p, t_range.0, packet.t_max(hits, t_range.1));
+        for (lane, ray) in packet.rays().iter().enumerate().filter(|&(lane, _)| hit.lane(lane)) {
+            hits[lane] = Some(self.payload(ray, t[lane], &vec3(b[0][lane], b[1][lane], b[2][lane])));
+        }
+    }
+
+
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool {
+        intersect_triangle(ray, &self.p, t_range).is_some()
+    }
+
+
+    fn bounding_box(&self) -> Option<AABB> {
+        Some(triangle_bounding_box(&self.p))
+    }
+
+
+    fn pdf(&self, _ray: &Ray) -> Float {
+        match self.hit(_ray, (0.0, Float::INFINITY)) {
+            None => 0.0,
+
+            // 在三角形上均匀选择一个点，转换为关于立体角的概率密度
+            Some(hit_payload) => {
+                let distance_squared = hit_payload.t() * hit_payload.t();
+                let cosine = glm::dot(*_ray.dir(), self.normal).abs();
+                if cosine <= 0.0 {
+                    return 0.0;
+                }
+
+                distance_squared / (cosine * self.area)
+            }
+        }
+    }
+
+
+    fn rand_dir(&self, origin: &Vec3) -> Option<(Vec3, Float)> {
+        for _ in 0..5 {
+            let b = rand_barycentric();
+            let rand_point = self.p[0] * b.x + self.p[1] * b.y + self.p[2] * b.z;
+
+            let ray = Ray::new(*origin, rand_point);
+            let pdf = self.pdf(&ray);
+
+            if pdf > 0.0 {
+                return Some((*ray.dir(), pdf));
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use crate::material::Lambertian;
+    use super::*;
+    use num::Zero;
+
+    #[test]
+    fn test_triangle_hit()
+    {
+        let mat = Arc::new(Lambertian::new(Vec3::zero()));
+        let triangle = Triangle::new_uv(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0),
+                                        [vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0)], mat);
+
+        let ray = Ray::new(vec3(0.25, 0.5, 2.0), vec3(0.25, 0.5, 0.0));
+        let payload = triangle.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+        assert!((payload.t() - 2.0).abs() < 1e-5);
+        assert!((payload.uv().x - 0.25).abs() < 1e-5 && (payload.uv().y - 0.5).abs() < 1e-5);
+        assert!(payload.front_face());
+
+        // 公共边上的点也要能击中
+        let ray = Ray::new(vec3(0.5, 0.5, -1.0), vec3(0.5, 0.5, 0.0));
+        assert!(triangle.hit(&ray, (0.001, Float::INFINITY)).is_some());
+
+        let ray = Ray::new(vec3(0.6, 0.6, 1.0), vec3(0.6, 0.6, 0.0));
+        assert!(triangle.hit(&ray, (0.001, Float::INFINITY)).is_none());
+    }
+
+    #[test]
+    fn test_triangle_rand()
+    {
+        let mat = Arc::new(Lambertian::new(Vec3::zero()));
+        let triangle = Triangle::new(vec3(-1.0, 2.0, -1.0), vec3(1.0, 2.0, -1.0), vec3(0.0, 2.0, 1.0), mat);
+        let p = vec3(0.3, 0.0, 0.2);
+
+        for _ in 0..100 {
+            let (dir, pdf) = triangle.rand_dir(&p).unwrap();
+            let ray = Ray::new_d(p, dir);
+            assert!((pdf - triangle.pdf(&ray)).abs() < 0.001 * pdf);
+        }
+    }
+}
@@ -2,34 +2,35 @@ use std::sync::Arc;
 use glm::ext::Consts;
 use crate::geom::aabb::AABB;
 use crate::hit::{HitPayload, Hittable};
-use crate::material::{Material, Scatter};
+use crate::material::{Bsdf, BsdfFlags, BsdfSample, Bxdf, Material};
 use crate::pdf::RandSpherePDF;
 use crate::ray::Ray;
 use crate::texture::{SolidColor, Texture};
-use crate::utility::rand_unit_vec;
+use crate::utility::{random, unit_vec};
+use crate::float::{Float, Vec3, vec2, vec3};
 
 
 /// 密度是常数的介质
 pub struct ConstantMedium
 {
-    /// 假定包围体是凸的
+    /// 包围体需要是封闭的，可以是非凸的（例如 CSG），此时光线会依次穿过多段介质
     boundary: Arc<dyn Hittable + Sync + Send>,
     phase_function: Arc<dyn Material + Sync + Send>,
 
-    neg_inv_density: f32,
+    neg_inv_density: Float,
 }
 
 
 impl ConstantMedium
 {
-    pub fn new(boundary: Arc<dyn Hittable + Sync + Send>, density: f32, albedo: Arc<dyn Texture + Sync + Send>) -> ConstantMedium
+    pub fn new(boundary: Arc<dyn Hittable + Sync + Send>, density: Float, albedo: Arc<dyn Texture + Sync + Send>) -> ConstantMedium
     {
         debug_assert!(density.is_finite() && density != 0.0);
 
         ConstantMedium { boundary, neg_inv_density: -1.0 / density, phase_function: Arc::new(Isotropic::new(albedo)) }
     }
 
-    pub fn new_c(boundary: Arc<dyn Hittable + Sync + Send>, density: f32, color: glm::Vec3) -> ConstantMedium
+    pub fn new_c(boundary: Arc<dyn Hittable + Sync + Send>, density: Float, color: Vec3) -> ConstantMedium
     {
         debug_assert!(density.is_finite() && density != 0.0);
 
@@ -41,45 +42,56 @@ impl ConstantMedium
 impl Hittable for ConstantMedium
 {
     /// 在介质内，每前进单位距离，就有固定的概率发生散射，概率与介质密度有关
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload> {
-        // 得到光线关于 boundary 的两个交点，其中 hit1 是符合条件的 t 最小的交点
-        // 需要包围体是凸的
-        let hit_payload1 = match self.boundary.hit(ray, (f32::NEG_INFINITY, f32::INFINITY)) {
-            None => { return None; }
-            Some(payload) => payload
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload> {
+        // 散射距离，在光线第一次进入介质时才进行采样
+        // 因为密度处处相同，光线穿过多段介质时，可以将这些介质首尾相接，只需要采样一次散射距离
+        let mut hit_distance: Option<Float> = None;
+
+        let mut t_min = Float::NEG_INFINITY;
+        let (t1, hit_distance) = loop {
+            // 得到光线关于 boundary 的一对交点，分别是进入和离开介质的位置
+            let hit_payload1 = match self.boundary.hit(ray, (t_min, Float::INFINITY)) {
+                None => { return None; }
+                Some(payload) => payload
+            };
+
+            let hit_payload2 = match self.boundary.hit(ray, (hit_payload1.t_next(ray), Float::INFINITY)) {
+                None => { return None; }
+                Some(payload) => payload
+            };
+
+            // clamp 两个交点的范围
+            let t1 = Float::max(hit_payload1.t(), t_range.0);
+            let t2 = Float::min(hit_payload2.t(), t_range.1);
+            if t1 < t2 {
+                let t1 = Float::max(0.0, t1);
+
+                // 光线在这一段介质内可以走的最大距离
+                let distance_inside_boundary = t2 - t1;
+
+                // 注：对数函数在 (0, 1) 的运算结果是负数
+                // 在雾中发生散射是一个泊松过程，lambda = density（单位距离发生散射的概率/次数）
+                // 「散射距离」符合「爱尔兰」分布，根据分布变换，可以从 uniform 分布的随机数得到「散射距离」这个随机变量。
+                // hit_distance 的取值范围是 [0, +inf]，不会发生意外错误
+                let distance = hit_distance.unwrap_or_else(|| self.neg_inv_density * glm::log(random::<Float>()));
+                if distance <= distance_inside_boundary {
+                    break (t1, distance);
+                }
+                hit_distance = Some(distance - distance_inside_boundary);
+            }
+
+            // 光线能够直接穿过介质而不发生散射
+            if hit_payload2.t() >= t_range.1 { return None; }
+            t_min = hit_payload2.t_next(ray);
         };
 
-        let hit_payload2 = match self.boundary.hit(ray, (hit_payload1.t() + 0.0001, f32::INFINITY)) {
-            None => { return None; }
-            Some(payload) => payload
-        };
-
-        // clamp 两个交点的范围
-        let t1 = f32::max(hit_payload1.t(), t_range.0);
-        let t2 = f32::min(hit_payload2.t(), t_range.1);
-        if t1 >= t2 { return None; }
-        let t1 = f32::max(0.0, t1);
-
-
-        // 光线在介质内可以走的最大距离
-        let distance_inside_boundary = t2 - t1;
-
-        // 注：对数函数在 (0, 1) 的运算结果是负数
-        // 在雾中发生散射是一个泊松过程，lambda = density（单位距离发生散射的概率/次数）
-        // 「散射距离」符合「爱尔兰」分布，根据分布变换，可以从 uniform 分布的随机数得到「散射距离」这个随机变量。
-        // hit_distance 的取值范围是 [0, +inf]，不会发生意外错误
-        let hit_distance = self.neg_inv_density * glm::log(rand::random::<f32>());
-
-        // 光线能够直接穿过介质而不发生散射
-        if hit_distance > distance_inside_boundary { return None; }
-
         let t = t1 + hit_distance;
-        let normal = glm::vec3(1.0, 0.0, 0.0);
+        let normal = vec3(1.0, 0.0, 0.0);
         // NOTE 说是需要保证始终是 front_face，目前没有看出什么影响
         // https://raytracing.github.io/books/RayTracingTheNextWeek.html#volumes
 
 
-        Some(HitPayload::new(ray, t, normal, self.phase_function.clone(), glm::vec2(0.0, 0.0)))
+        Some(HitPayload::new(ray, t, normal, self.phase_function.clone(), vec2(0.0, 0.0)))
     }
 
     fn bounding_box(&self) -> Option<AABB> {
@@ -102,7 +114,7 @@ impl Isotropic
         Isotropic { albedo }
     }
 
-    pub fn new_c(albedo: glm::Vec3) -> Isotropic
+    pub fn new_c(albedo: Vec3) -> Isotropic
     {
         Isotropic { albedo: Arc::new(SolidColor::new(albedo)) }
     }
@@ -112,11 +124,36 @@ impl Isotropic
 impl Material for Isotropic
 {
     /// ios 介质会让散射方向随机
-    fn scatter(&self, _ray_in: &Ray, hit_payload: &HitPayload) -> Option<Scatter> {
-        Some(Scatter {
-            attenuation: self.albedo.sample(hit_payload.uv(), hit_payload.hit_point()),
-            diffuse_pdf: None,
-            specular_ray: Some(Ray::new_d(*hit_payload.hit_point(), rand_unit_vec())),
-        })
+    fn bsdf(&self, _: &Ray, hit_payload: &HitPayload) -> Option<Bsdf> {
+        Some(Bsdf::new(hit_payload, Box::new(IsotropicBxdf { albedo: self.albedo.sample_hit(hit_payload) })))
+    }
+}
+
+
+/// 各向同性的相位函数：所有方向的概率密度都是 1/(4pi)，没有余弦项，和法线无关
+struct IsotropicBxdf
+{
+    albedo: Vec3,
+}
+
+
+impl Bxdf for IsotropicBxdf
+{
+    fn flags(&self) -> BsdfFlags { BsdfFlags::REFLECTION | BsdfFlags::TRANSMISSION | BsdfFlags::DIFFUSE }
+
+
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3) -> Vec3 {
+        self.albedo * (0.25 * Float::one_over_pi())
+    }
+
+
+    fn sample(&self, wo: &Vec3, u: &Vec3) -> Option<BsdfSample> {
+        let wi = unit_vec(&vec2(u.y, u.z));
+        Some(BsdfSample { wi, f: self.eval(wo, &wi), pdf: self.pdf(wo, &wi), flags: self.flags() })
+    }
+
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3) -> Float {
+        0.25 * Float::one_over_pi()
     }
 }
\ No newline at end of file
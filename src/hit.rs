@@ -1,21 +1,29 @@
 use std::sync::Arc;
 use crate::geom::aabb::AABB;
 use crate::material::Material;
+use crate::packet::{PACKET_SIZE, RayPacket};
 use crate::ray::Ray;
-use crate::utility::{is_normalized};
+use crate::utility::{gamma, is_normalized};
+use crate::float::{Float, Vec2, Vec3};
 
 
 /// 射线交点需要带有的信息
 pub struct HitPayload
 {
     /// 交点到射线起点的距离，根据射线的单位向量方向来计算
-    t: f32,
+    t: Float,
 
     /// 交点位置几何的法线，一定是单位向量；与光线方向相对的，并不是物体的实际法线
-    normal: glm::Vec3,
+    normal: Vec3,
+
+    /// 用于着色的法线（例如由顶点法线插值得到），单位向量，和 normal 位于表面的同一侧
+    shading_normal: Vec3,
 
     /// 击中的交点
-    p: glm::Vec3,
+    p: Vec3,
+
+    /// 交点坐标每个分量的绝对误差上界，用于偏移从交点出发的光线的起点
+    p_error: Vec3,
 
     /// 光线是否是从外部击中物体表面
     front_face: bool,
@@ -23,14 +31,17 @@ pub struct HitPayload
     mat: Arc<dyn Material + Send + Sync>,
 
     /// 交点的纹理坐标
-    uv: glm::Vec2,
+    uv: Vec2,
+
+    /// 由顶点颜色插值得到的颜色；物体没有顶点颜色时为 None
+    color: Option<Vec3>,
 }
 
 
 impl HitPayload
 {
     /// obj_normal 是物体的实际法线，保证已经正规化
-    pub fn new(ray: &Ray, t: f32, obj_normal: glm::Vec3, mat: Arc<dyn Material + Send + Sync>, uv: glm::Vec2) -> HitPayload
+    pub fn new(ray: &Ray, t: Float, obj_normal: Vec3, mat: Arc<dyn Material + Send + Sync>, uv: Vec2) -> HitPayload
     {
         debug_assert!(is_normalized(&obj_normal));
         debug_assert!(t.is_finite());
@@ -39,28 +50,135 @@ impl HitPayload
         let front_face = glm::dot(*ray.dir(), obj_normal) < 0.0;
         let normal = if front_face { obj_normal } else { -obj_normal };
 
-        HitPayload { t, normal, p: ray.at(t), front_face, mat, uv }
+        // 默认的误差上界：假设 t 只有几个 ulp 的相对误差，那么 o + t * d 的误差和 |o|、|t * d| 成比例。
+        // 能够更精确地计算交点的物体（例如球、三角形）会通过 `set_hit_point` 给出更紧的上界
+        let p_error = (glm::abs(*ray.orig()) + glm::abs(*ray.dir() * t)) * gamma(7);
+
+        HitPayload { t, normal, shading_normal: normal, p: ray.at(t), p_error, front_face, mat, uv, color: None }
     }
 
     /// 和光线相对的法线方向，并不是物体本身的法线方向
-    pub fn normal(&self) -> &glm::Vec3 { &self.normal }
+    pub fn normal(&self) -> &Vec3 { &self.normal }
 
     /// 物体本身的法线方向
-    pub fn obj_normal(&self) -> glm::Vec3 { if self.front_face { self.normal } else { -self.normal } }
+    pub fn obj_normal(&self) -> Vec3 { if self.front_face { self.normal } else { -self.normal } }
+
+    /// 用于着色的法线，和 normal 位于表面的同一侧，即与光线方向相对
+    pub fn shading_normal(&self) -> &Vec3 { &self.shading_normal }
+
+    /// 物体本身的着色法线方向
+    pub fn obj_shading_normal(&self) -> Vec3 { if self.front_face { self.shading_normal } else { -self.shading_normal } }
     pub fn front_face(&self) -> bool { self.front_face }
-    pub fn t(&self) -> f32 { self.t }
+    pub fn t(&self) -> Float { self.t }
     pub fn material(&self) -> &Arc<dyn Material + Send + Sync> { &self.mat }
-    pub fn hit_point(&self) -> &glm::Vec3 { &self.p }
-    pub fn uv(&self) -> &glm::Vec2 { &self.uv }
+    pub fn hit_point(&self) -> &Vec3 { &self.p }
+    pub fn p_error(&self) -> &Vec3 { &self.p_error }
+    pub fn uv(&self) -> &Vec2 { &self.uv }
+    pub fn color(&self) -> Option<&Vec3> { self.color.as_ref() }
 
     /// 重新设置交点的法线，确保法线是正规化的，且方向是和光线方向相对的
-    pub fn set_normal(&mut self, normal: glm::Vec3, front_face: bool)
+    pub fn set_normal(&mut self, normal: Vec3, front_face: bool)
     {
         debug_assert!((glm::length(normal) - 1.0).abs() < 0.0001);
 
+        // 着色法线需要跟随几何法线翻转
+        if glm::dot(normal, self.normal) < 0.0 {
+            self.shading_normal = -self.shading_normal;
+        }
+
         self.front_face = front_face;
         self.normal = normal;
     }
+
+
+    /// 设置着色法线，obj_shading_normal 是物体本身的着色法线，确保已经正规化
+    ///
+    /// 着色法线会被调整到和几何法线相同的一侧
+    pub fn set_shading_normal(&mut self, obj_shading_normal: Vec3)
+    {
+        debug_assert!(is_normalized(&obj_shading_normal));
+
+        self.shading_normal = if glm::dot(obj_shading_normal, self.normal) < 0.0 { -obj_shading_normal } else { obj_shading_normal };
+    }
+
+
+    /// 重新设置交点的位置，以及位置每个分量的绝对误差上界
+    ///
+    /// 物体可以将交点投影回表面（例如球面、三角形的重心坐标插值），得到比 `ray.at(t)` 更精确的位置
+    pub fn set_hit_point(&mut self, p: Vec3, p_error: Vec3)
+    {
+        debug_assert!(p_error.x >= 0.0 && p_error.y >= 0.0 && p_error.z >= 0.0);
+
+        self.p = p;
+        self.p_error = p_error;
+    }
+
+
+    /// 交点位于和 axis 轴垂直的平面 coord 上（例如轴对齐的矩形、圆盘），这个分量的坐标是精确的
+    pub fn snap_to_plane(&mut self, axis: usize, coord: Float)
+    {
+        self.p[axis] = coord;
+        self.p_error[axis] = 0.0;
+    }
+
+
+    /// 从交点出发、朝向 dir 的光线的起点
+    ///
+    /// 交点的坐标存在误差，直接作为起点时，光线可能再次击中同一个表面（shadow acne），或者穿过表面（漏光）。
+    /// 这里沿着几何法线向 dir 所在的一侧偏移，偏移量恰好越过交点的误差范围，只取决于误差上界，和场景的尺度无关。
+    /// 参考 pbrt-v3 3.9.5
+    pub fn spawn_origin(&self, dir: &Vec3) -> Vec3
+    {
+        let d = glm::dot(glm::abs(self.normal), self.p_error);
+        let n = if glm::dot(*dir, self.normal) < 0.0 { -self.normal } else { self.normal };
+        let mut origin = self.p + n * d;
+
+        // 加法的舍入可能抵消一部分偏移量，再向偏移的方向移动到下一个浮点数
+        for axis in 0..3 {
+            if n[axis] > 0.0 {
+                origin[axis] = origin[axis].next_up();
+            } else if n[axis] < 0.0 {
+                origin[axis] = origin[axis].next_down();
+            }
+        }
+        origin
+    }
+
+
+    /// 从交点出发、方向为 dir 的光线，起点经过了 `spawn_origin` 的偏移，求交时 t 的范围从 0 开始即可
+    pub fn spawn_ray(&self, dir: Vec3) -> Ray
+    {
+        Ray::new_d(self.spawn_origin(&dir), dir)
+    }
+
+
+    /// 沿着同一条光线越过这个交点，继续寻找下一个交点时，t 的下界
+    ///
+    /// 交点在法线方向上的误差范围宽度为 d，光线需要前进 d / cos 才能离开这个范围
+    pub fn t_next(&self, ray: &Ray) -> Float
+    {
+        let d = glm::dot(glm::abs(self.normal), self.p_error);
+        if d == 0.0 {
+            return self.t.next_up();
+        }
+
+        let cos = glm::dot(*ray.dir(), self.normal).abs();
+        (self.t + 2.0 * d / cos).next_up()
+    }
+
+
+    /// 替换交点的材质，例如实例覆盖了原型的材质
+    pub fn set_material(&mut self, mat: Arc<dyn Material + Send + Sync>)
+    {
+        self.mat = mat;
+    }
+
+
+    /// 设置交点的顶点颜色
+    pub fn set_color(&mut self, color: Option<Vec3>)
+    {
+        self.color = color;
+    }
 }
 
 
@@ -68,8 +186,35 @@ impl HitPayload
 pub trait Hittable
 {
     /// 判断是否相交，并返回相交的数据
-    /// - `t_range` 表示射线的有效范围，是一个开区间
-    fn hit(&self, ray: &Ray, t_range: (f32, f32)) -> Option<HitPayload>;
+    /// - `t_range` 表示射线的有效范围，是一个开区间；从表面出发的光线由 `HitPayload::spawn_ray` 偏移了起点，范围从 0 开始即可
+    fn hit(&self, ray: &Ray, t_range: (Float, Float)) -> Option<HitPayload>;
+
+
+    /// 光线在 `t_range` 范围内是否被遮挡，用于阴影光线等只关心可见性的场合
+    ///
+    /// 找到任意一个交点就可以返回，不需要找到最近的交点，也不需要构造 `HitPayload`
+    fn occluded(&self, ray: &Ray, t_range: (Float, Float)) -> bool
+    {
+        self.hit(ray, t_range).is_some()
+    }
+
+
+    /// 光线包中的每条光线分别求交，hits[lane] 对应第 lane 条光线
+    ///
+    /// hits 中已有的交点会缩小对应光线的 t 的范围，只有更近的交点才会替换它，因此可以依次对多个物体调用。
+    /// 默认逐条光线调用 `hit`，BVH、球体以及三角形会同时处理整个光线包
+    fn hit_packet(&self, packet: &RayPacket, t_range: (Float, Float), hits: &mut [Option<HitPayload>; PACKET_SIZE])
+    {
+        for (lane, ray) in packet.rays().iter().enumerate() {
+            let t_max = hits[lane].as_ref().map_or(t_range.1, |payload| payload.t());
+            if t_range.0 >= t_max {
+                continue;
+            }
+            if let Some(payload) = self.hit(ray, (t_range.0, t_max)) {
+                hits[lane] = Some(payload);
+            }
+        }
+    }
 
 
     /// 获取物体的 AABB
@@ -79,15 +224,91 @@ pub trait Hittable
     /// 已知光线的起点，按照某种方法选择一个方向，让光线可以击中物体
     ///
     /// 某个方向对应的 pdf 为多少
-    fn pdf(&self, _ray: &Ray) -> f32
+    fn pdf(&self, _ray: &Ray) -> Float
     {
         0.0
     }
 
 
     /// 已知光线的起点，按照某种方法选择一个方向，让光线可以击中物体
-    fn rand_dir(&self, _origin: &glm::Vec3) -> Option<(glm::Vec3, f32)>
+    fn rand_dir(&self, _origin: &Vec3) -> Option<(Vec3, Float)>
     {
         None
     }
 }
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::float::{Mat4, vec3};
+    use num::One;
+    use crate::geom::Sphere;
+    use crate::geom::cylinder::Cylinder;
+    use crate::geom::heightfield::Heightfield;
+    use crate::geom::plane::Plane;
+    use crate::geom::quad::Quad;
+    use crate::geom::transform::Transform;
+    use crate::geom::triangle::Triangle;
+    use crate::material::Lambertian;
+    use crate::utility::rand_unit_vec;
+
+    #[test]
+    fn test_spawn_ray()
+    {
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+
+        // 物体的尺寸从 0.001 到 1000，并且都远离原点
+        for scale in [1e-3 as Float, 1.0, 1e3] {
+            let center = vec3(30.0, -20.0, 50.0) * scale;
+            let sphere = Sphere::new(center, scale, mat.clone());
+            let matrix = glm::ext::scale(&glm::ext::translate(&Mat4::one(), center), vec3(scale, 2.0 * scale, scale));
+            let transformed = Transform::new(Arc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 1.0, mat.clone())), matrix);
+            let triangle = Triangle::new(center + vec3(-2.0, 0.0, -2.0) * scale, center + vec3(2.0, 0.0, -2.0) * scale,
+                                         center + vec3(0.0, 0.0, 2.0) * scale, mat.clone());
+            let cylinder = Cylinder::new(center - vec3(0.0, scale, 0.0), scale, 2.0 * scale, true, mat.clone());
+            let quad = Quad::new(center + vec3(-2.0, -1.0, -2.0) * scale, vec3(4.0, 1.0, 0.0) * scale, vec3(0.0, 1.0, 4.0) * scale, mat.clone());
+            let plane = Plane::new(center, vec3(1.0, 2.0, 3.0), mat.clone());
+
+            // 斜坡形状的高度场，每个格子中的两个三角形位于同一个平面上
+            let slope = (0..9).map(|k| (k % 3) as Float / 2.0).collect();
+            let heightfield = Heightfield::new(slope, (3, 3), center + vec3(-2.0, -0.5, -2.0) * scale, vec3(4.0, 1.0, 4.0) * scale, mat.clone());
+
+            let objects: [(&dyn Hittable, bool); 7] = [(&sphere, true), (&transformed, true), (&cylinder, true), (&triangle, false),
+                                                        (&quad, false), (&plane, false), (&heightfield, false)];
+
+            for (obj, closed) in objects {
+                for _ in 0..200 {
+                    let ray = Ray::new(center + rand_unit_vec() * (5.0 * scale), center + rand_unit_vec() * (0.5 * scale));
+                    // 光线恰好经过圆柱侧面和底面的交界时可能会漏掉第一个交点，这里只检查从外侧击中的交点
+                    let payload = match obj.hit(&ray, (0.0, Float::INFINITY)) {
+                        Some(payload) if payload.front_face() => payload,
+                        _ => continue,
+                    };
+
+                    // 朝向表面外侧的光线不会再次击中同一个表面（凸的物体）
+                    let mut dir = rand_unit_vec();
+                    if glm::dot(dir, *payload.normal()) < 0.0 {
+                        dir = -dir;
+                    }
+                    assert!(obj.hit(&payload.spawn_ray(dir), (0.0, Float::INFINITY)).is_none());
+
+                    // 进入封闭物体内部的光线一定会从另一侧离开，不会漏光
+                    if closed {
+                        let inside = obj.hit(&payload.spawn_ray(-dir), (0.0, Float::INFINITY));
+                        assert!(inside.is_some_and(|payload| !payload.front_face()));
+                    }
+
+                    // 交点的误差上界只和物体以及交点的位置有关，和光线起点的距离无关
+                    let target = *payload.hit_point();
+                    let far = Ray::new(target - *ray.dir() * (1e4 * scale), target);
+                    if let Some(payload) = obj.hit(&far, (0.0, Float::INFINITY)) {
+                        let bound = (glm::length(center) + glm::length(target - center) + 10.0 * scale) * gamma(10);
+                        assert!(glm::length(*payload.p_error()) <= bound, "{:?} > {}", payload.p_error(), bound);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -5,7 +5,10 @@ extern crate quickcheck;
 extern crate quickcheck_macros;
 
 
+pub mod float;
 pub mod ray;
+pub mod simd;
+pub mod packet;
 pub mod framebuffer;
 pub mod utility;
 pub mod camera;
@@ -17,3 +20,4 @@ pub mod noise;
 pub mod hit;
 pub mod pdf;
 
+pub mod scene;
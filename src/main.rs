@@ -1,106 +1,281 @@
 use std::sync::Arc;
+use std::time::Instant;
 
 use rand::{Rand, Rng};
 use num::Zero;
+use num::traits::FloatConst;
 
 use rt_week::{camera::Camera,
-              framebuffer::FrameBuffer,
+              framebuffer::{FrameBuffer, ImageFormat},
               geom::Sphere,
               material::{Dielecric, Lambertian, Material, Metal},
               render::Renderer};
-use rt_week::geom::Axis;
-use rt_week::geom::bvh::BVHNode;
+use rt_week::float::{Float, Vec3, vec2, vec3};
+use rt_week::geom::{Axis, MovingSphere};
+use rt_week::geom::bvh::{set_build_threads, BVHNode};
 use rt_week::geom::cube::Cube;
 use rt_week::geom::hittable_list::HittableList;
+use rt_week::geom::plane::Plane;
 use rt_week::geom::rect::AxisRect;
-use rt_week::geom::transform::{FlipFace, RotateY, Translate};
+use rt_week::geom::transform::{FlipFace, Translate};
 use rt_week::geom::volumn::ConstantMedium;
 use rt_week::material::DiffuseEmit;
 use rt_week::noise::NoiseTexture;
-use rt_week::hit::Hittable;
 use rt_week::render::Background;
+use rt_week::scene::{load_scene, Scene};
 use rt_week::texture::{CheckerTexture, ImageTexture};
+use rt_week::utility::{random, rng, seed_rng};
 
 
-fn main() {
-    let mut renderer = Renderer::new();
+const USAGE: &str = "\
+Usage: rt-week [OPTIONS]
 
+Options:
+  -s, --scene <NAME|FILE>   built-in scene name or scene file [default: final]
+  -l, --list                list built-in scenes and exit
+  -w, --width <N>           image width, height follows the camera aspect [default: 600]
+      --height <N>          image height, width follows the camera aspect
+  -n, --samples <N>         samples per pixel [default: from scene]
+  -d, --depth <N>           max ray depth [default: from scene]
+  -j, --threads <N>         worker threads, 1 renders on the main thread [default: 8]
+  -t, --tile <N>            tile size in pixels [default: 32]
+      --seed <N>            random seed, makes the image reproducible
+  -o, --output <PATH>       output image [default: image.ppm]
+  -f, --format <ppm|bmp>    output format [default: from output extension]
+  -h, --help                print this help";
 
-    let (scene, camera, lights) = match 7 {
-        0 => random_scene(),
-        1 => two_sphere(),
-        2 => two_perlin_sphere(),
-        3 => scene_earch(),
-        4 => {
-            renderer.set_backround(Background::Color(glm::Vec3::zero()));
-            renderer.set_quality(64, 50);
-            scene_light()
-        }
-        5 => {
-            renderer.set_backround(Background::Color(glm::Vec3::zero()));
-            renderer.set_quality(128, 128);
-            cornel_box()
+
+/// 场景的来源：代码生成，或者场景文件
+enum SceneSource
+{
+    Code(fn() -> Scene),
+    File(&'static str),
+}
+
+
+/// 内置的场景：名字，描述，来源
+const BUILTIN_SCENES: [(&str, &str, SceneSource); 11] = [
+    ("random", "random small spheres with lambert, metal and glass", SceneSource::Code(random_scene)),
+    ("two-sphere", "two checker textured spheres", SceneSource::File("scenes/two_sphere.scene")),
+    ("two-perlin", "two spheres with perlin noise texture", SceneSource::File("scenes/two_perlin_sphere.scene")),
+    ("earth", "image texture mapped earth", SceneSource::File("scenes/earth.scene")),
+    ("light", "perlin spheres lit by an area light", SceneSource::File("scenes/light.scene")),
+    ("cornel-box", "cornel box with a glass sphere", SceneSource::File("scenes/cornel_box.scene")),
+    ("cornel-smoke", "cornel box with two smoke boxes", SceneSource::File("scenes/cornel_smoke.scene")),
+    ("instances", "a forest of instanced trees sharing one prototype", SceneSource::File("scenes/instances.scene")),
+    ("sdf", "sphere traced fractal and blended SDF shapes", SceneSource::File("scenes/sdf.scene")),
+    ("terrain", "heightfields from perlin noise and an image", SceneSource::File("scenes/terrain.scene")),
+    ("final", "everything: BVH, textures, glass, fog, motion blur", SceneSource::Code(final_scene)),
+];
+
+
+/// 命令行参数，None 表示使用场景或者渲染器的默认值
+struct Options
+{
+    scene: String,
+    list: bool,
+    width: Option<u32>,
+    height: Option<u32>,
+    samples: Option<u32>,
+    depth: Option<u32>,
+    threads: Option<u32>,
+    tile: Option<u32>,
+    seed: Option<u64>,
+    output: String,
+    format: Option<ImageFormat>,
+}
+
+
+impl Options
+{
+    fn parse(args: &[String]) -> Result<Options, String>
+    {
+        let mut options = Options {
+            scene: "final".to_string(),
+            list: false,
+            width: None,
+            height: None,
+            samples: None,
+            depth: None,
+            threads: None,
+            tile: None,
+            seed: None,
+            output: "image.ppm".to_string(),
+            format: None,
+        };
+
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            // 支持 --key=value 的形式
+            let (key, inline_value) = match arg.split_once('=') {
+                Some((key, value)) if key.starts_with("--") => (key, Some(value.to_string())),
+                _ => (arg.as_str(), None),
+            };
+            let mut value = || inline_value.clone().or_else(|| iter.next().cloned())
+                .ok_or(format!("missing value for `{}`", key));
+
+            match key {
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                }
+                "-l" | "--list" => options.list = true,
+                "-s" | "--scene" => options.scene = value()?,
+                "-w" | "--width" => options.width = Some(parse_positive(key, &value()?)?),
+                "--height" => options.height = Some(parse_positive(key, &value()?)?),
+                "-n" | "--samples" => options.samples = Some(parse_positive(key, &value()?)?),
+                "-d" | "--depth" => options.depth = Some(parse_positive(key, &value()?)?),
+                "-j" | "--threads" => options.threads = Some(parse_positive(key, &value()?)?),
+                "-t" | "--tile" => options.tile = Some(parse_positive(key, &value()?)?),
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(seed.parse().map_err(|_| format!("invalid seed `{}`", seed))?);
+                }
+                "-o" | "--output" => options.output = value()?,
+                "-f" | "--format" => {
+                    let format = value()?;
+                    options.format = Some(ImageFormat::from_name(&format).ok_or(format!("unknown image format `{}`", format))?);
+                }
+                _ => return Err(format!("unknown argument `{}`", arg)),
+            }
         }
-        6 => {
-            renderer.set_backround(Background::Color(glm::Vec3::zero()));
-            renderer.set_quality(32, 32);
-            cornel_smoke()
+
+        if options.width.is_some() && options.height.is_some() {
+            return Err("only one of --width and --height can be given, the other follows the camera aspect".to_string());
         }
-        7 => {
-            renderer.set_backround(Background::Color(glm::Vec3::zero()));
-            renderer.set_quality(32, 32);
-            final_scene()
+
+        Ok(options)
+    }
+}
+
+
+fn parse_positive(key: &str, value: &str) -> Result<u32, String>
+{
+    match value.parse::<u32>() {
+        Ok(val) if val > 0 => Ok(val),
+        _ => Err(format!("`{}` expects a positive integer, found `{}`", key, value)),
+    }
+}
+
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = Options::parse(&args).unwrap_or_else(|msg| exit_with(&format!("{}\n\n{}", msg, USAGE)));
+
+    if options.list {
+        for (name, desc, source) in BUILTIN_SCENES.iter() {
+            let source = match source {
+                SceneSource::Code(_) => "built in".to_string(),
+                SceneSource::File(path) => path.to_string(),
+            };
+            println!("{:<14} {:<50} ({})", name, desc, source);
         }
-        _ => panic!(""),
+        return;
+    }
+
+    let format = options.format
+        .or_else(|| ImageFormat::from_path(&options.output))
+        .unwrap_or(ImageFormat::Ppm);
+
+    // 场景的构建可能也需要随机数
+    if let Some(seed) = options.seed {
+        seed_rng(seed);
+    }
+
+    // 构建 BVH 使用和渲染相同的线程数量
+    let mut renderer = Renderer::new();
+    renderer.set_performance(options.threads.unwrap_or(renderer.thread_num()),
+                             options.tile.unwrap_or(renderer.tile_size()));
+    set_build_threads(renderer.thread_num());
+
+    let build_start = Instant::now();
+    let scene = match BUILTIN_SCENES.iter().find(|(name, _, _)| *name == options.scene) {
+        Some((_, _, SceneSource::Code(builder))) => builder(),
+        Some((_, _, SceneSource::File(path))) => load(path),
+        None if std::path::Path::new(&options.scene).is_file() => load(&options.scene),
+        None => exit_with(&format!("unknown scene `{}`, use --list to see the built-in scenes", options.scene)),
+    };
+    let build_time = build_start.elapsed();
+
+
+    // 配置渲染器：场景的设置优先级低于命令行参数
+    scene.apply(&mut renderer);
+    renderer.set_quality(options.samples.unwrap_or(renderer.samples()),
+                         options.depth.unwrap_or(renderer.max_depth()));
+    renderer.set_seed(options.seed);
+
+    let width = match (options.width, options.height) {
+        (_, Some(height)) => ((height as Float * scene.camera.aspect()).round() as u32).max(1),
+        (width, None) => width.unwrap_or(600),
     };
+    let mut framebuffer = FrameBuffer::new(width, scene.camera.aspect());
 
-    let mut framebuffer = FrameBuffer::new(600, camera.aspect());
+    println!("scene `{}`: {}x{}, {} spp, max depth {}, {} threads, tile {}",
+             options.scene, framebuffer.width(), framebuffer.height(), renderer.samples(),
+             renderer.max_depth(), renderer.thread_num(), renderer.tile_size());
+    println!("build time:   {:.3} s (scene loading and BVH construction)", build_time.as_secs_f64());
 
 
     // 开始渲染
-    {
-        let now = std::time::SystemTime::now();
-        match 0 {
-            0 => { Renderer::render_multi_thread(Arc::new(renderer), &mut framebuffer, scene, &camera, lights); }
-            _ => { renderer.render_single_thread(&mut framebuffer, scene, &camera, lights); }
-        }
-        println!("{}", now.elapsed().unwrap().as_secs_f32());
+    let stats = if renderer.thread_num() == 1 {
+        renderer.render_single_thread(&mut framebuffer, scene.world, &scene.camera, scene.lights)
+    } else {
+        Renderer::render_multi_thread(Arc::new(renderer), &mut framebuffer, scene.world, &scene.camera, scene.lights)
+    };
+    println!("{}", stats);
+
+    if let Err(err) = framebuffer.save_as(&options.output, format) {
+        exit_with(&format!("can not write `{}`: {}", options.output, err));
     }
+    println!("saved to {}", options.output);
+}
+
+
+fn exit_with(msg: &str) -> !
+{
+    eprintln!("{}", msg);
+    std::process::exit(1);
+}
+
 
-    framebuffer.save("image.ppm".to_string()).unwrap();
+/// 读取场景文件，出错时打印错误信息并退出
+fn load(path: &str) -> Scene
+{
+    load_scene(path).unwrap_or_else(|err| exit_with(&err.to_string()))
 }
 
 
 /// 创建一个随机的场景
-fn random_scene() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hittable + Sync + Send>>)
+fn random_scene() -> Scene
 {
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
 
     let mut scene = HittableList::default();
 
     // 地面
-    let tex_checker = Arc::new(CheckerTexture::new_c(glm::vec3(0.2, 0.3, 0.1), glm::vec3(0.9, 0.9, 0.9)));
+    // 使用纹理坐标的棋盘格，格子的边长和空间棋盘格相同，为 pi / 10
+    let tex_checker = Arc::new(CheckerTexture::new_uv_c(vec3(0.2, 0.3, 0.1), vec3(0.9, 0.9, 0.9), 2.0));
     let mat_ground = Arc::new(Lambertian::new_t(tex_checker));
-    scene.add(Arc::new(Sphere::new(glm::vec3(0., -1000., 0.), 1000., mat_ground.clone())));
+    scene.add(Arc::new(Plane::new_uv(vec3(0., 0., 0.), vec3(0., 1., 0.), 0.2 * Float::PI(), mat_ground.clone())));
 
 
     // 随机生成一系列的小球
     for a in -11..11 {
         for b in -11..11 {
-            let center = glm::vec3(a as f32 + 0.9 * rand::random::<f32>(), 0.2, b as f32 + 0.9 * rand::random::<f32>());
+            let center = vec3(a as Float + 0.9 * random::<Float>(), 0.2, b as Float + 0.9 * random::<Float>());
 
-            let choose_mat: f32 = rand::random();
-            if glm::length(center - glm::vec3(4., 0.2, 0.)) > 0.9 {
+            let choose_mat: Float = random();
+            if glm::length(center - vec3(4., 0.2, 0.)) > 0.9 {
                 let mat_sphere: Arc<dyn Material + Send + Sync> = match choose_mat {
                     x if x < 0.8 => {
                         // diffuse 材质
-                        let albedo = glm::Vec3::rand(&mut rng) * glm::Vec3::rand(&mut rng);
+                        let albedo = Vec3::rand(&mut rng) * Vec3::rand(&mut rng);
                         Arc::new(Lambertian::new(albedo))
                     }
                     x if x < 0.95 => {
                         // metal 材质
-                        let albedo = glm::vec3(rng.gen_range(0.5, 1.0), rng.gen_range(0.5, 1.0), rng.gen_range(0.5, 1.0));
-                        let fuzz: f32 = rng.gen();
+                        let albedo = vec3(rng.gen_range(0.5, 1.0), rng.gen_range(0.5, 1.0), rng.gen_range(0.5, 1.0));
+                        let fuzz: Float = rng.gen();
                         Arc::new(Metal::new(albedo, fuzz))
                     }
                     _ => {
@@ -117,311 +292,114 @@ fn random_scene() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hi
 
     // 生成三个大球
     let mat1 = Arc::new(Dielecric::new(1.5));
-    scene.add(Arc::new(Sphere::new(glm::vec3(0., 1., 0.), 1., mat1)));
+    scene.add(Arc::new(Sphere::new(vec3(0., 1., 0.), 1., mat1)));
 
-    let mat2 = Arc::new(Lambertian::new(glm::vec3(0.4, 0.2, 0.1)));
-    scene.add(Arc::new(Sphere::new(glm::vec3(-4., 1., 0.), 1., mat2)));
+    let mat2 = Arc::new(Lambertian::new(vec3(0.4, 0.2, 0.1)));
+    scene.add(Arc::new(Sphere::new(vec3(-4., 1., 0.), 1., mat2)));
 
-    let mat3 = Arc::new(Metal::new(glm::vec3(0.7, 0.6, 0.5), 0.0));
-    scene.add(Arc::new(Sphere::new(glm::vec3(4., 1., 0.), 1., mat3)));
+    let mat3 = Arc::new(Metal::new(vec3(0.7, 0.6, 0.5), 0.0));
+    scene.add(Arc::new(Sphere::new(vec3(4., 1., 0.), 1., mat3)));
 
 
     // 摄像机
     let camera =
-        Camera::new(glm::vec3(13., 2., 3.), glm::vec3(0., 0., 0.),
-                    glm::vec3(0., 1., 0.), 20.0, 16.0 / 9.0,
+        Camera::new(vec3(13., 2., 3.), vec3(0., 0., 0.),
+                    vec3(0., 1., 0.), 20.0, 16.0 / 9.0,
                     0.1, 10.0);
 
-    (Arc::new(BVHNode::new_with_list(&scene)), camera, None)
-}
-
-
-/// 另一个场景
-fn two_sphere() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hittable + Sync + Send>>)
-{
-    let mut scene = HittableList::default();
-
-    let tex_checker = Arc::new(CheckerTexture::new_c(glm::vec3(0.2, 0.3, 0.1), glm::vec3(0.9, 0.9, 0.9)));
-    let mat_checker = Arc::new(Lambertian::new_t(tex_checker));
-
-    scene.add(Arc::new(Sphere::new(glm::vec3(0.0, -10.0, 0.0), 10.0, mat_checker.clone())));
-    scene.add(Arc::new(Sphere::new(glm::vec3(0.0, 10.0, 0.0), 10.0, mat_checker.clone())));
-
-
-    // 摄像机
-    let camera =
-        Camera::new(glm::vec3(13., 2., 3.), glm::vec3(0., 0., 0.),
-                    glm::vec3(0., 1., 0.), 20.0, 16.0 / 9.0,
-                    0.0, 10.0);
-
-    (Arc::new(BVHNode::new_with_list(&scene)), camera, None)
-}
-
-
-/// 有随机噪声纹理的球体
-fn two_perlin_sphere() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hittable + Sync + Send>>)
-{
-    let mut scene = HittableList::default();
-
-    let tex_perlin = Arc::new(NoiseTexture::new(4.0));
-    let mat_perlin = Arc::new(Lambertian::new_t(tex_perlin));
-
-    scene.add(Arc::new(Sphere::new(glm::vec3(0.0, -1000.0, 0.0), 1000.0, mat_perlin.clone())));
-    scene.add(Arc::new(Sphere::new(glm::vec3(0.0, 2.0, 0.0), 2.0, mat_perlin.clone())));
-
-
-    // 摄像机
-    let camera =
-        Camera::new(glm::vec3(13., 2., 3.), glm::vec3(0., 0., 0.),
-                    glm::vec3(0., 1., 0.), 20.0, 16.0 / 9.0,
-                    0.0, 10.0);
-
-    (Arc::new(BVHNode::new_with_list(&scene)), camera, None)
-}
-
-
-/// 地球的场景
-fn scene_earch() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hittable + Sync + Send>>)
-{
-    let mut scene = HittableList::default();
-
-    let tex_earth = Arc::new(ImageTexture::new(&"earthmap.jpg".to_string()));
-    let mat_earth = Arc::new(Lambertian::new_t(tex_earth));
-
-    scene.add(Arc::new(Sphere::new(glm::vec3(0.0, 0.0, 0.0), 2.0, mat_earth)));
-
-    // 摄像机
-    let camera =
-        Camera::new(glm::vec3(13., 2., 3.), glm::vec3(0., 0., 0.),
-                    glm::vec3(0., 1., 0.), 20.0, 16.0 / 9.0,
-                    0.0, 10.0);
-
-    (Arc::new(scene), camera, None)
-}
-
-
-/// 具有灯光的场景
-fn scene_light() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hittable + Sync + Send>>)
-{
-    // 配置场景
-    let mut scene = HittableList::default();
-
-    let tex_noise = Arc::new(NoiseTexture::new(4.0));
-    let mat_lam = Arc::new(Lambertian::new_t(tex_noise));
-
-    let sphere1 = Sphere::new(glm::vec3(0.0, -1000.0, 0.0), 1000.0, mat_lam.clone());
-    let sphere2 = Sphere::new(glm::vec3(0.0, 2.0, 0.0), 2.0, mat_lam.clone());
-    scene.add(Arc::new(sphere1));
-    scene.add(Arc::new(sphere2));
-
-    let mat_diffuse_emit = Arc::new(DiffuseEmit::new_c(glm::vec3(4.0, 4.0, 4.0)));
-    let light = Arc::new(AxisRect::new(glm::vec2(3.0, 1.0), glm::vec2(5.0, 3.0), -2.0, mat_diffuse_emit, Axis::Z));
-    scene.add(light.clone());
-
-
-    // 摄像机
-    let camera =
-        Camera::new(glm::vec3(26., 3., 6.), glm::vec3(0., 2., 0.),
-                    glm::vec3(0., 1., 0.), 20.0, 16.0 / 9.0,
-                    0.0, 10.0);
-
-
-    (Arc::new(scene), camera, Some(light.clone()))
-}
-
-
-fn cornel_box() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hittable + Sync + Send>>)
-{
-    // 配置场景
-    let mut scene = HittableList::default();
-
-    let mat_red = Arc::new(Lambertian::new(glm::vec3(0.65, 0.05, 0.05)));
-    let mat_white = Arc::new(Lambertian::new(glm::vec3(0.73, 0.73, 0.73)));
-    let mat_green = Arc::new(Lambertian::new(glm::vec3(0.12, 0.45, 0.15)));
-    let mat_light = Arc::new(DiffuseEmit::new_c(glm::vec3(15.0, 15.0, 15.0)));
-
-    // 右
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 555.0, mat_green.clone(), Axis::X)));
-    // 左
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 0.0, mat_red.clone(), Axis::X)));
-    // 灯
-    let light = Arc::new(AxisRect::new(glm::vec2(213.0, 227.0), glm::vec2(343.0, 332.0), 554.0, mat_light.clone(), Axis::Y));
-    scene.add(Arc::new(FlipFace::new(light.clone())));
-    // 地板
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 0.0, mat_white.clone(), Axis::Y)));
-    // 天花板
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 555.0, mat_white.clone(), Axis::Y)));
-    // 背景墙
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 555.0, mat_white.clone(), Axis::Z)));
-
-
-    // 两个立方体
-    // let mat_metal = Arc::new(Metal::new(glm::vec3(0.8, 0.85, 0.88), 0.0));
-    let box1 = Cube::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(165.0, 330.0, 165.0), mat_white.clone());
-    let box1 = RotateY::new(Arc::new(box1), 15.0);
-    let box1 = Translate::new(Arc::new(box1), glm::vec3(265.0, 0.0, 295.0));
-    scene.add(Arc::new(box1));
-
-    // let box2 = Cube::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(165.0, 165.0, 165.0), mat_white.clone());
-    // let box2 = RotateY::new(Arc::new(box2), -18.0);
-    // let box2 = Translate::new(Arc::new(box2), glm::vec3(130.0, 0.0, 65.0));
-    // scene.add(Arc::new(box2));
-
-
-    let mat_glass = Arc::new(Dielecric::new(1.5));
-    let sphere = Arc::new(Sphere::new(glm::vec3(190.0, 90.0, 190.0), 90.0, mat_glass.clone()));
-    scene.add(sphere.clone());
-
-
-    // 摄像机
-    let camera =
-        Camera::new(glm::vec3(278.0, 278.0, -800.0), glm::vec3(278.0, 278.0, 0.0),
-                    glm::vec3(0., 1., 0.), 40.0, 1.0,
-                    0.0, 10.0);
-
-
-    // 优先采样的目标
-    let mut lights = HittableList::default();
-    lights.add(light.clone());
-    // lights.add(sphere.clone());      // 没有必要对这个球进行优先采样
-    let lights: Option<Arc<dyn Hittable + Sync + Send>> = Some(Arc::new(lights));
-
-    (Arc::new(scene), camera, lights)
-}
-
-
-fn cornel_smoke() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hittable + Sync + Send>>)
-{
-    // 配置场景
-    let mut scene = HittableList::default();
-
-    let mat_red = Arc::new(Lambertian::new(glm::vec3(0.65, 0.05, 0.05)));
-    let mat_white = Arc::new(Lambertian::new(glm::vec3(0.73, 0.73, 0.73)));
-    let mat_green = Arc::new(Lambertian::new(glm::vec3(0.12, 0.45, 0.15)));
-    let mat_light = Arc::new(DiffuseEmit::new_c(glm::vec3(7.0, 7.0, 7.0)));
-
-    // 右
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 555.0, mat_green.clone(), Axis::X)));
-    // 左
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 0.0, mat_red.clone(), Axis::X)));
-    // 灯
-    let light = Arc::new(AxisRect::new(glm::vec2(113.0, 127.0), glm::vec2(443.0, 432.0), 554.0, mat_light.clone(), Axis::Y));
-    scene.add(Arc::new(FlipFace::new(light.clone())));
-    // 地板
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 0.0, mat_white.clone(), Axis::Y)));
-    // 天花板
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 555.0, mat_white.clone(), Axis::Y)));
-    // 背景墙
-    scene.add(Arc::new(AxisRect::new(glm::vec2(0.0, 0.0), glm::vec2(555.0, 555.0), 555.0, mat_white.clone(), Axis::Z)));
-
-
-    // 两个立方体
-    let box1 = Cube::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(165.0, 330.0, 165.0), mat_white.clone());
-    let box1 = RotateY::new(Arc::new(box1), 15.0);
-    let box1 = Translate::new(Arc::new(box1), glm::vec3(265.0, 0.0, 295.0));
-    scene.add(Arc::new(ConstantMedium::new_c(Arc::new(box1), 0.01, glm::vec3(0.0, 0.0, 0.0))));
-
-
-    let box2 = Cube::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(165.0, 165.0, 165.0), mat_white.clone());
-    let box2 = RotateY::new(Arc::new(box2), -18.0);
-    let box2 = Translate::new(Arc::new(box2), glm::vec3(130.0, 0.0, 65.0));
-    scene.add(Arc::new(ConstantMedium::new_c(Arc::new(box2), 0.01, glm::vec3(1.0, 1.0, 1.0))));
-
-
-    // 摄像机
-    let camera =
-        Camera::new(glm::vec3(278.0, 278.0, -800.0), glm::vec3(278.0, 278.0, 0.0),
-                    glm::vec3(0., 1., 0.), 40.0, 1.0,
-                    0.0, 10.0);
-
-    (Arc::new(scene), camera, Some(light.clone()))
+    Scene::new(Arc::new(BVHNode::new_with_list(&scene)), camera, None)
 }
 
 
 /// 融合了各种技术的最终场景
-fn final_scene() -> (Arc<dyn Hittable + Sync + Send>, Camera, Option<Arc<dyn Hittable + Sync + Send>>)
+fn final_scene() -> Scene
 {
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     let mut scene = HittableList::default();
 
     // 地面由多个盒子组成
     let mut boxes1 = HittableList::default();
-    let mat_ground = Arc::new(Lambertian::new(glm::vec3(0.48, 0.83, 0.53)));
+    let mat_ground = Arc::new(Lambertian::new(vec3(0.48, 0.83, 0.53)));
     let boxes_per_sides = 20;
     for i in 0..boxes_per_sides {
         for j in 0..boxes_per_sides {
             let w = 100.0;
-            let x0 = -1000.0 + w * i as f32;
-            let z0 = -1000.0 + w * j as f32;
+            let x0 = -1000.0 + w * i as Float;
+            let z0 = -1000.0 + w * j as Float;
             let y0 = 0.0;
             let x1 = x0 + w;
-            let y1 = rng.gen_range::<f32>(1.0, 101.0);
+            let y1 = rng.gen_range::<Float>(1.0, 101.0);
             let z1 = z0 + w;
 
-            boxes1.add(Arc::new(Cube::new(glm::vec3(x0, y0, z0), glm::vec3(x1, y1, z1), mat_ground.clone())));
+            boxes1.add(Arc::new(Cube::new(vec3(x0, y0, z0), vec3(x1, y1, z1), mat_ground.clone())));
         }
     }
     scene.add(Arc::new(BVHNode::new_with_list(&boxes1)));
 
 
     // 光源：灯光
-    let mat_light = Arc::new(DiffuseEmit::new_c(glm::vec3(7.0, 7.0, 7.0)));
-    let light =Arc::new(AxisRect::new(glm::vec2(123.0, 147.0), glm::vec2(423.0, 412.0), 554.0, mat_light.clone(), Axis::Y)) ;
+    let mat_light = Arc::new(DiffuseEmit::new_c(vec3(7.0, 7.0, 7.0)));
+    let light =Arc::new(AxisRect::new(vec2(123.0, 147.0), vec2(423.0, 412.0), 554.0, mat_light.clone(), Axis::Y)) ;
     scene.add(Arc::new(FlipFace::new(light.clone())));
 
 
-    // 运动模糊的球体，可惜没有运动
-    let center1 = glm::vec3(400.0, 400.0, 200.0);
-    let mat_sphere = Arc::new(Lambertian::new(glm::vec3(0.7, 0.3, 0.1)));
-    scene.add(Arc::new(Sphere::new(center1, 50.0, mat_sphere.clone())));
+    // 运动模糊的球体，在快门打开的时间内沿 x 方向移动
+    let center1 = vec3(400.0, 400.0, 200.0);
+    let center2 = center1 + vec3(30.0, 0.0, 0.0);
+    let mat_sphere = Arc::new(Lambertian::new(vec3(0.7, 0.3, 0.1)));
+    scene.add(Arc::new(MovingSphere::new(center1, 0.0, center2, 1.0, 50.0, mat_sphere.clone())));
 
     // 玻璃球
-    scene.add(Arc::new(Sphere::new(glm::vec3(260.0, 150.0, 45.0), 50.0,
+    scene.add(Arc::new(Sphere::new(vec3(260.0, 150.0, 45.0), 50.0,
                                    Arc::new(Dielecric::new(1.5)))));
 
     // 金属球
-    scene.add(Arc::new(Sphere::new(glm::vec3(0.0, 150.0, 145.0), 50.0,
-                                   Arc::new(Metal::new(glm::vec3(0.8, 0.8, 0.9), 1.0)))));
+    scene.add(Arc::new(Sphere::new(vec3(0.0, 150.0, 145.0), 50.0,
+                                   Arc::new(Metal::new(vec3(0.8, 0.8, 0.9), 1.0)))));
 
 
     // 玻璃球内部的体积雾
-    let boundary = Arc::new(Sphere::new(glm::vec3(360.0, 150.0, 145.0), 70.0,
+    let boundary = Arc::new(Sphere::new(vec3(360.0, 150.0, 145.0), 70.0,
                                         Arc::new(Dielecric::new(1.5))));
     scene.add(boundary.clone());
-    scene.add(Arc::new(ConstantMedium::new_c(boundary.clone(), 0.2, glm::vec3(0.2, 0.4, 0.9))));
+    scene.add(Arc::new(ConstantMedium::new_c(boundary.clone(), 0.2, vec3(0.2, 0.4, 0.9))));
 
     // 笼罩在整个环境中的体积雾
-    let boundary = Arc::new(Sphere::new(glm::vec3(0.0, 0.0, 0.0), 5000.0,
+    let boundary = Arc::new(Sphere::new(vec3(0.0, 0.0, 0.0), 5000.0,
                                         Arc::new(Dielecric::new(1.5))));
-    scene.add(Arc::new(ConstantMedium::new_c(boundary.clone(), 0.0001, glm::vec3(1.0, 1.0, 1.0))));
+    scene.add(Arc::new(ConstantMedium::new_c(boundary.clone(), 0.0001, vec3(1.0, 1.0, 1.0))));
 
     // 地球
     let earth_mat = Arc::new(Lambertian::new_t(Arc::new(
         ImageTexture::new(&"earthmap.jpg".to_string()))));
-    scene.add(Arc::new(Sphere::new(glm::vec3(400.0, 200.0, 400.0), 100.0, earth_mat.clone())));
+    scene.add(Arc::new(Sphere::new(vec3(400.0, 200.0, 400.0), 100.0, earth_mat.clone())));
 
     // 噪声纹理的球
     let tex_perlin = Arc::new(NoiseTexture::new(0.1));
-    scene.add(Arc::new(Sphere::new(glm::vec3(220.0, 280.0, 300.0), 80.0,
+    scene.add(Arc::new(Sphere::new(vec3(220.0, 280.0, 300.0), 80.0,
                                    Arc::new(Lambertian::new_t(tex_perlin)))));
 
     // 一个立方体，里面塞满了球
     let mut boxes2 = HittableList::default();
-    let mat_white = Arc::new(Lambertian::new(glm::vec3(0.73, 0.73, 0.73)));
+    let mat_white = Arc::new(Lambertian::new(vec3(0.73, 0.73, 0.73)));
     let ns = 1000;
     for _ in 0..ns {
-        boxes2.add(Arc::new(Sphere::new(glm::Vec3::rand(&mut rng) * 165.0, 10.0, mat_white.clone())));
+        boxes2.add(Arc::new(Sphere::new(Vec3::rand(&mut rng) * 165.0, 10.0, mat_white.clone())));
     }
     let boxes2 = BVHNode::new_with_list(&boxes2);
-    let boxes2 = Translate::new(Arc::new(boxes2), glm::vec3(-100.0, 270.0, 395.0));
+    let boxes2 = Translate::new(Arc::new(boxes2), vec3(-100.0, 270.0, 395.0));
     scene.add(Arc::new(boxes2));
 
 
     // 摄像机
-    let camera =
-        Camera::new(glm::vec3(478.0, 278.0, -600.0), glm::vec3(278.0, 278.0, 0.0),
-                    glm::vec3(0., 1., 0.), 40.0, 1.0,
+    let mut camera =
+        Camera::new(vec3(478.0, 278.0, -600.0), vec3(278.0, 278.0, 0.0),
+                    vec3(0., 1., 0.), 40.0, 1.0,
                     0.0, 10.0);
+    camera.set_shutter(0.0, 1.0);
 
-    (Arc::new(scene), camera, Some(light.clone()))
+    let mut scene = Scene::new(Arc::new(scene), camera, Some(light.clone()));
+    scene.background = Background::Color(Vec3::zero());
+    scene.quality = Some((32, 32));
+    scene
 }
\ No newline at end of file
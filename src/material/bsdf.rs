@@ -0,0 +1,223 @@
+use std::ops::BitOr;
+use num::Zero;
+use crate::geom::onb::ONB;
+use crate::hit::HitPayload;
+use crate::float::{Float, Vec3};
+
+
+/// BSDF 的类型，可以通过 `|` 组合
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BsdfFlags(u8);
+
+
+impl BsdfFlags
+{
+    /// 散射方向和观察方向位于表面的同一侧
+    pub const REFLECTION: BsdfFlags = BsdfFlags(1);
+
+    /// 散射方向穿过表面
+    pub const TRANSMISSION: BsdfFlags = BsdfFlags(1 << 1);
+
+    pub const DIFFUSE: BsdfFlags = BsdfFlags(1 << 2);
+    pub const GLOSSY: BsdfFlags = BsdfFlags(1 << 3);
+
+    /// delta 分布，例如理想的镜面反射和折射：散射方向是确定的，只能通过 `sample` 得到
+    pub const SPECULAR: BsdfFlags = BsdfFlags(1 << 4);
+
+    pub fn contains(self, other: BsdfFlags) -> bool { self.0 & other.0 == other.0 }
+    pub fn is_specular(self) -> bool { self.contains(BsdfFlags::SPECULAR) }
+}
+
+
+impl BitOr for BsdfFlags
+{
+    type Output = BsdfFlags;
+
+    fn bitor(self, rhs: BsdfFlags) -> BsdfFlags { BsdfFlags(self.0 | rhs.0) }
+}
+
+
+/// 一次 BSDF 采样的结果
+pub struct BsdfSample
+{
+    /// 散射方向，单位向量
+    pub wi: Vec3,
+
+    /// BSDF 和 |cos(theta_i)| 的乘积，见 `Bxdf::eval`
+    pub f: Vec3,
+
+    /// 散射方向的立体角概率密度；delta 分布时为选中这个方向的概率
+    pub pdf: Float,
+
+    /// 这次采样所属的 lobe 的类型
+    pub flags: BsdfFlags,
+}
+
+
+/// 位于着色法线的局部坐标系中的 BSDF，z 轴是着色法线
+///
+/// wo 和 wi 都是背离交点的单位向量：wo 指向观察者（入射光线的反方向），wi 指向光源（散射光线的方向）
+pub trait Bxdf
+{
+    fn flags(&self) -> BsdfFlags;
+
+
+    /// BSDF 和 |cos(theta_i)| 的乘积，即反射方程 Lo = Le + ∫ f * Li * |cos(theta_i)| dwi 中除了 Li 以外的部分
+    ///
+    /// 余弦项包含在内，这样没有余弦项的相位函数（例如 `Isotropic`）也可以使用相同的接口；delta 分布返回 0
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3;
+
+
+    /// 根据 [0, 1)^3 中均匀分布的随机数 u 采样一个散射方向
+    fn sample(&self, wo: &Vec3, u: &Vec3) -> Option<BsdfSample>;
+
+
+    /// `sample` 得到方向 wi 的立体角概率密度，delta 分布返回 0
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float;
+}
+
+
+/// 交点处的 BSDF，负责世界坐标系和局部坐标系之间的变换，具体的计算交给 `Bxdf`
+///
+/// 着色法线和几何法线不一致时，局部坐标系中的反射方向可能位于几何表面的另一侧。
+/// 这里根据几何法线判断反射还是透射，丢弃和 lobe 类型不符的方向，避免漏光
+pub struct Bsdf
+{
+    frame: ONB,
+
+    /// 几何法线，和观察方向位于表面的同一侧
+    normal: Vec3,
+
+    bxdf: Box<dyn Bxdf>,
+}
+
+
+impl Bsdf
+{
+    pub fn new(hit_payload: &HitPayload, bxdf: Box<dyn Bxdf>) -> Bsdf
+    {
+        Bsdf {
+            frame: ONB::new(*hit_payload.shading_normal()),
+            normal: *hit_payload.normal(),
+            bxdf,
+        }
+    }
+
+
+    pub fn flags(&self) -> BsdfFlags { self.bxdf.flags() }
+
+
+    /// wo 和 wi 是世界坐标系中背离交点的方向，不需要是单位向量
+    pub fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3
+    {
+        if !self.flags().contains(self.side(wo, wi)) {
+            return Vec3::zero();
+        }
+        self.bxdf.eval(&self.to_local(wo), &self.to_local(wi))
+    }
+
+
+    /// 采样得到的 wi 位于世界坐标系
+    pub fn sample(&self, wo: &Vec3, u: &Vec3) -> Option<BsdfSample>
+    {
+        let mut sample = self.bxdf.sample(&self.to_local(wo), u)?;
+        sample.wi = self.frame.local(&sample.wi);
+        if !sample.flags.contains(self.side(wo, &sample.wi)) {
+            return None;
+        }
+        Some(sample)
+    }
+
+
+    pub fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float
+    {
+        if !self.flags().contains(self.side(wo, wi)) {
+            return 0.0;
+        }
+        self.bxdf.pdf(&self.to_local(wo), &self.to_local(wi))
+    }
+
+
+    /// 根据几何法线判断 wi 是反射方向还是透射方向
+    fn side(&self, wo: &Vec3, wi: &Vec3) -> BsdfFlags
+    {
+        if glm::dot(*wo, self.normal) * glm::dot(*wi, self.normal) > 0.0 {
+            BsdfFlags::REFLECTION
+        } else {
+            BsdfFlags::TRANSMISSION
+        }
+    }
+
+
+    fn to_local(&self, v: &Vec3) -> Vec3
+    {
+        glm::normalize(self.frame.to_local(v))
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use std::sync::Arc;
+    use num::traits::FloatConst;
+    use crate::float::{vec2, vec3};
+    use crate::geom::volumn::Isotropic;
+    use crate::material::{Dielecric, Lambertian, Material, Metal};
+    use crate::ray::Ray;
+    use crate::utility::{rand_vec3, random, seed_rng, unit_vec};
+
+    #[test]
+    fn test_bsdf()
+    {
+        seed_rng(1);
+
+        // 光线斜着射向 y = 0 的平面
+        let ray = Ray::new(vec3(0.0, 1.0, 0.0), vec3(0.6, 0.0, 0.3));
+        let wo = -*ray.dir();
+        let hit = |mat: Arc<dyn Material + Send + Sync>| HitPayload::new(&ray, 1.0, vec3(0.0, 1.0, 0.0), mat, vec2(0.0, 0.0));
+
+        let materials: Vec<Arc<dyn Material + Send + Sync>> = vec![
+            Arc::new(Lambertian::new(vec3(0.5, 0.6, 0.7))),
+            Arc::new(Metal::new(vec3(0.8, 0.7, 0.6), 0.3)),
+            Arc::new(Metal::new(vec3(0.8, 0.7, 0.6), 1.0)),
+            Arc::new(Isotropic::new_c(vec3(0.5, 0.5, 0.5))),
+        ];
+        for mat in materials {
+            let payload = hit(mat.clone());
+            let bsdf = mat.bsdf(&ray, &payload).unwrap();
+            assert!(!bsdf.flags().is_specular());
+
+            // 采样的结果和 eval、pdf 一致；低于几何表面的方向被丢弃
+            let n = 100000;
+            let mut accepted = 0;
+            for _ in 0..n {
+                if let Some(sample) = bsdf.sample(&wo, &rand_vec3()) {
+                    accepted += 1;
+                    assert!(sample.pdf > 0.0);
+                    assert!((bsdf.pdf(&wo, &sample.wi) - sample.pdf).abs() <= 1e-3 * sample.pdf);
+                    assert!(glm::length(bsdf.eval(&wo, &sample.wi) - sample.f) <= 1e-3 * glm::length(sample.f));
+                }
+            }
+
+            // pdf 在球面上的积分等于采样被接受的比例
+            let integral = (0..n).map(|_| bsdf.pdf(&wo, &unit_vec(&vec2(random(), random()))))
+                .sum::<Float>() * 4.0 * Float::PI() / n as Float;
+            let accept_rate = accepted as Float / n as Float;
+            assert!((integral - accept_rate).abs() < 0.05, "integral: {}, accept rate: {}", integral, accept_rate);
+        }
+
+        // 理想折射：f / pdf = 1，反射和折射的方向分别位于表面的两侧
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Dielecric::new(1.5));
+        let bsdf = mat.bsdf(&ray, &hit(mat.clone())).unwrap();
+        assert!(bsdf.flags().is_specular());
+        assert_eq!(bsdf.pdf(&wo, &wo), 0.0);
+        for u in [0.0, 0.999] {
+            let sample = bsdf.sample(&wo, &vec3(u, 0.5, 0.5)).unwrap();
+            assert!(glm::length(sample.f / sample.pdf - vec3(1.0, 1.0, 1.0)) < 1e-5);
+            let reflect = sample.flags.contains(BsdfFlags::REFLECTION);
+            assert_eq!(reflect, sample.wi.y > 0.0);
+        }
+    }
+}
@@ -1,19 +1,20 @@
-use crate::material::{Material, Scatter};
+use crate::material::{Bsdf, BsdfFlags, BsdfSample, Bxdf, Material};
 use crate::ray::Ray;
-use num::One;
+use num::{One, Zero};
 use num::pow::Pow;
 use crate::hit::HitPayload;
+use crate::float::{Float, Vec3, vec3};
 
 
 pub struct Dielecric
 {
-    ir: f32,    // 材质的折射系数 index of refraction
+    ir: Float,    // 材质的折射系数 index of refraction
 }
 
 
 impl Dielecric
 {
-    pub fn new(ir: f32) -> Dielecric
+    pub fn new(ir: Float) -> Dielecric
     {
         Dielecric { ir }
     }
@@ -23,48 +24,75 @@ impl Dielecric
 /// snell 折射定律
 /// i 是入射方向，n 是法线，两者都是单位向量
 /// rate 表示两个界面的相对折射率
-fn refract(i: glm::Vec3, n: glm::Vec3, refraction_ratio: f32) -> glm::Vec3
+fn refract(i: Vec3, n: Vec3, refraction_ratio: Float) -> Vec3
 {
-    let cos_theta = f32::min(glm::dot(-i, n), 1.0);
+    let cos_theta = Float::min(glm::dot(-i, n), 1.0);
 
     let r_out_perp = (i + n * cos_theta) * refraction_ratio;
-    let r_out_parallel = -n * f32::sqrt(f32::abs(1.0 - glm::dot(r_out_perp, r_out_perp)));
+    let r_out_parallel = -n * Float::sqrt(Float::abs(1.0 - glm::dot(r_out_perp, r_out_perp)));
 
     r_out_perp + r_out_parallel
 }
 
 
 /// 使用 Schlick 近似来计算菲涅尔方程，得到反射能量占比
-fn reflectance(cos_theta: f32, ref_idx: f32) -> f32
+fn reflectance(cos_theta: Float, ref_idx: Float) -> Float
 {
     let r0 = (1. - ref_idx) / (1. + ref_idx);
     let r0 = r0 * r0;
-    r0 + (1. - r0) * f32::pow(1. - cos_theta, 5_u8)
+    r0 + (1. - r0) * Float::pow(1. - cos_theta, 5_u8)
 }
 
 
 impl Material for Dielecric
 {
-    fn scatter(&self, ray_in: &Ray, hit_payload: &HitPayload) -> Option<Scatter> {
+    fn bsdf(&self, _: &Ray, hit_payload: &HitPayload) -> Option<Bsdf> {
         let refraction_ratio = if hit_payload.front_face() { 1.0 / self.ir } else { self.ir };
+        Some(Bsdf::new(hit_payload, Box::new(DielecricBxdf { refraction_ratio })))
+    }
+}
+
+
+/// 理想的折射和反射，根据反射能量占比随机选择其中一个方向，两个方向都是 delta 分布
+struct DielecricBxdf
+{
+    refraction_ratio: Float,
+}
+
+
+impl Bxdf for DielecricBxdf
+{
+    fn flags(&self) -> BsdfFlags { BsdfFlags::REFLECTION | BsdfFlags::TRANSMISSION | BsdfFlags::SPECULAR }
+
+
+    fn eval(&self, _wo: &Vec3, _wi: &Vec3) -> Vec3 { Vec3::zero() }
+
 
-        let cos_theta = f32::min(glm::dot(-*ray_in.dir(), *hit_payload.normal()), 1.0);
-        let sin_theta = f32::sqrt(1.0 - cos_theta * cos_theta);
-
-        let scatter_dir =
-            // 从 snell 和 fresnell 两个角度来判断是否发生全反射
-            if refraction_ratio * sin_theta > 1.0 || reflectance(cos_theta, refraction_ratio) > rand::random() {
-                // 全反射
-                glm::reflect(*ray_in.dir(), *hit_payload.normal())
-            } else {
-                // 既有折射，又有反射
-                refract(*ray_in.dir(), *hit_payload.normal(), refraction_ratio)
-            };
-            
-        Some(Scatter{
-            diffuse_pdf: None,
-            specular_ray: Some(Ray::new_d(*hit_payload.hit_point(), scatter_dir)),
-            attenuation: glm::Vec3::one(),
-        })
+    /// 选择反射的概率就是反射能量占比，因此 f / pdf = 1
+    fn sample(&self, wo: &Vec3, u: &Vec3) -> Option<BsdfSample> {
+        let cos_theta = Float::min(wo.z, 1.0);
+        let sin_theta = Float::sqrt(1.0 - cos_theta * cos_theta);
+
+        // 从 snell 和 fresnell 两个角度来判断是否发生全反射
+        let reflect_ratio = if self.refraction_ratio * sin_theta > 1.0 { 1.0 } else { reflectance(cos_theta, self.refraction_ratio) };
+
+        if u.x < reflect_ratio {
+            Some(BsdfSample {
+                wi: vec3(-wo.x, -wo.y, wo.z),
+                f: Vec3::one() * reflect_ratio,
+                pdf: reflect_ratio,
+                flags: BsdfFlags::REFLECTION | BsdfFlags::SPECULAR,
+            })
+        } else {
+            Some(BsdfSample {
+                wi: refract(-*wo, vec3(0.0, 0.0, 1.0), self.refraction_ratio),
+                f: Vec3::one() * (1.0 - reflect_ratio),
+                pdf: 1.0 - reflect_ratio,
+                flags: BsdfFlags::TRANSMISSION | BsdfFlags::SPECULAR,
+            })
+        }
     }
+
+
+    fn pdf(&self, _wo: &Vec3, _wi: &Vec3) -> Float { 0.0 }
 }
@@ -1,9 +1,10 @@
 use std::sync::Arc;
 use num::Zero;
 use crate::hit::HitPayload;
-use crate::material::{Material, Scatter};
+use crate::material::{Bsdf, Material};
 use crate::ray::Ray;
 use crate::texture::{SolidColor, Texture};
+use crate::float::Vec3;
 
 
 pub struct DiffuseEmit
@@ -19,7 +20,7 @@ impl DiffuseEmit
         DiffuseEmit { emit }
     }
 
-    pub fn new_c(color: glm::Vec3) -> DiffuseEmit
+    pub fn new_c(color: Vec3) -> DiffuseEmit
     {
         DiffuseEmit {
             emit: Arc::new(SolidColor::new(color)),
@@ -30,16 +31,16 @@ impl DiffuseEmit
 
 impl Material for DiffuseEmit {
     /// 发光材料，因此不反射或者折射光线
-    fn scatter(&self, _ray_in: &Ray, _hit_payload: &HitPayload) -> Option<Scatter> {
+    fn bsdf(&self, _ray_in: &Ray, _hit_payload: &HitPayload) -> Option<Bsdf> {
         None
     }
 
 
-    fn emit(&self, _ray_in: &Ray, _payload: &HitPayload) -> glm::Vec3 {
+    fn emit(&self, _ray_in: &Ray, _payload: &HitPayload) -> Vec3 {
         if _payload.front_face() {
-            self.emit.sample(_payload.uv(), _payload.hit_point())
+            self.emit.sample_hit(_payload)
         } else {
-            glm::Vec3::zero()
+            Vec3::zero()
         }
     }
 }
@@ -1,13 +1,15 @@
 use std::sync::Arc;
 use num::traits::FloatConst;
+use num::Zero;
 
 use crate::hit::HitPayload;
 
-use crate::material::{Material, Scatter};
-use crate::pdf::CosPDF;
+use crate::material::{Bsdf, BsdfFlags, BsdfSample, Bxdf, Material};
 use crate::ray::Ray;
 
 use crate::texture::{SolidColor, Texture};
+use crate::utility::cos_dir;
+use crate::float::{Float, Vec3, vec2};
 
 /// Lambert 材质，fr = albedo / pi
 pub struct Lambertian
@@ -18,7 +20,7 @@ pub struct Lambertian
 
 impl Lambertian
 {
-    pub fn new(albedo: glm::Vec3) -> Lambertian
+    pub fn new(albedo: Vec3) -> Lambertian
     {
         debug_assert!(albedo.x >= 0.0 && albedo.y >= 0.0 && albedo.z >= 0.0);
 
@@ -35,21 +37,56 @@ impl Lambertian
 
 impl Material for Lambertian
 {
-    fn scatter(&self, _: &Ray, hit_payload: &HitPayload) -> Option<Scatter>
+    fn bsdf(&self, _: &Ray, hit_payload: &HitPayload) -> Option<Bsdf>
     {
-        let pdf = CosPDF::new(*hit_payload.normal());
+        Some(Bsdf::new(hit_payload, Box::new(LambertianBxdf { albedo: self.albedo.sample_hit(hit_payload) })))
+    }
+}
+
+
+/// 交点处的 Lambert 反射，只在观察方向所在的半球内散射
+struct LambertianBxdf
+{
+    albedo: Vec3,
+}
+
+
+impl Bxdf for LambertianBxdf
+{
+    fn flags(&self) -> BsdfFlags { BsdfFlags::REFLECTION | BsdfFlags::DIFFUSE }
 
-        Some(Scatter {
-            diffuse_pdf: Some(Box::new(pdf)),
-            attenuation: self.albedo.sample(hit_payload.uv(), hit_payload.hit_point()),
-            specular_ray: None,
-        })
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3
+    {
+        if wo.z * wi.z <= 0.0 {
+            return Vec3::zero();
+        }
+        self.albedo * (wi.z.abs() / Float::PI())
+    }
+
+
+    /// 按照 cos(theta) 采样，使得 f / pdf = albedo
+    fn sample(&self, wo: &Vec3, u: &Vec3) -> Option<BsdfSample>
+    {
+        let mut wi = cos_dir(&vec2(u.y, u.z));
+        if wo.z < 0.0 {
+            wi.z = -wi.z;
+        }
+
+        let pdf = self.pdf(wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(BsdfSample { wi, f: self.eval(wo, &wi), pdf, flags: self.flags() })
     }
 
 
-    /// 根据另一种形式的反射方程，朝某个方向散射的 pdf = cos(theta) / pi
-    fn scatter_pdf(&self, _ray_in: &Ray, _hit_payload: &HitPayload, _ray_out: &Ray) -> f32 {
-        f32::max(0.0,
-                 glm::dot(*_hit_payload.normal(), *_ray_out.dir()) / f32::PI())
+    /// 朝某个方向散射的 pdf = cos(theta) / pi
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float
+    {
+        if wo.z * wi.z <= 0.0 {
+            return 0.0;
+        }
+        wi.z.abs() / Float::PI()
     }
 }
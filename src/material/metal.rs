@@ -1,20 +1,22 @@
+use num::traits::FloatConst;
 use crate::hit::HitPayload;
-use crate::material::{Material, Scatter};
+use crate::material::{Bsdf, BsdfFlags, BsdfSample, Bxdf, Material};
 use crate::ray::Ray;
-use crate::utility::rand_in_unit_sphere;
+use crate::utility::in_unit_sphere;
+use crate::float::{Float, Vec3, vec3};
 
 
 /// 金属材质，并不是基于物理的
 pub struct Metal
 {
-    albedo: glm::Vec3,
-    fuzz: f32,
+    albedo: Vec3,
+    fuzz: Float,
 }
 
 
 impl Metal
 {
-    pub fn new(albedo: glm::Vec3, fuzz: f32) -> Metal
+    pub fn new(albedo: Vec3, fuzz: Float) -> Metal
     {
         Metal {
             albedo,
@@ -26,21 +28,88 @@ impl Metal
 
 impl Material for Metal
 {
-    fn scatter(&self, ray_in: &Ray, hit_payload: &HitPayload) -> Option<Scatter>
+    fn bsdf(&self, _: &Ray, hit_payload: &HitPayload) -> Option<Bsdf>
     {
-        let reflect_dir = glm::reflect(*ray_in.dir(), *hit_payload.normal());
+        Some(Bsdf::new(hit_payload, Box::new(MetalBxdf { albedo: self.albedo, fuzz: self.fuzz })))
+    }
+}
+
 
-        let specular_ray = Ray::new_d(*hit_payload.hit_point(),
-                                      glm::normalize(reflect_dir + rand_in_unit_sphere() * self.fuzz));
+/// 在理想反射方向 r 上加上半径为 fuzz 的球内的随机偏移，fuzz 为 0 时就是理想的镜面反射
+///
+/// 散射方向等价于从交点看向球内均匀分布的一点，球心为 r。沿着 wi 的射线穿过球的区间为 [t1, t2]，
+/// 因此立体角的概率密度为 (t2^3 - t1^3) / (4 pi fuzz^3)。f 等于 albedo * pdf，使得 f / pdf = albedo
+struct MetalBxdf
+{
+    albedo: Vec3,
+    fuzz: Float,
+}
+
+
+impl MetalBxdf
+{
+    fn reflect(wo: &Vec3) -> Vec3 { vec3(-wo.x, -wo.y, wo.z) }
+}
 
-        if glm::dot(*specular_ray.dir(), *hit_payload.normal()) <= 0.0 {
+
+impl Bxdf for MetalBxdf
+{
+    fn flags(&self) -> BsdfFlags
+    {
+        if self.fuzz > 0.0 {
+            BsdfFlags::REFLECTION | BsdfFlags::GLOSSY
+        } else {
+            BsdfFlags::REFLECTION | BsdfFlags::SPECULAR
+        }
+    }
+
+
+    fn eval(&self, wo: &Vec3, wi: &Vec3) -> Vec3
+    {
+        self.albedo * self.pdf(wo, wi)
+    }
+
+
+    fn sample(&self, wo: &Vec3, u: &Vec3) -> Option<BsdfSample>
+    {
+        let r = Self::reflect(wo);
+        if self.fuzz <= 0.0 {
+            return Some(BsdfSample { wi: r, f: self.albedo, pdf: 1.0, flags: self.flags() });
+        }
+
+        let dir = r + in_unit_sphere(u) * self.fuzz;
+        let len = glm::length(dir);
+        if len <= 0.0 {
+            return None;
+        }
+
+        let wi = dir / len;
+        let pdf = self.pdf(wo, &wi);
+        if pdf <= 0.0 {
             return None;
         }
+        Some(BsdfSample { wi, f: self.albedo * pdf, pdf, flags: self.flags() })
+    }
+
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3) -> Float
+    {
+        if self.fuzz <= 0.0 {
+            return 0.0;
+        }
+
+        // 射线和球求交，|h|^2 是球心到射线的距离的平方
+        let r = Self::reflect(wo);
+        let b = glm::dot(*wi, r);
+        let h = glm::cross(r, *wi);
+        let discriminant = self.fuzz * self.fuzz - glm::dot(h, h);
+        if b <= 0.0 || discriminant <= 0.0 {
+            return 0.0;
+        }
 
-        Some(Scatter {
-            specular_ray: Some(specular_ray),
-            diffuse_pdf: None,
-            attenuation: self.albedo,
-        })
+        // fuzz 不超过 1，交点位于球外或者球面上，t1 只可能因为舍入误差略小于 0
+        let half = discriminant.sqrt();
+        let (t1, t2) = (Float::max(0.0, b - half), b + half);
+        (t2 - t1) * (t2 * t2 + t1 * t2 + t1 * t1) / (4.0 * Float::PI() * self.fuzz.powi(3))
     }
 }
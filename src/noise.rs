@@ -1,6 +1,8 @@
 use num::{One, Zero};
 use rand::{Rng, Rand};
 use crate::texture::Texture;
+use crate::utility::rng;
+use crate::float::{Float, Vec2, Vec3, vec3};
 
 
 const PERLIN_POINT: usize = 256;
@@ -12,7 +14,7 @@ pub struct Perlin
     perm_y: [i32; PERLIN_POINT],
     perm_z: [i32; PERLIN_POINT],
 
-    ranvec: [glm::Vec3; PERLIN_POINT],
+    ranvec: [Vec3; PERLIN_POINT],
 }
 
 
@@ -20,10 +22,10 @@ impl Perlin
 {
     pub fn new() -> Self
     {
-        let mut rng = rand::thread_rng();
-        let mut ranvec = [glm::Vec3::zero(); PERLIN_POINT];
+        let mut rng = rng();
+        let mut ranvec = [Vec3::zero(); PERLIN_POINT];
         for v in &mut ranvec {
-            *v = glm::normalize(glm::Vec3::rand(&mut rng) * 2.0 - 1.0);
+            *v = glm::normalize(Vec3::rand(&mut rng) * 2.0 - 1.0);
         }
 
         Perlin {
@@ -48,7 +50,7 @@ impl Perlin
     /// permute: vt. 变换
     fn permute(mut p: [i32; PERLIN_POINT], n: i32) -> [i32; PERLIN_POINT]
     {
-        let mut rng = rand::thread_rng();
+        let mut rng = rng();
         for i in (1..n as usize).rev() {
             let target = rng.gen_range(0, i + 1);
             let tmp = p[i];
@@ -59,19 +61,19 @@ impl Perlin
     }
 
 
-    pub fn noise(&self, p: &glm::Vec3) -> f32
+    pub fn noise(&self, p: &Vec3) -> Float
     {
         // 确保 uvw 的范围是 [0, 1] 用作插值系数
-        let u = p.x - f32::floor(p.x);
-        let v = p.y - f32::floor(p.y);
-        let w = p.z - f32::floor(p.z);
+        let u = p.x - Float::floor(p.x);
+        let v = p.y - Float::floor(p.y);
+        let w = p.z - Float::floor(p.z);
 
         // 使用 i32，可以保留负数。可以确保按位与的结果是正数
-        let i = f32::floor(p.x) as i32;
-        let j = f32::floor(p.y) as i32;
-        let k = f32::floor(p.z) as i32;
+        let i = Float::floor(p.x) as i32;
+        let j = Float::floor(p.y) as i32;
+        let k = Float::floor(p.z) as i32;
 
-        let mut c = [[[glm::Vec3::zero(); 2]; 2]; 2];
+        let mut c = [[[Vec3::zero(); 2]; 2]; 2];
         for di in 0..2 {
             for dj in 0..2 {
                 for dk in 0..2 {
@@ -89,7 +91,7 @@ impl Perlin
 
 
     /// 三线性插值，c 是锚点，uvw 是插值系数
-    fn trilinear_interp(c: [[[glm::Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32
+    fn trilinear_interp(c: [[[Vec3; 2]; 2]; 2], u: Float, v: Float, w: Float) -> Float
     {
         // 使用 Hermit 对插值系数进行平滑处理
         let uu = u * u * (3.0 - 2.0 * u);
@@ -97,15 +99,15 @@ impl Perlin
         let ww = w * w * (3.0 - 2.0 * w);
 
 
-        let mut accum = 0.0_f32;
+        let mut accum: Float = 0.0;
 
         for i in 0..2 {
             for j in 0..2 {
                 for k in 0..2 {
-                    let weight_v = glm::vec3(u - i as f32, v - j as f32, w - k as f32);
-                    accum += (i as f32 * uu + (1.0 - i as f32) * (1.0 - uu))
-                        * (j as f32 * vv + (1.0 - j as f32) * (1.0 - vv))
-                        * (k as f32 * ww + (1.0 - k as f32) * (1.0 - ww))
+                    let weight_v = vec3(u - i as Float, v - j as Float, w - k as Float);
+                    accum += (i as Float * uu + (1.0 - i as Float) * (1.0 - uu))
+                        * (j as Float * vv + (1.0 - j as Float) * (1.0 - vv))
+                        * (k as Float * ww + (1.0 - k as Float) * (1.0 - ww))
                         * glm::dot(c[i][j][k], weight_v);
                 }
             }
@@ -116,14 +118,14 @@ impl Perlin
 
 
     /// 湍流：将多个噪声复合
-    pub fn turb(&self, p: &glm::Vec3, depth: Option<i32>) -> f32
+    pub fn turb(&self, p: &Vec3, depth: Option<i32>) -> Float
     {
         // depth 的默认值是 7
         let depth = depth.unwrap_or(7);
 
-        let mut accum = 0.0_f32;
+        let mut accum: Float = 0.0;
         let mut temp_p = *p;
-        let mut weight = 1.0_f32;
+        let mut weight: Float = 1.0;
 
         for _ in 0..depth {
             accum += weight * self.noise(&temp_p);
@@ -131,7 +133,7 @@ impl Perlin
             temp_p = temp_p * 2.0;
         }
 
-        f32::abs(accum)
+        Float::abs(accum)
     }
 }
 
@@ -139,27 +141,27 @@ impl Perlin
 pub struct NoiseTexture
 {
     noise: Perlin,
-    scale: f32, // 可以改变噪声的频率
+    scale: Float, // 可以改变噪声的频率
 }
 
 
 impl NoiseTexture
 {
-    pub fn new(scale: f32) -> NoiseTexture { NoiseTexture { noise: Perlin::new(), scale } }
+    pub fn new(scale: Float) -> NoiseTexture { NoiseTexture { noise: Perlin::new(), scale } }
 }
 
 
 impl Texture for NoiseTexture
 {
-    fn sample(&self, _uv: &glm::Vec2, p: &glm::Vec3) -> glm::Vec3 {
+    fn sample(&self, _uv: &Vec2, p: &Vec3) -> Vec3 {
         // 模糊的 Perlin 噪声
-        // glm::Vec3::one() * 0.5 * (self.noise.noise(&(*p * self.scale)) + 1.0)
+        // Vec3::one() * 0.5 * (self.noise.noise(&(*p * self.scale)) + 1.0)
 
         // 网状的纹理
-        // glm::Vec3::one() * self.noise.turb(&(*p * self.scale), None)
+        // Vec3::one() * self.noise.turb(&(*p * self.scale), None)
 
         // 大理石纹理
-        glm::Vec3::one() * 0.5 * (1.0 + f32::sin(self.scale * p.z + 10.0 * self.noise.turb(p, None)))
+        Vec3::one() * 0.5 * (1.0 + Float::sin(self.scale * p.z + 10.0 * self.noise.turb(p, None)))
     }
 }
 
@@ -173,7 +175,7 @@ mod test {
     fn t1() {
         let perlin = NoiseTexture::new(2.0);
 
-        println!("{:?}", perlin.sample(&glm::Vec2::zero(), &glm::vec3(-50.0, -50.0, -50.0)));
-        println!("{:?}", perlin.sample(&glm::Vec2::zero(), &glm::vec3(-51.0, -51.0, -51.0)));
+        println!("{:?}", perlin.sample(&Vec2::zero(), &vec3(-50.0, -50.0, -50.0)));
+        println!("{:?}", perlin.sample(&Vec2::zero(), &vec3(-51.0, -51.0, -51.0)));
     }
 }
\ No newline at end of file
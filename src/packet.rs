@@ -0,0 +1,193 @@
+use crate::geom::aabb::AABB;
+use crate::hit::HitPayload;
+use crate::ray::Ray;
+use crate::float::Float;
+use crate::simd::{FloatX4, LANES, MaskX4};
+
+
+/// 一个光线包中光线的数量，每条光线占用 `FloatX4` 的一个 lane
+pub const PACKET_SIZE: usize = LANES;
+
+
+/// 光线包：一起求交的一组光线，例如相邻 2x2 个像素的摄像机光线，它们的起点和方向都非常接近
+///
+/// 光线的各个分量按照 SoA（structure of arrays）的方式存放在 `FloatX4` 中，包围盒、球和三角形的求交
+/// 对 4 个 lane 执行相同的标量运算，通过 `MaskX4` 选择结果，依靠编译器的自动向量化生成 SIMD 指令。
+/// 光线数量不足 `PACKET_SIZE` 时，多余的 lane 不参与求交
+pub struct RayPacket
+{
+    rays: Vec<Ray>,
+
+    /// orig[axis][lane]
+    orig: [FloatX4; 3],
+    dir: [FloatX4; 3],
+    inv_dir: [FloatX4; 3],
+
+    /// watertight 三角形求交中，每条光线的坐标轴重排 (kx, ky, kz)：permute[i] 是 (ki == 0, ki == 1)
+    permute: [[MaskX4; 2]; 3],
+
+    /// 剪切变换的系数 (sx, sy, sz)
+    shear: [FloatX4; 3],
+}
+
+
+impl RayPacket
+{
+    /// rays 的数量需要在 [1, PACKET_SIZE] 范围内
+    pub fn new(rays: Vec<Ray>) -> RayPacket
+    {
+        debug_assert!(!rays.is_empty() && rays.len() <= PACKET_SIZE);
+
+        let mut packet = RayPacket {
+            rays,
+            orig: [FloatX4::splat(0.0); 3],
+            dir: [FloatX4::splat(0.0); 3],
+            inv_dir: [FloatX4::splat(0.0); 3],
+            permute: [[MaskX4::splat(false); 2]; 3],
+            shear: [FloatX4::splat(0.0); 3],
+        };
+
+        // 无效的 lane 使用最后一条光线填充，保证计算过程中不会出现 NaN
+        for lane in 0..PACKET_SIZE {
+            let ray = &packet.rays[lane.min(packet.rays.len() - 1)];
+            let (orig, dir) = (*ray.orig(), *ray.dir());
+
+            // 选择光线方向中绝对值最大的分量作为 z 轴，和 `intersect_triangle` 相同
+            let kz = if dir.x.abs() > dir.y.abs() {
+                if dir.x.abs() > dir.z.abs() { 0 } else { 2 }
+            } else if dir.y.abs() > dir.z.abs() { 1 } else { 2 };
+            let kx = (kz + 1) % 3;
+            let ky = (kx + 1) % 3;
+
+            for axis in 0..3 {
+                packet.orig[axis].0[lane] = orig[axis];
+                packet.dir[axis].0[lane] = dir[axis];
+                packet.inv_dir[axis].0[lane] = 1.0 / dir[axis];
+            }
+            for (masks, k) in packet.permute.iter_mut().zip([kx, ky, kz]) {
+                masks[0].0[lane] = k == 0;
+                masks[1].0[lane] = k == 1;
+            }
+            packet.shear[0].0[lane] = -dir[kx] / dir[kz];
+            packet.shear[1].0[lane] = -dir[ky] / dir[kz];
+            packet.shear[2].0[lane] = 1.0 / dir[kz];
+        }
+
+        packet
+    }
+
+
+    /// 有效的光线数量
+    pub fn len(&self) -> usize { self.rays.len() }
+    pub fn is_empty(&self) -> bool { self.rays.is_empty() }
+
+    pub fn ray(&self, lane: usize) -> &Ray { &self.rays[lane] }
+    pub fn rays(&self) -> &[Ray] { &self.rays }
+
+    pub fn orig(&self) -> &[FloatX4; 3] { &self.orig }
+    pub fn dir(&self) -> &[FloatX4; 3] { &self.dir }
+    pub(crate) fn shear(&self) -> &[FloatX4; 3] { &self.shear }
+
+
+    /// 按照每条光线各自的 (kx, ky, kz) 重排向量 v 的坐标轴
+    #[inline(always)]
+    pub(crate) fn permute(&self, v: &[FloatX4; 3]) -> [FloatX4; 3]
+    {
+        self.permute.map(|[is_x, is_y]| is_x.select(v[0], is_y.select(v[1], v[2])))
+    }
+
+
+    /// 每条光线当前的 t 的上界：已经有交点时为交点的 t，否则为 t_max；无效的 lane 为负无穷，不会和任何物体相交
+    pub fn t_max(&self, hits: &[Option<HitPayload>; PACKET_SIZE], t_max: Float) -> FloatX4
+    {
+        let mut res = FloatX4::splat(Float::NEG_INFINITY);
+        for (lane, hit) in hits.iter().enumerate().take(self.len()) {
+            res.0[lane] = hit.as_ref().map_or(t_max, |payload| payload.t());
+        }
+        res
+    }
+
+
+    /// 是否有任意一条光线在 (t_min, t_max[lane]) 范围内和 AABB 相交，用于遍历 BVH
+    ///
+    /// 光线的起点恰好位于 AABB 的面上并且和该面平行时会出现 NaN，这里会将其判定为相交，结果是保守的
+    pub fn hit_aabb(&self, aabb: &AABB, t_min: Float, t_max: FloatX4) -> bool
+    {
+        let mut near = FloatX4::splat(t_min);
+        let mut far = t_max;
+        for axis in 0..3 {
+            let t0 = (FloatX4::splat(aabb.min()[axis]) - self.orig[axis]) * self.inv_dir[axis];
+            let t1 = (FloatX4::splat(aabb.max()[axis]) - self.orig[axis]) * self.inv_dir[axis];
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+
+        near.lt(far).any()
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::float::{Mat4, Vec3, vec3};
+    use num::One;
+    use std::sync::Arc;
+    use crate::geom::bvh::BVHNode;
+    use crate::geom::instance::Instance;
+    use crate::geom::tlas::Tlas;
+    use crate::geom::transform::Transform;
+    use crate::geom::mesh::TriangleMesh;
+    use crate::geom::Sphere;
+    use crate::hit::Hittable;
+    use crate::material::{Lambertian, Material};
+    use crate::utility::{rand_unit_vec, random};
+
+    #[test]
+    fn test_packet()
+    {
+        // 球和网格三角形混合的场景
+        let mat: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)));
+        let mut objects: Vec<Arc<dyn Hittable + Send + Sync>> = (0..200).map(|_| {
+            let center = vec3(random::<Float>(), random::<Float>(), random::<Float>()) * 10.0 - vec3(5.0, 5.0, 5.0);
+            Arc::new(Sphere::new(center, 0.3, mat.clone())) as Arc<dyn Hittable + Send + Sync>
+        }).collect();
+        let positions: Vec<Vec3> = (0..300).map(|_| vec3(random::<Float>(), random::<Float>(), random::<Float>()) * 10.0 - vec3(5.0, 5.0, 5.0)).collect();
+        let indices: Vec<[u32; 3]> = (0..100).map(|i| [3 * i, 3 * i + 1, 3 * i + 2]).collect();
+        objects.extend(Arc::new(TriangleMesh::new(positions, indices, mat.clone())).triangles());
+        let bvh: Arc<dyn Hittable + Send + Sync> = Arc::new(BVHNode::new_sah(&objects, 4));
+
+        // 非均匀缩放的变换，以及实例组成的 TLAS，物体空间中每条光线的 t 的缩放比例都不同
+        let matrix = glm::ext::scale(&glm::ext::rotate(&Mat4::one(), 0.7, vec3(1.0, 2.0, 3.0)), vec3(0.5, 1.0, 1.5));
+        let transform = Transform::new(bvh.clone(), matrix);
+        let red: Arc<dyn Material + Send + Sync> = Arc::new(Lambertian::new(vec3(0.8, 0.1, 0.1)));
+        let tlas = Tlas::new(vec![
+            Instance::new(bvh.clone(), matrix, None),
+            Instance::new(bvh.clone(), glm::ext::translate(&Mat4::one(), vec3(1.0, 0.5, 0.0)), Some(red)),
+        ]);
+        let scenes: [&dyn Hittable; 3] = [bvh.as_ref(), &transform, &tlas];
+
+        // 光线包的结果和逐条光线求交的结果完全相同
+        for n in 0..300 {
+            let orig = rand_unit_vec() * 8.0;
+            let target = rand_unit_vec() * 2.0;
+            let targets: Vec<Vec3> = (0..n % PACKET_SIZE + 1).map(|_| target + rand_unit_vec() * 0.5).collect();
+            let rays = || -> Vec<Ray> { targets.iter().map(|target| Ray::new(orig, *target)).collect() };
+            let t_range = if n % 2 == 0 { (0.0, Float::INFINITY) } else { (random::<Float>() * 8.0, 8.0 + random::<Float>() * 4.0) };
+
+            for scene in scenes {
+                // 比较 t 以及材质，实例可以覆盖原型的材质
+                let key = |payload: &HitPayload| (payload.t(), Arc::as_ptr(payload.material()) as *const ());
+                let expected: Vec<Option<(Float, *const ())>> = rays().iter().map(|ray| scene.hit(ray, t_range).map(|payload| key(&payload))).collect();
+
+                let packet = RayPacket::new(rays());
+                let mut hits = Default::default();
+                scene.hit_packet(&packet, t_range, &mut hits);
+                for lane in 0..PACKET_SIZE {
+                    assert_eq!(hits[lane].as_ref().map(key), expected.get(lane).copied().flatten());
+                }
+            }
+        }
+    }
+}
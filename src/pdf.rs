@@ -2,19 +2,21 @@ use glm::ext::Consts;
 use num::traits::FloatConst;
 use crate::geom::onb::ONB;
 use crate::hit::Hittable;
+use crate::material::Bsdf;
 use crate::ray::Ray;
-use crate::utility::{rand_cos_dir, rand_unit_vec};
+use crate::utility::{rand_cos_dir, rand_unit_vec, rand_vec3, random};
+use crate::float::{Float, Vec3};
 
 
 /// 封装一种随机采样的策略
 pub trait PDF
 {
     /// 根据采样的方法，随机变量 dir 的概率密度是多少
-    fn value(&self, dir: &glm::Vec3) -> f32;
+    fn value(&self, dir: &Vec3) -> Float;
 
 
     /// 根据采样策略，生成一个随机变量 dir
-    fn generate(&self) -> Option<(glm::Vec3, f32)>;
+    fn generate(&self) -> Option<(Vec3, Float)>;
 }
 
 
@@ -27,7 +29,7 @@ pub struct CosPDF
 
 impl CosPDF
 {
-    pub fn new(n: glm::Vec3) -> CosPDF
+    pub fn new(n: Vec3) -> CosPDF
     {
         CosPDF { uvw: ONB::new(n) }
     }
@@ -36,18 +38,18 @@ impl CosPDF
 
 impl PDF for CosPDF
 {
-    fn value(&self, dir: &glm::Vec3) -> f32 {
+    fn value(&self, dir: &Vec3) -> Float {
         let cosine = glm::dot(glm::normalize(*dir), *self.uvw.w());
         debug_assert!(cosine.is_finite());
-        f32::max(0.0, cosine / f32::PI())
+        Float::max(0.0, cosine / Float::PI())
     }
 
-    fn generate(&self) -> Option<(glm::Vec3, f32)> {
+    fn generate(&self) -> Option<(Vec3, Float)> {
         loop {
             let dir = self.uvw.local(&rand_cos_dir());
             let cosine = glm::dot(dir, *self.uvw.w());
             if cosine > 0.0 {
-                return Some((dir, cosine / f32::PI()));
+                return Some((dir, cosine / Float::PI()));
             }
         }
     }
@@ -58,13 +60,13 @@ impl PDF for CosPDF
 pub struct HittablePDF<'a>
 {
     obj: &'a dyn Hittable,
-    p: glm::Vec3,
+    p: Vec3,
 }
 
 
 impl<'a> HittablePDF<'a>
 {
-    pub fn new(obj: &'a dyn Hittable, p: glm::Vec3) -> HittablePDF<'a>
+    pub fn new(obj: &'a dyn Hittable, p: Vec3) -> HittablePDF<'a>
     {
         HittablePDF { obj, p }
     }
@@ -73,22 +75,51 @@ impl<'a> HittablePDF<'a>
 
 impl<'a> PDF for HittablePDF<'a>
 {
-    fn value(&self, dir: &glm::Vec3) -> f32 {
+    fn value(&self, dir: &Vec3) -> Float {
         let ray = Ray::new(self.p, self.p + *dir);
         self.obj.pdf(&ray)
     }
 
-    fn generate(&self) -> Option<(glm::Vec3, f32)> {
+    fn generate(&self) -> Option<(Vec3, Float)> {
         self.obj.rand_dir(&self.p)
     }
 }
 
 
+/// 按照交点处的 BSDF 进行重要性采样，wo 是背离交点的观察方向
+pub struct BsdfPDF<'a>
+{
+    bsdf: &'a Bsdf,
+    wo: Vec3,
+}
+
+
+impl<'a> BsdfPDF<'a>
+{
+    pub fn new(bsdf: &'a Bsdf, wo: Vec3) -> BsdfPDF<'a>
+    {
+        BsdfPDF { bsdf, wo }
+    }
+}
+
+
+impl<'a> PDF for BsdfPDF<'a>
+{
+    fn value(&self, dir: &Vec3) -> Float {
+        self.bsdf.pdf(&self.wo, dir)
+    }
+
+    fn generate(&self) -> Option<(Vec3, Float)> {
+        self.bsdf.sample(&self.wo, &rand_vec3()).map(|sample| (sample.wi, sample.pdf))
+    }
+}
+
+
 /// 混合两个 pdf
 pub struct MixPDF<'a>
 {
     // 混合系数，0 表示完全采用 pdf-a，1 表示完全采用 pdf-b
-    t: f32,
+    t: Float,
     pdf_a: &'a dyn PDF,
     pdf_b: &'a dyn PDF,
 }
@@ -96,7 +127,7 @@ pub struct MixPDF<'a>
 
 impl<'a> MixPDF<'a>
 {
-    pub fn new(pdf_a: &'a dyn PDF, pdf_b: &'a dyn PDF, t: f32) -> MixPDF<'a>
+    pub fn new(pdf_a: &'a dyn PDF, pdf_b: &'a dyn PDF, t: Float) -> MixPDF<'a>
     {
         debug_assert!(t >= 0.0 && t <= 1.0);
 
@@ -107,12 +138,12 @@ impl<'a> MixPDF<'a>
 
 impl<'a> PDF for MixPDF<'a>
 {
-    fn value(&self, dir: &glm::Vec3) -> f32 {
+    fn value(&self, dir: &Vec3) -> Float {
         self.pdf_a.value(dir) * self.t + self.pdf_b.value(dir) * (1.0 - self.t)
     }
 
-    fn generate(&self) -> Option<(glm::Vec3, f32)> {
-        if rand::random::<f32>() >= self.t {
+    fn generate(&self) -> Option<(Vec3, Float)> {
+        if random::<Float>() >= self.t {
             self.pdf_a.generate().and_then(|(dir, pdf)| {
                 let mix_pdf = (1.0 - self.t) * pdf + self.t * self.pdf_b.value(&dir);
                 Some((dir, mix_pdf))
@@ -131,11 +162,11 @@ pub struct RandSpherePDF;
 
 impl PDF for RandSpherePDF
 {
-    fn value(&self, _dir: &glm::Vec3) -> f32 {
-        0.25 * f32::one_over_pi()
+    fn value(&self, _dir: &Vec3) -> Float {
+        0.25 * Float::one_over_pi()
     }
 
-    fn generate(&self) -> Option<(glm::Vec3, f32)> {
-        Some((rand_unit_vec(), 0.25 * f32::one_over_pi()))
+    fn generate(&self) -> Option<(Vec3, Float)> {
+        Some((rand_unit_vec(), 0.25 * Float::one_over_pi()))
     }
 }
@@ -1,40 +1,54 @@
 use crate::utility::{check_and, is_normalized};
+use crate::float::{Float, Vec3};
 
 
 #[derive(Debug)]
 pub struct Ray
 {
-    orig: glm::Vec3,
+    orig: Vec3,
     /// 确保该向量一定是单位向量
-    dir: glm::Vec3,
+    dir: Vec3,
+
+    /// 光线所在的时刻，用于运动模糊
+    time: Float,
 }
 
 
 impl Ray {
     /// 通过起点和目标点的方式来创建射线
-    pub fn new(orig: glm::Vec3, target: glm::Vec3) -> Ray
+    pub fn new(orig: Vec3, target: Vec3) -> Ray
     {
         let dir = glm::normalize(target - orig);
-        debug_assert!(check_and(&dir, f32::is_finite));
+        debug_assert!(check_and(&dir, Float::is_finite));
 
-        Ray { orig, dir }
+        Ray { orig, dir, time: 0.0 }
     }
 
     /// dir 是方向，确保是单位向量
-    pub fn new_d(orig: glm::Vec3, dir: glm::Vec3) -> Ray
+    pub fn new_d(orig: Vec3, dir: Vec3) -> Ray
     {
         debug_assert!(is_normalized(&dir));
 
-        Ray { orig, dir }
+        Ray { orig, dir, time: 0.0 }
+    }
+
+    /// 设置光线所在的时刻，默认为 0
+    pub fn with_time(mut self, time: Float) -> Ray
+    {
+        debug_assert!(time.is_finite());
+
+        self.time = time;
+        self
     }
 
 
-    pub fn orig(&self) -> &glm::Vec3 { &self.orig }
-    pub fn dir(&self) -> &glm::Vec3 { &self.dir }
+    pub fn orig(&self) -> &Vec3 { &self.orig }
+    pub fn dir(&self) -> &Vec3 { &self.dir }
+    pub fn time(&self) -> Float { self.time }
 
 
     // 射线方向上，距离原点 t 的点的坐标
-    pub fn at(&self, t: f32) -> glm::Vec3
+    pub fn at(&self, t: Float) -> Vec3
     {
         debug_assert!(t.is_finite());
 
@@ -52,7 +66,7 @@ mod test
 
     #[test]
     fn t1() {
-        let _ray = Ray::new(glm::Vec3::zero(), glm::Vec3::one());
+        let _ray = Ray::new(Vec3::zero(), Vec3::one());
         // println!("{:?}", ray.at(f32::NAN));
         println!("{}, {}", 123.0 > f32::NAN, 213.0 < f32::NAN);
     }
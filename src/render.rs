@@ -1,32 +1,43 @@
+use std::cell::Cell;
+use std::fmt;
 use std::ops::Deref;
+use std::time::{Duration, Instant};
 use num::{One, Zero};
 
-use crate::utility::clone_sender;
+use crate::utility::{clone_sender, rand_vec3, seed_rng};
 use crate::ray::Ray;
 use crate::camera::Camera;
 use crate::framebuffer::{FrameBuffer, Grid};
 use std::sync::{Arc, mpsc};
 use std::thread;
-use crate::hit::Hittable;
+use crate::hit::{HitPayload, Hittable};
+use crate::packet::{PACKET_SIZE, RayPacket};
 use crate::render::Background::Sky;
-use crate::material::Scatter;
-use crate::pdf::{HittablePDF, MixPDF, PDF};
+use crate::pdf::{BsdfPDF, HittablePDF, MixPDF, PDF};
+use crate::float::{Float, Vec3, vec3};
 
 
+thread_local! {
+    /// 当前线程投射的光线数量，用于统计
+    static RAY_COUNT: Cell<u64> = const { Cell::new(0) };
+}
+
+
+#[derive(Clone)]
 pub enum Background
 {
     Sky,
-    Color(glm::Vec3),
+    Color(Vec3),
 }
 
 impl Background
 {
-    fn color(&self, ray: &Ray) -> glm::Vec3
+    fn color(&self, ray: &Ray) -> Vec3
     {
         match self {
             Sky => {
                 let t = 0.5 * (ray.dir().y + 1.0);
-                glm::mix_s(glm::Vec3::one(), glm::vec3(0.5, 0.7, 1.0), t)
+                glm::mix_s(Vec3::one(), vec3(0.5, 0.7, 1.0), t)
             }
             Self::Color(c) => *c,
         }
@@ -47,6 +58,47 @@ pub struct Renderer
     tile_size: u32,
 
     background: Background,
+
+    /// 随机数种子，设置后每个 tile 都会使用由种子派生的随机数，渲染结果可以复现
+    seed: Option<u64>,
+}
+
+
+/// 一次渲染的统计信息
+#[derive(Debug, Clone)]
+pub struct RenderStats
+{
+    pub width: u32,
+    pub height: u32,
+
+    /// 每个像素的采样数
+    pub samples: u32,
+
+    /// 投射的光线总数，包括从摄像机出发的光线以及所有的散射光线
+    pub rays: u64,
+
+    pub elapsed: Duration,
+}
+
+
+impl RenderStats
+{
+    /// 从摄像机出发的光线数量
+    pub fn primary_rays(&self) -> u64 { self.width as u64 * self.height as u64 * self.samples as u64 }
+
+    pub fn rays_per_second(&self) -> f64 { self.rays as f64 / self.elapsed.as_secs_f64().max(1e-9) }
+}
+
+
+impl fmt::Display for RenderStats
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "resolution:   {}x{}, {} spp", self.width, self.height, self.samples)?;
+        writeln!(f, "render time:  {:.3} s", self.elapsed.as_secs_f64())?;
+        writeln!(f, "primary rays: {}", self.primary_rays())?;
+        writeln!(f, "total rays:   {} ({:.2} rays/sample)", self.rays, self.rays as f64 / self.primary_rays().max(1) as f64)?;
+        write!(f, "throughput:   {:.3} Mrays/s", self.rays_per_second() / 1.0e6)
+    }
 }
 
 
@@ -60,6 +112,7 @@ impl Renderer
             thread_num: 8,
             tile_size: 32,
             background: Sky,
+            seed: None,
         }
     }
 
@@ -74,10 +127,19 @@ impl Renderer
 
     pub fn set_performance(&mut self, thread_num: u32, tile_size: u32)
     {
+        debug_assert!(thread_num > 0 && tile_size > 0);
+
         self.thread_num = thread_num;
         self.tile_size = tile_size;
     }
 
+    pub fn set_seed(&mut self, seed: Option<u64>) { self.seed = seed }
+
+    pub fn samples(&self) -> u32 { self.samples }
+    pub fn max_depth(&self) -> u32 { self.max_depth as u32 }
+    pub fn thread_num(&self) -> u32 { self.thread_num }
+    pub fn tile_size(&self) -> u32 { self.tile_size }
+
     /// iter_depth 表示剩余的迭代深度，为 0 时，超出范围
     ///
     /// # 光线投射的基本过程
@@ -86,12 +148,20 @@ impl Renderer
     /// - 命中：采集发光颜色，并进行下一步的光线投射
     ///      - 光线没有后续：直接返回发光色
     ///      - 递归，返回发光色 + 递归的结果
-    fn cast_ray(&self, scene: &dyn Hittable, ray_in: &Ray, iter_depth: i32, lights: Option<&dyn Hittable>) -> glm::Vec3
+    fn cast_ray(&self, scene: &dyn Hittable, ray_in: &Ray, iter_depth: i32, lights: Option<&dyn Hittable>) -> Vec3
     {
-        if iter_depth <= 0 { return glm::Vec3::zero(); }
+        if iter_depth <= 0 { return Vec3::zero(); }
+        RAY_COUNT.with(|count| count.set(count.get() + 1));
 
-        // 注：使用 near=0.001 可以避免自身反射
-        match scene.hit(ray_in, (0.001, f32::INFINITY)) {
+        // 散射光线的起点已经沿着法线偏移过了，不会和出发的表面自相交，t 的范围可以从 0 开始
+        self.shade(scene, ray_in, scene.hit(ray_in, (0.0, Float::INFINITY)), iter_depth, lights)
+    }
+
+
+    /// 根据光线 ray_in 的求交结果计算颜色，后续的散射光线通过 `cast_ray` 逐条投射
+    fn shade(&self, scene: &dyn Hittable, ray_in: &Ray, hit: Option<HitPayload>, iter_depth: i32, lights: Option<&dyn Hittable>) -> Vec3
+    {
+        match hit {
 
             // 情形 1：光线什么都没有击中，返回背景色
             None => self.background.color(ray_in),
@@ -101,89 +171,108 @@ impl Renderer
                 // 被击中物体的自发光色
                 let emit_color = payload.material().emit(ray_in, &payload);
 
-                match payload.material().scatter(ray_in, &payload) {
-
-                    // 情形 2-1 ：光线击中了物体，但是不再有后续的散射，直接返回物体的发光色
+                // 情形 2-1 ：光线击中了物体，但是不再有后续的散射，直接返回物体的发光色
+                let bsdf = match payload.material().bsdf(ray_in, &payload) {
                     None => return emit_color,
+                    Some(bsdf) => bsdf,
+                };
+                let wo = -*ray_in.dir();
+
+                // 情形 2-2：击中了物体，且还有后续的散射，确定散射方向 wi 以及对应的 pdf
+                let scatter_res = match lights {
+                    // 情形 2-2-1：使用混合的 pdf，由以下部分得到：
+                    // - 通过符合材质的重要性采样的 bsdf-pdf
+                    // - 以及符合光源几何的 light-pdf
+                    Some(lights) if !bsdf.flags().is_specular() => {
+                        let light_pdf = HittablePDF::new(lights, *payload.hit_point());
+                        let bsdf_pdf = BsdfPDF::new(&bsdf, wo);
+                        MixPDF::new(&light_pdf, &bsdf_pdf, 0.5).generate()
+                            .map(|(wi, monte_pdf)| (wi, bsdf.eval(&wo, &wi), monte_pdf))
+                    }
 
-                    // 情形 2-2：击中了物体，且还有后续的散射
-                    Some(Scatter { diffuse_pdf, attenuation, specular_ray }) => {
+                    // 情形 2-2-2：specular 材质，scatter 方向是确定的，只能由 BSDF 采样
+                    _ => bsdf.sample(&wo, &rand_vec3()).map(|sample| (sample.wi, sample.f, sample.pdf)),
+                };
 
-                        // 情形 2-2-1：specular 材质，scatter 方向是确定的
-                        if let Some(specular_ray) = specular_ray {
-                            let scatter_color = self.cast_ray(scene, &specular_ray, iter_depth - 1, lights);
-                            return attenuation * scatter_color;
-                        }
+                let (scatter_dir, f, monte_pdf) =
+                    if let Some(val) = scatter_res { val } else { return emit_color; };
+                debug_assert!(monte_pdf > 0.0);
 
+                // 光源方向位于 BSDF 的范围之外，例如光源在表面的背面，不需要继续投射光线
+                if f.is_zero() { return emit_color; }
 
-                        // 情形 2-2-2：diffuse 材质，scatter 方向由 pdf 决定
-                        let diffuse_pdf =
-                            if let Some(diffuse_pdf) = diffuse_pdf { diffuse_pdf } else { return emit_color; };
+                let scatter_ray = payload.spawn_ray(scatter_dir).with_time(ray_in.time());
 
-                        // 使用混合的 pdf，由以下部分得到：
-                        // - 通过符合材质的重要性采样的 mat-pdf
-                        // - 以及符合光源几何的 light-pdf
-                        let scatter_res =
-                            if lights.is_none() {
-                                diffuse_pdf.generate()
-                            } else {
-                                let light_pdf = HittablePDF::new(lights.unwrap(), *payload.hit_point());
-                                let mix_pdf = MixPDF::new(&light_pdf, diffuse_pdf.deref(), 0.5);
-                                mix_pdf.generate()
-                            };
+                // 使用 Monte Carlo 积分计算来自散射的光照，其 pdf 可以任意选择，f 中已经包含了余弦项
+                let scatter_color = self.cast_ray(scene, &scatter_ray, iter_depth - 1, lights);
+                debug_assert!(scatter_color.x >= 0.0 && scatter_color.y >= 0.0 && scatter_color.z >= 0.0);
+
+                emit_color + f * scatter_color / monte_pdf
+            }
+        }
+    }
 
-                        let (scatter_dir, monte_pdf) =
-                            if let Some(val) = scatter_res { val } else { return emit_color; };
-                        debug_assert!(monte_pdf > 0.0);
 
-                        let scatter_ray = Ray::new_d(*payload.hit_point(), scatter_dir);
+    /// 渲染一个 tile，返回 tile 中每个像素的颜色
+    fn render_tile(&self, tile: &Grid, framebuffer_size: (u32, u32), scene: &dyn Hittable, camera: &Camera, lights: Option<&dyn Hittable>) -> Vec<((u32, u32), Vec3)>
+    {
+        // 每个 tile 的种子只和 tile 的位置有关，因此结果和线程数量无关
+        if let Some(seed) = self.seed {
+            seed_rng(seed ^ ((tile.pos.0 as u64) << 32 | tile.pos.1 as u64));
+        }
 
+        let mut tile_res: Vec<((u32, u32), Vec3)> = Vec::with_capacity((tile.size.0 * tile.size.1) as usize);
 
-                        // 朝某个方向散射的 pdf，是 BRDF 的一部分
-                        let scatter_pdf = payload.material().scatter_pdf(ray_in, &payload, &scatter_ray);
+        // 相邻 2x2 个像素的摄像机光线非常接近，每次取每个像素的一个样本，打包成光线包一起遍历 BVH，
+        // 只有第一次求交使用光线包，之后的散射光线方向发散，仍然逐条投射
+        debug_assert_eq!(PACKET_SIZE, 4);
+        for block in tile.blocks(2) {
+            let pixels = block.iter();
+            let uvs: Vec<Vec<(Float, Float)>> = pixels.iter()
+                .map(|pos| FrameBuffer::multi_sample(framebuffer_size, *pos, self.samples))
+                .collect();
+            let mut colors = vec![Vec3::zero(); pixels.len()];
 
-                        // 这里的反射方程是另一种形式的，带有 scatter pdf 项的
-                        // 使用 Monte Carlo 积分计算来自散射的光照，其 pdf 可以任意选择
-                        let scatter_color = self.cast_ray(scene, &scatter_ray, iter_depth - 1, lights);
-                        debug_assert!(scatter_color.x >= 0.0 && scatter_color.y >= 0.0 && scatter_color.z >= 0.0);
+            for sample in 0..self.samples as usize {
+                if self.max_depth <= 0 { break; }
 
+                let packet = RayPacket::new(uvs.iter().map(|uvs| camera.ray_from_uv(uvs[sample])).collect());
+                RAY_COUNT.with(|count| count.set(count.get() + packet.len() as u64));
 
-                        emit_color + attenuation * scatter_pdf * scatter_color / monte_pdf
-                    }
+                let mut hits: [Option<HitPayload>; PACKET_SIZE] = Default::default();
+                scene.hit_packet(&packet, (0.0, Float::INFINITY), &mut hits);
+                for (color, (ray, hit)) in colors.iter_mut().zip(packet.rays().iter().zip(hits)) {
+                    *color = *color + self.shade(scene, ray, hit, self.max_depth, lights);
                 }
             }
+
+            tile_res.extend(pixels.into_iter().zip(colors).map(|(pos, color)| (pos, color / self.samples as Float)));
         }
+
+        tile_res
     }
 
 
-    pub fn render_single_thread(&self, framebuffer: &mut FrameBuffer, scene: Arc<dyn Hittable + Sync + Send>, camera: &Camera, lights: Option<Arc<dyn Hittable + Sync + Send>>)
+    pub fn render_single_thread(&self, framebuffer: &mut FrameBuffer, scene: Arc<dyn Hittable + Sync + Send>, camera: &Camera, lights: Option<Arc<dyn Hittable + Sync + Send>>) -> RenderStats
     {
+        let now = Instant::now();
         let framebuffer_size = (framebuffer.width(), framebuffer.height());
 
         let lights = lights.as_ref().and_then(|val| Some(val.as_ref() as &dyn Hittable));
 
-        for pos in framebuffer.pixel_iter()
-        {
-            let mut pixel_color = glm::Vec3::zero();
-
-            // NOTE 手动条件断点
-            if pos.0 == 30 && pos.1 == 56 {
-                println!("accc");
-                println!("bbb");
-            }
-
-
-            // multi samlpe
-            for uv in FrameBuffer::multi_sample(framebuffer_size, pos, self.samples)
-            {
-                let ray = camera.ray_from_uv(uv);
-
-                pixel_color = pixel_color + self.cast_ray(scene.deref(), &ray, self.max_depth, lights);
+        RAY_COUNT.with(|count| count.set(0));
+        for tile in framebuffer.split_to_tile(self.tile_size) {
+            for (pos, color) in self.render_tile(&tile, framebuffer_size, scene.deref(), camera, lights) {
+                framebuffer.write_color(pos, &color);
             }
+        }
 
-            pixel_color = pixel_color / self.samples as f32;
-
-            framebuffer.write_color(pos, &pixel_color);
+        RenderStats {
+            width: framebuffer.width(),
+            height: framebuffer.height(),
+            samples: self.samples,
+            rays: RAY_COUNT.with(|count| count.get()),
+            elapsed: now.elapsed(),
         }
     }
 
@@ -200,8 +289,9 @@ impl Renderer
     }
 
 
-    pub fn render_multi_thread(renderer: Arc<Renderer>, framebuffer: &mut FrameBuffer, scene: Arc<dyn Hittable + Sync + Send>, camera: &Camera, lights: Option<Arc<dyn Hittable + Sync + Send>>)
+    pub fn render_multi_thread(renderer: Arc<Renderer>, framebuffer: &mut FrameBuffer, scene: Arc<dyn Hittable + Sync + Send>, camera: &Camera, lights: Option<Arc<dyn Hittable + Sync + Send>>) -> RenderStats
     {
+        let now = Instant::now();
         let framebuffer_size = (framebuffer.width(), framebuffer.height());
         let mut tasks = Renderer::generate_tasks(framebuffer, renderer.thread_num, renderer.tile_size);
 
@@ -222,22 +312,13 @@ impl Renderer
             threads.push(thread::spawn(move || {
                 let lights = lights.as_ref().and_then(|val| Some(val.deref() as &dyn Hittable));
 
+                RAY_COUNT.with(|count| count.set(0));
                 for tile in task {
-                    let mut tile_res: Vec<((u32, u32), glm::Vec3)> = Vec::with_capacity((tile.pos.0 * tile.pos.1) as usize);
-
-                    for pos in tile.iter() {
-                        let mut color = glm::Vec3::zero();
-                        for uv in FrameBuffer::multi_sample(framebuffer_size, pos, renderer.samples) {
-                            let ray = camera.ray_from_uv(uv);
-                            color = color + renderer.cast_ray(scene.deref(), &ray, renderer.max_depth, lights);
-                        }
-                        color = color / renderer.samples as f32;
-
-                        tile_res.push((pos, color));
-                    }
-
+                    let tile_res = renderer.render_tile(&tile, framebuffer_size, scene.deref(), &camera, lights);
                     sender.send(tile_res).unwrap();
                 }
+
+                RAY_COUNT.with(|count| count.get())
             }));
         }
 
@@ -260,8 +341,17 @@ impl Renderer
 
         pb.finish_with_message("done");
 
+        let mut rays = 0;
         for thread in threads {
-            thread.join().unwrap();
+            rays += thread.join().unwrap();
+        }
+
+        RenderStats {
+            width: framebuffer.width(),
+            height: framebuffer.height(),
+            samples: renderer.samples,
+            rays,
+            elapsed: now.elapsed(),
         }
     }
 }
\ No newline at end of file
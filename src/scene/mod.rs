@@ -0,0 +1,83 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::camera::Camera;
+use crate::hit::Hittable;
+use crate::render::{Background, Renderer};
+
+
+/// 一个完整的场景：物体、摄像机、需要优先采样的光源，以及渲染参数
+pub struct Scene
+{
+    pub world: Arc<dyn Hittable + Send + Sync>,
+
+    pub camera: Camera,
+
+    /// 需要进行重要性采样的物体（一般是光源）
+    pub lights: Option<Arc<dyn Hittable + Send + Sync>>,
+
+    pub background: Background,
+
+    /// 场景建议的渲染质量：(每个像素的采样数, 最大迭代深度)，None 表示使用渲染器的默认值
+    pub quality: Option<(u32, u32)>,
+}
+
+
+impl Scene
+{
+    pub fn new(world: Arc<dyn Hittable + Send + Sync>, camera: Camera, lights: Option<Arc<dyn Hittable + Send + Sync>>) -> Scene
+    {
+        Scene { world, camera, lights, background: Background::Sky, quality: None }
+    }
+
+
+    /// 将场景中的渲染参数（背景，渲染质量）应用到渲染器上
+    pub fn apply(&self, renderer: &mut Renderer)
+    {
+        renderer.set_backround(self.background.clone());
+        if let Some((samples, max_depth)) = self.quality {
+            renderer.set_quality(samples, max_depth);
+        }
+    }
+}
+
+
+/// 读取场景文件时发生的错误，会指明文件、行号以及出错的条目
+#[derive(Debug)]
+pub struct SceneError
+{
+    pub file: String,
+
+    /// 行号从 1 开始；0 表示错误不属于某一行（例如缺少某个条目）
+    pub line: usize,
+
+    /// 出错的那一行的原始内容
+    pub entry: String,
+
+    pub msg: String,
+}
+
+
+impl fmt::Display for SceneError
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.msg)?;
+        if !self.entry.is_empty() {
+            write!(f, "\n    {}", self.entry)?;
+        }
+        Ok(())
+    }
+}
+
+
+impl std::error::Error for SceneError {}
+
+
+mod parser;
+mod obj;
+mod ply;
+
+
+pub use parser::{load_scene, parse_scene};
+pub use obj::{load_obj, ObjMesh, ObjModel};
+pub use ply::{load_ply, parse_ply};
@@ -0,0 +1,585 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::geom::bvh::BVHNode;
+use crate::geom::hittable_list::HittableList;
+use crate::geom::mesh::TriangleMesh;
+use crate::hit::Hittable;
+use crate::material::{Dielecric, DiffuseEmit, Lambertian, Material, Metal};
+use crate::scene::SceneError;
+use crate::texture::{ImageTexture, SolidColor, Texture};
+use crate::float::{Float, Vec2, Vec3, vec2, vec3};
+
+
+/// OBJ 中的一个网格：同一个组（或对象）中使用同一个材质的面
+pub struct ObjMesh
+{
+    /// 组或者对象的名字
+    pub name: String,
+
+    pub mesh: Arc<TriangleMesh>,
+
+    /// 材质是否发光
+    pub emissive: bool,
+}
+
+
+/// 读取 OBJ 文件得到的模型
+pub struct ObjModel
+{
+    pub meshes: Vec<ObjMesh>,
+}
+
+
+impl ObjModel
+{
+    /// 将所有网格的三角形放入同一个 BVH 中；模型中没有三角形时返回 None
+    pub fn to_bvh(&self) -> Option<BVHNode>
+    {
+        let triangles: Vec<_> = self.meshes.iter().flat_map(|m| m.mesh.triangles()).collect();
+        if triangles.is_empty() {
+            return None;
+        }
+        Some(BVHNode::new(&triangles))
+    }
+
+
+    /// 所有发光的三角形，可以作为 `Renderer` 的 lights 进行重要性采样
+    pub fn lights(&self) -> Vec<Arc<dyn Hittable + Send + Sync>>
+    {
+        self.meshes.iter().filter(|m| m.emissive).flat_map(|m| m.mesh.triangles()).collect()
+    }
+
+
+    /// 所有发光三角形组成的列表，没有发光的面时返回 None
+    pub fn lights_list(&self) -> Option<HittableList>
+    {
+        let lights = self.lights();
+        if lights.is_empty() {
+            return None;
+        }
+
+        let mut list = HittableList::default();
+        for light in lights {
+            list.add(light);
+        }
+        Some(list)
+    }
+}
+
+
+/// MTL 中的一个材质，以及是否发光
+#[derive(Clone)]
+struct ObjMaterial
+{
+    mat: Arc<dyn Material + Send + Sync>,
+    emissive: bool,
+}
+
+
+/// 读取 OBJ 文件，以及其引用的 MTL 文件
+///
+/// - 支持 v/vt/vn，以及 `f` 中的 `v`、`v/vt`、`v//vn`、`v/vt/vn` 和负数索引，多边形会被分解为三角形
+/// - 按照 `o`/`g` 以及 `usemtl` 划分网格，没有指定材质的面使用 default_mat
+/// - 其他的语句（例如 `s`、`l`）会被忽略
+pub fn load_obj(path: &str, default_mat: Arc<dyn Material + Send + Sync>) -> Result<ObjModel, SceneError>
+{
+    let src = read_file(path)?;
+    let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    let mut parser = ObjParser::new(ObjMaterial { mat: default_mat, emissive: false });
+
+    for (idx, line) in src.lines().enumerate() {
+        let to_error = |msg: String| SceneError {
+            file: path.to_string(),
+            line: idx + 1,
+            entry: line.trim().to_string(),
+            msg,
+        };
+
+        let line = line.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() { continue; }
+
+        parser.entry(&tokens, base_dir).map_err(|err| match err {
+            ObjError::Line(msg) => to_error(msg),
+            ObjError::Mtl(err) => err,
+        })?;
+    }
+
+    Ok(parser.finish())
+}
+
+
+fn read_file(path: &str) -> Result<String, SceneError>
+{
+    std::fs::read_to_string(path).map_err(|err| SceneError {
+        file: path.to_string(),
+        line: 0,
+        entry: String::new(),
+        msg: format!("can not read file: {}", err),
+    })
+}
+
+
+enum ObjError
+{
+    /// 当前行的错误
+    Line(String),
+
+    /// 读取 mtllib 引用的文件时发生的错误，已经带有文件和行号
+    Mtl(SceneError),
+}
+
+
+impl From<String> for ObjError
+{
+    fn from(msg: String) -> Self { ObjError::Line(msg) }
+}
+
+
+/// 正在构建的网格，顶点使用 (v, vt, vn) 的组合去重
+struct MeshBuilder
+{
+    name: String,
+    mat: ObjMaterial,
+
+    vertex_map: HashMap<(usize, Option<usize>, Option<usize>), u32>,
+    positions: Vec<Vec3>,
+    uvs: Vec<Option<Vec2>>,
+    normals: Vec<Option<Vec3>>,
+    indices: Vec<[u32; 3]>,
+}
+
+
+impl MeshBuilder
+{
+    fn new(name: String, mat: ObjMaterial) -> MeshBuilder
+    {
+        MeshBuilder {
+            name,
+            mat,
+            vertex_map: HashMap::new(),
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+
+    fn build(self) -> Option<ObjMesh>
+    {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        let mut mesh = TriangleMesh::new(self.positions, self.indices, self.mat.mat);
+
+        // 只要有一个顶点带有纹理坐标，就使用纹理坐标，缺失的纹理坐标设为 (0, 0)
+        if self.uvs.iter().any(|uv| uv.is_some()) {
+            mesh.set_uvs(self.uvs.iter().map(|uv| uv.unwrap_or(vec2(0.0, 0.0))).collect());
+        }
+
+        // 只有所有的顶点都有法线时，才使用顶点法线进行平滑着色
+        if !self.normals.is_empty() && self.normals.iter().all(|n| n.is_some()) {
+            mesh.set_normals(self.normals.iter().map(|n| n.unwrap()).collect());
+        }
+
+        Some(ObjMesh { name: self.name, mesh: Arc::new(mesh), emissive: self.mat.emissive })
+    }
+}
+
+
+struct ObjParser
+{
+    positions: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    normals: Vec<Vec3>,
+
+    materials: HashMap<String, ObjMaterial>,
+    default_mat: ObjMaterial,
+
+    group: String,
+    current: MeshBuilder,
+    meshes: Vec<ObjMesh>,
+}
+
+
+impl ObjParser
+{
+    fn new(default_mat: ObjMaterial) -> ObjParser
+    {
+        ObjParser {
+            positions: Vec::new(),
+            uvs: Vec::new(),
+            normals: Vec::new(),
+            materials: HashMap::new(),
+            current: MeshBuilder::new(String::new(), default_mat.clone()),
+            default_mat,
+            group: String::new(),
+            meshes: Vec::new(),
+        }
+    }
+
+
+    fn entry(&mut self, tokens: &[&str], base_dir: &Path) -> Result<(), ObjError>
+    {
+        let args = &tokens[1..];
+
+        match tokens[0] {
+            "v" => {
+                // 可能带有顶点颜色等额外的分量，只使用前三个
+                if args.len() < 3 { return Err("vertex needs 3 components".to_string().into()); }
+                self.positions.push(vec3(parse_float(args[0])?, parse_float(args[1])?, parse_float(args[2])?));
+            }
+            "vt" => {
+                if args.is_empty() { return Err("texture coordinate needs at least 1 component".to_string().into()); }
+                let v = if args.len() > 1 { parse_float(args[1])? } else { 0.0 };
+                self.uvs.push(vec2(parse_float(args[0])?, v));
+            }
+            "vn" => {
+                if args.len() != 3 { return Err("normal needs 3 components".to_string().into()); }
+                let n = vec3(parse_float(args[0])?, parse_float(args[1])?, parse_float(args[2])?);
+                let len = glm::length(n);
+                self.normals.push(if len > 0.0 { n / len } else { n });
+            }
+            "f" => self.face(args)?,
+            "o" | "g" => {
+                self.group = args.join(" ");
+                let mat = self.current.mat.clone();
+                self.switch_mesh(mat);
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                let mat = self.materials.get(&name).cloned().ok_or(format!("unknown material `{}`", name))?;
+                self.switch_mesh(mat);
+            }
+            "mtllib" => {
+                if args.is_empty() { return Err("missing mtl file".to_string().into()); }
+                for file in args {
+                    let path = base_dir.join(file);
+                    let materials = load_mtl(&path.to_string_lossy()).map_err(ObjError::Mtl)?;
+                    self.materials.extend(materials);
+                }
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+
+    /// 开始一个新的网格，之前的网格如果有三角形，就保存起来
+    fn switch_mesh(&mut self, mat: ObjMaterial)
+    {
+        let builder = std::mem::replace(&mut self.current, MeshBuilder::new(self.group.clone(), mat));
+        if let Some(mesh) = builder.build() {
+            self.meshes.push(mesh);
+        }
+    }
+
+
+    fn face(&mut self, args: &[&str]) -> Result<(), String>
+    {
+        if args.len() < 3 {
+            return Err("face needs at least 3 vertices".to_string());
+        }
+
+        let mut vertices = Vec::with_capacity(args.len());
+        for arg in args {
+            let mut parts = arg.split('/');
+            let v = resolve_index(parts.next(), self.positions.len(), "vertex")?
+                .ok_or("face vertex needs a position index".to_string())?;
+            let vt = resolve_index(parts.next(), self.uvs.len(), "texture coordinate")?;
+            let vn = resolve_index(parts.next(), self.normals.len(), "normal")?;
+            if parts.next().is_some() {
+                return Err(format!("invalid face vertex `{}`", arg));
+            }
+
+            let key = (v, vt, vn);
+            let builder = &mut self.current;
+            let idx = match builder.vertex_map.get(&key) {
+                Some(&idx) => idx,
+                None => {
+                    let idx = builder.positions.len() as u32;
+                    builder.positions.push(self.positions[v]);
+                    builder.uvs.push(vt.map(|i| self.uvs[i]));
+                    builder.normals.push(vn.map(|i| self.normals[i]));
+                    builder.vertex_map.insert(key, idx);
+                    idx
+                }
+            };
+            vertices.push(idx);
+        }
+
+        // 使用扇形的方式分解多边形
+        for i in 1..vertices.len() - 1 {
+            self.current.indices.push([vertices[0], vertices[i], vertices[i + 1]]);
+        }
+
+        Ok(())
+    }
+
+
+    fn finish(mut self) -> ObjModel
+    {
+        let mat = self.default_mat.clone();
+        self.switch_mesh(mat);
+        ObjModel { meshes: self.meshes }
+    }
+}
+
+
+fn parse_float(word: &str) -> Result<Float, String>
+{
+    match word.parse::<Float>() {
+        Ok(val) if val.is_finite() => Ok(val),
+        _ => Err(format!("expect a number, found `{}`", word)),
+    }
+}
+
+
+/// 将 OBJ 中从 1 开始的索引（负数表示从末尾倒数）转换为从 0 开始的索引
+fn resolve_index(word: Option<&str>, len: usize, what: &str) -> Result<Option<usize>, String>
+{
+    let word = match word {
+        None | Some("") => return Ok(None),
+        Some(word) => word,
+    };
+
+    let idx: i64 = word.parse().map_err(|_| format!("invalid {} index `{}`", what, word))?;
+    let resolved = if idx > 0 { idx - 1 } else { len as i64 + idx };
+
+    if idx == 0 || resolved < 0 || resolved >= len as i64 {
+        return Err(format!("{} index {} out of range", what, idx));
+    }
+    Ok(Some(resolved as usize))
+}
+
+
+/// MTL 中一个材质的参数
+struct MtlParams
+{
+    kd: Vec3,
+    map_kd: Option<Arc<dyn Texture + Send + Sync>>,
+    ke: Vec3,
+    map_ke: Option<Arc<dyn Texture + Send + Sync>>,
+    ks: Vec3,
+    ns: Float,
+    ni: Float,
+    d: Float,
+    illum: u32,
+}
+
+
+impl MtlParams
+{
+    fn new() -> MtlParams
+    {
+        MtlParams {
+            kd: vec3(0.8, 0.8, 0.8),
+            map_kd: None,
+            ke: vec3(0.0, 0.0, 0.0),
+            map_ke: None,
+            ks: vec3(0.0, 0.0, 0.0),
+            ns: 0.0,
+            ni: 1.5,
+            d: 1.0,
+            illum: 2,
+        }
+    }
+
+
+    /// 将 MTL 的参数转换为对应的材质，优先级：发光 > 透明 > 金属 > 漫反射
+    ///
+    /// - Ke/map_Ke：DiffuseEmit
+    /// - d < 1 或者 illum 为 4, 6, 7, 9：Dielecric，折射率为 Ni
+    /// - illum 为 3，或者没有漫反射只有镜面反射：Metal，颜色为 Ks，粗糙程度由 Ns 换算
+    /// - 其他：Lambertian，使用 Kd 或者 map_Kd
+    fn to_material(&self) -> ObjMaterial
+    {
+        let max = |c: Vec3| Float::max(c.x, Float::max(c.y, c.z));
+
+        if self.map_ke.is_some() || max(self.ke) > 0.0 {
+            let emit = self.map_ke.clone().unwrap_or_else(|| Arc::new(SolidColor::new(self.ke)));
+            return ObjMaterial { mat: Arc::new(DiffuseEmit::new(emit)), emissive: true };
+        }
+
+        let mat: Arc<dyn Material + Send + Sync> =
+            if self.d < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+                Arc::new(Dielecric::new(self.ni))
+            } else if max(self.ks) > 0.0 && (self.illum == 3 || (self.map_kd.is_none() && max(self.kd) == 0.0)) {
+                // Phong 指数和粗糙程度的近似换算关系：alpha = sqrt(2 / (Ns + 2))
+                let fuzz = Float::sqrt(2.0 / (self.ns.max(0.0) + 2.0));
+                Arc::new(Metal::new(self.ks, fuzz))
+            } else {
+                let albedo = self.map_kd.clone().unwrap_or_else(|| Arc::new(SolidColor::new(self.kd)));
+                Arc::new(Lambertian::new_t(albedo))
+            };
+
+        ObjMaterial { mat, emissive: false }
+    }
+}
+
+
+/// 读取 MTL 文件，返回其中定义的所有材质
+fn load_mtl(path: &str) -> Result<HashMap<String, ObjMaterial>, SceneError>
+{
+    let src = read_file(path)?;
+    let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlParams)> = None;
+
+    for (idx, line) in src.lines().enumerate() {
+        let to_error = |msg: String| SceneError {
+            file: path.to_string(),
+            line: idx + 1,
+            entry: line.trim().to_string(),
+            msg,
+        };
+
+        let line = line.split('#').next().unwrap_or("");
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.is_empty() { continue; }
+
+        if tokens[0] == "newmtl" {
+            if let Some((name, params)) = current.take() {
+                materials.insert(name, params.to_material());
+            }
+            if tokens.len() < 2 {
+                return Err(to_error("missing material name".to_string()));
+            }
+            current = Some((tokens[1..].join(" "), MtlParams::new()));
+            continue;
+        }
+
+        let params = match &mut current {
+            Some((_, params)) => params,
+            None => return Err(to_error("statement before `newmtl`".to_string())),
+        };
+        mtl_entry(params, &tokens, base_dir).map_err(to_error)?;
+    }
+
+    if let Some((name, params)) = current.take() {
+        materials.insert(name, params.to_material());
+    }
+
+    Ok(materials)
+}
+
+
+fn mtl_entry(params: &mut MtlParams, tokens: &[&str], base_dir: &Path) -> Result<(), String>
+{
+    let args = &tokens[1..];
+    let color = |args: &[&str]| -> Result<Vec3, String> {
+        match args.len() {
+            1 => {
+                let c = parse_float(args[0])?;
+                Ok(vec3(c, c, c))
+            }
+            3 => Ok(vec3(parse_float(args[0])?, parse_float(args[1])?, parse_float(args[2])?)),
+            _ => Err("color needs 1 or 3 components".to_string()),
+        }
+    };
+    let scalar = |args: &[&str]| -> Result<Float, String> {
+        match args.len() {
+            1 => parse_float(args[0]),
+            _ => Err("expect exactly one number".to_string()),
+        }
+    };
+    // 贴图语句的最后一个参数是文件名，前面可能有 -s 等选项，这里忽略这些选项
+    let texture = |args: &[&str]| -> Result<Arc<dyn Texture + Send + Sync>, String> {
+        let file = args.last().ok_or("missing texture file".to_string())?;
+        let path = base_dir.join(file);
+        Ok(Arc::new(ImageTexture::load(&path.to_string_lossy())?))
+    };
+
+    match tokens[0] {
+        "Kd" => params.kd = color(args)?,
+        "Ke" => params.ke = color(args)?,
+        "Ks" => params.ks = color(args)?,
+        "Ns" => params.ns = scalar(args)?,
+        "Ni" => params.ni = scalar(args)?,
+        "d" => params.d = scalar(args)?,
+        "Tr" => params.d = 1.0 - scalar(args)?,
+        "illum" => {
+            params.illum = match args {
+                [word] => word.parse().map_err(|_| format!("invalid illum `{}`", word))?,
+                _ => return Err("expect exactly one illum model".to_string()),
+            }
+        }
+        "map_Kd" => params.map_kd = Some(texture(args)?),
+        "map_Ke" => params.map_ke = Some(texture(args)?),
+        _ => {}
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use num::Zero;
+    use crate::ray::Ray;
+
+    fn write_temp(name: &str, content: &str) -> String
+    {
+        let dir = std::env::temp_dir().join(format!("rt_week_obj_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn test_load_obj()
+    {
+        write_temp("test.mtl", "newmtl white\nKd 0.8 0.8 0.8\n\nnewmtl light\nKe 10 10 10\n");
+        let path = write_temp("test.obj", r#"
+            mtllib test.mtl
+            v -1 0 -1
+            v 1 0 -1
+            v 1 0 1
+            v -1 0 1
+            v -1 2 -1
+            v 1 2 -1
+            v 0 2 1
+            vn 0 1 0
+            o floor
+            usemtl white
+            f 1//1 4//1 3//1 2//1
+            o lamp
+            usemtl light
+            f -3 -1 -2
+        "#);
+
+        let model = load_obj(&path, Arc::new(Lambertian::new(Vec3::zero()))).unwrap();
+        assert_eq!(model.meshes.len(), 2);
+        assert_eq!(model.meshes[0].name, "floor");
+        assert_eq!(model.meshes[0].mesh.triangle_count(), 2);
+        assert!(model.meshes[1].emissive);
+        assert_eq!(model.lights().len(), 1);
+
+        let bvh = model.to_bvh().unwrap();
+        let ray = Ray::new(vec3(0.2, 1.0, 0.3), vec3(0.2, 0.0, 0.3));
+        assert!(bvh.hit(&ray, (0.001, Float::INFINITY)).is_some());
+    }
+
+    #[test]
+    fn test_obj_error()
+    {
+        let path = write_temp("bad.obj", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 4\n");
+        let err = load_obj(&path, Arc::new(Lambertian::new(Vec3::zero()))).err().unwrap();
+        assert_eq!(err.line, 4);
+        assert_eq!(err.entry, "f 1 2 4");
+
+        let path = write_temp("missing_tex.mtl", "newmtl a\nmap_Kd no_such_file.png\n");
+        let err = load_mtl(&path).err().unwrap();
+        assert_eq!(err.line, 2);
+    }
+}
@@ -0,0 +1,1011 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use num::One;
+
+use crate::camera::Camera;
+use crate::geom::Axis;
+use crate::geom::aabb::AABB;
+use crate::geom::bvh::BVHNode;
+use crate::geom::cone::Cone;
+use crate::geom::cube::Cube;
+use crate::geom::csg::{Csg, CsgOp};
+use crate::geom::cylinder::Cylinder;
+use crate::geom::disk::Disk;
+use crate::geom::heightfield::Heightfield;
+use crate::geom::hittable_list::HittableList;
+use crate::geom::instance::Instance;
+use crate::geom::plane::Plane;
+use crate::geom::quad::Quad;
+use crate::geom::sdf::{Mandelbulb, Repeat, Sdf, SdfBox, SdfShape, SdfSphere, SdfTorus, SmoothUnion, Subtraction, Twist};
+use crate::geom::rect::AxisRect;
+use crate::geom::{MovingSphere, Sphere};
+use crate::geom::torus::Torus;
+use crate::geom::transform::{affine_inverse, FlipFace, MovingTransform, RotateY, Transform, Translate};
+use crate::geom::triangle::Triangle;
+use crate::geom::volumn::ConstantMedium;
+use crate::hit::Hittable;
+use crate::material::{Dielecric, DiffuseEmit, Lambertian, Material, Metal};
+use crate::noise::{NoiseTexture, Perlin};
+use crate::render::Background;
+use crate::scene::{Scene, SceneError};
+use crate::scene::obj::load_obj;
+use crate::scene::ply::load_ply;
+use crate::texture::{CheckerTexture, ImageTexture, SolidColor, Texture, VertexColorTexture};
+use crate::float::{Float, Vec2, Vec3, Mat4, vec2, vec3, vec4};
+
+
+type SharedTexture = Arc<dyn Texture + Send + Sync>;
+type SharedMaterial = Arc<dyn Material + Send + Sync>;
+type SharedHittable = Arc<dyn Hittable + Send + Sync>;
+type SharedSdf = Arc<dyn Sdf + Send + Sync>;
+
+
+/// 读取场景文件，文件中的相对路径（例如图片纹理）以场景文件所在的目录为起点
+pub fn load_scene(path: &str) -> Result<Scene, SceneError>
+{
+    let src = std::fs::read_to_string(path).map_err(|err| SceneError {
+        file: path.to_string(),
+        line: 0,
+        entry: String::new(),
+        msg: format!("can not read scene file: {}", err),
+    })?;
+
+    let base_dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    parse_scene(&src, path, base_dir)
+}
+
+
+/// 解析场景描述文本
+///
+/// 文件按行组织，每行一个条目，`#` 之后的内容是注释，包含空格的路径可以用双引号括起来。
+/// 纹理、材质、距离场、形状都需要先定义再使用，分别使用各自的名字空间：
+///
+/// ```text
+/// camera from 278 278 -800 at 278 278 0 up 0 1 0 vfov 40 aspect 1 aperture 0 focus 10 shutter 0 1
+/// renderer samples 128 depth 50
+/// background color 0 0 0                      # 或者 background sky
+///
+/// texture <name> solid <r g b>
+/// texture <name> checker <tex> <tex>
+/// texture <name> image <path>
+/// texture <name> noise <scale>
+/// texture <name> vertex_color [<tex>]         # 使用网格的顶点颜色，没有顶点颜色时使用 <tex>，默认为灰色
+///
+/// material <name> lambertian <tex>
+/// material <name> metal <r g b> <fuzz>
+/// material <name> dielectric <ir>
+/// material <name> emit <tex>
+///
+/// sdf <name> sphere <center> <radius>            # 距离场，用于 sdf 形状
+/// sdf <name> box <center> <half size>
+/// sdf <name> torus <center> <major radius> <minor radius>
+/// sdf <name> mandelbulb <power> <iterations>      # 位于原点附近，半径约为 1.2
+/// sdf <name> smooth_union <sdf> <sdf> <k>
+/// sdf <name> subtract <sdf> <sdf>
+/// sdf <name> repeat <sdf> <period>                # 周期为 0 的方向上不重复
+/// sdf <name> twist <sdf> <degree>                 # 绕 Y 轴扭曲，每单位高度旋转的角度
+///
+/// shape <name> sphere <cx cy cz> <radius> <mat>
+/// shape <name> moving_sphere <center0> <time0> <center1> <time1> <radius> <mat>
+/// shape <name> rect <x|y|z> <a0 b0> <a1 b1> <k> <mat>
+/// shape <name> plane <point> <normal> [tile <size>] <mat>   # 无限大的平面，纹理坐标每隔 size 重复一次
+/// shape <name> quad <q> <u> <v> <mat>         # 以 q 为顶点，u, v 为两条边的平行四边形，法线方向为 u x v
+/// shape <name> cube <x0 y0 z0> <x1 y1 z1> <mat>
+/// shape <name> disk <center> <radius> <mat>                       # 以下形状都以 Y 轴为朝向
+/// shape <name> cylinder <base> <radius> <height> [capped] <mat>
+/// shape <name> cone <base> <radius> <height> [capped] <mat>
+/// shape <name> torus <center> <major radius> <minor radius> <mat>
+/// shape <name> sdf <sdf> <min> <max> <mat>   # <min> <max> 是包含整个物体的包围盒
+/// shape <name> heightfield image <path> <corner> <size> <mat>               # 灰度图作为高度，图片上方对应 +Z 方向
+/// shape <name> heightfield noise <frequency> <nx> <nz> <corner> <size> <mat>  # 使用 Perlin 湍流生成的高度
+/// shape <name> triangle <p0> <p1> <p2> [uv <u0 v0> <u1 v1> <u2 v2>] <mat>
+/// shape <name> obj <path> [<mat>]            # 没有指定材质的面使用 <mat>，默认为灰色的 lambertian
+/// shape <name> ply <path> [<mat>]            # 默认材质为 lambertian，有顶点颜色时使用顶点颜色
+/// shape <name> rotate_y <shape> <degree>
+/// shape <name> translate <shape> <x y z>
+/// shape <name> transform <shape> <op>...     # 按顺序依次应用变换，op 见下
+/// shape <name> instance <shape> [mat <mat>] [<op>...]   # 共享 <shape> 的几何数据，可以覆盖材质
+/// shape <name> moving <shape> <time0> <time1> [<op>...] to [<op>...]   # 两个时刻的变换，中间的时刻进行插值
+/// shape <name> union|intersection|difference <a> <b>   # 两个封闭形状的布尔运算，可以嵌套
+/// shape <name> flip <shape>
+/// shape <name> medium <boundary> <density> <tex>
+/// shape <name> list <shape>...
+/// shape <name> bvh <shape>...                # 没有包围盒的形状（例如 plane）不放入 BVH，单独求交
+///
+/// add <shape>...                              # 放入场景中
+/// light <shape>...                            # 作为重要性采样的目标
+/// ```
+///
+/// transform 支持的变换：`translate <x y z>`，`scale <x y z>`，`rotate <axis x y z> <degree>`，
+/// `rotate_x|rotate_y|rotate_z <degree>`，`matrix <16 个数，按行排列>`。
+/// 相机的 `shutter <open> <close>` 是快门打开的时间段，和运动的物体一起产生运动模糊。
+///
+/// 其中 `<tex>` 既可以是纹理的名字，也可以直接写出颜色 `r g b`。
+/// 对 obj 形状使用 `light` 时，作为采样目标的是模型中发光的面。
+pub fn parse_scene(src: &str, file: &str, base_dir: &Path) -> Result<Scene, SceneError>
+{
+    let mut parser = Parser::new(base_dir);
+
+    for (idx, line) in src.lines().enumerate() {
+        let to_error = |msg: String| SceneError {
+            file: file.to_string(),
+            line: idx + 1,
+            entry: line.trim().to_string(),
+            msg,
+        };
+
+        let tokens = tokenize(line).map_err(to_error)?;
+        if tokens.is_empty() { continue; }
+
+        parser.entry(Tokens::new(tokens)).map_err(to_error)?;
+    }
+
+    parser.finish().map_err(|msg| SceneError {
+        file: file.to_string(),
+        line: 0,
+        entry: String::new(),
+        msg,
+    })
+}
+
+
+/// 将一行拆分为若干 token，去掉注释，支持用双引号括起来的 token
+fn tokenize(line: &str) -> Result<Vec<String>, String>
+{
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '#' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err("unterminated quote".to_string()),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '#' { break; }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    Ok(tokens)
+}
+
+
+/// 一个条目中的 token 序列
+struct Tokens
+{
+    items: Vec<String>,
+    pos: usize,
+}
+
+
+impl Tokens
+{
+    fn new(items: Vec<String>) -> Tokens { Tokens { items, pos: 0 } }
+
+    fn peek(&self) -> Option<&str> { self.items.get(self.pos).map(|s| s.as_str()) }
+
+    fn is_empty(&self) -> bool { self.pos >= self.items.len() }
+
+    fn word(&mut self, what: &str) -> Result<String, String>
+    {
+        let res = self.items.get(self.pos).cloned().ok_or(format!("missing {}", what))?;
+        self.pos += 1;
+        Ok(res)
+    }
+
+    fn float(&mut self, what: &str) -> Result<Float, String>
+    {
+        let word = self.word(what)?;
+        match word.parse::<Float>() {
+            Ok(val) if val.is_finite() => Ok(val),
+            _ => Err(format!("expect a number for {}, found `{}`", what, word)),
+        }
+    }
+
+    fn u32(&mut self, what: &str) -> Result<u32, String>
+    {
+        let word = self.word(what)?;
+        word.parse::<u32>().map_err(|_| format!("expect a positive integer for {}, found `{}`", what, word))
+    }
+
+    fn vec2(&mut self, what: &str) -> Result<Vec2, String>
+    {
+        Ok(vec2(self.float(what)?, self.float(what)?))
+    }
+
+    fn vec3(&mut self, what: &str) -> Result<Vec3, String>
+    {
+        Ok(vec3(self.float(what)?, self.float(what)?, self.float(what)?))
+    }
+
+    /// 确保所有的 token 都已经被使用
+    fn end(&self) -> Result<(), String>
+    {
+        match self.peek() {
+            None => Ok(()),
+            Some(word) => Err(format!("unexpected `{}`", word)),
+        }
+    }
+}
+
+
+struct Parser<'a>
+{
+    base_dir: &'a Path,
+
+    textures: HashMap<String, SharedTexture>,
+    materials: HashMap<String, SharedMaterial>,
+    shapes: HashMap<String, SharedHittable>,
+    sdfs: HashMap<String, SharedSdf>,
+
+    /// obj 形状中发光的三角形，key 是形状的名字
+    emitters: HashMap<String, Vec<SharedHittable>>,
+
+    world: HittableList,
+    lights: Vec<SharedHittable>,
+
+    camera: Option<Camera>,
+    background: Background,
+    quality: Option<(u32, u32)>,
+}
+
+
+impl<'a> Parser<'a>
+{
+    fn new(base_dir: &'a Path) -> Parser<'a>
+    {
+        Parser {
+            base_dir,
+            textures: HashMap::new(),
+            materials: HashMap::new(),
+            shapes: HashMap::new(),
+            sdfs: HashMap::new(),
+            emitters: HashMap::new(),
+            world: HittableList::default(),
+            lights: Vec::new(),
+            camera: None,
+            background: Background::Sky,
+            quality: None,
+        }
+    }
+
+
+    fn entry(&mut self, mut tokens: Tokens) -> Result<(), String>
+    {
+        let keyword = tokens.word("keyword")?;
+
+        match keyword.as_str() {
+            "camera" => self.camera(&mut tokens)?,
+            "renderer" => self.renderer(&mut tokens)?,
+            "background" => self.background(&mut tokens)?,
+            "texture" => {
+                let name = tokens.word("texture name")?;
+                let texture = self.texture_def(&mut tokens)?;
+                insert_unique(&mut self.textures, name, texture, "texture")?;
+            }
+            "material" => {
+                let name = tokens.word("material name")?;
+                let material = self.material_def(&mut tokens)?;
+                insert_unique(&mut self.materials, name, material, "material")?;
+            }
+            "sdf" => {
+                let name = tokens.word("sdf name")?;
+                let sdf = self.sdf_def(&mut tokens)?;
+                insert_unique(&mut self.sdfs, name, sdf, "sdf")?;
+            }
+            "shape" => {
+                let name = tokens.word("shape name")?;
+                let shape = if tokens.peek() == Some("obj") {
+                    let (shape, emitters) = self.obj_def(&mut tokens)?;
+                    if !emitters.is_empty() {
+                        self.emitters.insert(name.clone(), emitters);
+                    }
+                    shape
+                } else {
+                    self.shape_def(&mut tokens)?
+                };
+                insert_unique(&mut self.shapes, name, shape, "shape")?;
+            }
+            "add" => {
+                for shape in self.shape_refs(&mut tokens)? {
+                    self.world.add(shape);
+                }
+            }
+            "light" => {
+                let mut shapes = self.light_refs(&mut tokens)?;
+                self.lights.append(&mut shapes);
+            }
+            _ => return Err(format!("unknown entry `{}`", keyword)),
+        }
+
+        tokens.end()
+    }
+
+
+    fn finish(self) -> Result<Scene, String>
+    {
+        let camera = self.camera.ok_or("missing camera entry".to_string())?;
+
+        let lights: Option<SharedHittable> = match self.lights.len() {
+            0 => None,
+            1 => Some(self.lights[0].clone()),
+            _ => {
+                let mut lights = HittableList::default();
+                for light in self.lights {
+                    lights.add(light);
+                }
+                Some(Arc::new(lights))
+            }
+        };
+
+        let mut scene = Scene::new(Arc::new(self.world), camera, lights);
+        scene.background = self.background;
+        scene.quality = self.quality;
+        Ok(scene)
+    }
+
+
+    /// camera 由一系列 key value 组成，其中 from 和 at 是必须的
+    fn camera(&mut self, tokens: &mut Tokens) -> Result<(), String>
+    {
+        let mut from = None;
+        let mut at = None;
+        let mut up = vec3(0.0, 1.0, 0.0);
+        let mut vfov = 40.0;
+        let mut aspect = 1.0;
+        let mut aperture = 0.0;
+        let mut focus = 10.0;
+        let mut shutter = (0.0, 0.0);
+
+        while !tokens.is_empty() {
+            let key = tokens.word("camera key")?;
+            match key.as_str() {
+                "from" => from = Some(tokens.vec3("from")?),
+                "at" => at = Some(tokens.vec3("at")?),
+                "up" => up = tokens.vec3("up")?,
+                "vfov" => vfov = tokens.float("vfov")?,
+                "aspect" => aspect = tokens.float("aspect")?,
+                "aperture" => aperture = tokens.float("aperture")?,
+                "focus" => focus = tokens.float("focus")?,
+                "shutter" => shutter = (tokens.float("shutter open")?, tokens.float("shutter close")?),
+                _ => return Err(format!("unknown camera key `{}`", key)),
+            }
+        }
+
+        let from = from.ok_or("camera needs `from`".to_string())?;
+        let at = at.ok_or("camera needs `at`".to_string())?;
+        if aspect <= 0.0 || vfov <= 0.0 || vfov >= 180.0 {
+            return Err("camera needs aspect > 0 and 0 < vfov < 180".to_string());
+        }
+
+        if shutter.0 > shutter.1 {
+            return Err("camera shutter must be (open, close)".to_string());
+        }
+
+        let mut camera = Camera::new(from, at, up, vfov, aspect, aperture, focus);
+        camera.set_shutter(shutter.0, shutter.1);
+        self.camera = Some(camera);
+        Ok(())
+    }
+
+
+    fn renderer(&mut self, tokens: &mut Tokens) -> Result<(), String>
+    {
+        let (mut samples, mut depth) = self.quality.unwrap_or((32, 32));
+
+        while !tokens.is_empty() {
+            let key = tokens.word("renderer key")?;
+            match key.as_str() {
+                "samples" => samples = tokens.u32("samples")?,
+                "depth" => depth = tokens.u32("depth")?,
+                _ => return Err(format!("unknown renderer key `{}`", key)),
+            }
+        }
+
+        if samples == 0 {
+            return Err("samples must be greater than 0".to_string());
+        }
+
+        self.quality = Some((samples, depth));
+        Ok(())
+    }
+
+
+    fn background(&mut self, tokens: &mut Tokens) -> Result<(), String>
+    {
+        let kind = tokens.word("background kind")?;
+        self.background = match kind.as_str() {
+            "sky" => Background::Sky,
+            "color" => Background::Color(tokens.vec3("background color")?),
+            _ => return Err(format!("unknown background `{}`", kind)),
+        };
+        Ok(())
+    }
+
+
+    fn texture_def(&mut self, tokens: &mut Tokens) -> Result<SharedTexture, String>
+    {
+        let kind = tokens.word("texture kind")?;
+
+        let texture: SharedTexture = match kind.as_str() {
+            "solid" => Arc::new(SolidColor::new(tokens.vec3("color")?)),
+            "checker" => {
+                let odd = self.texture_ref(tokens)?;
+                let even = self.texture_ref(tokens)?;
+                Arc::new(CheckerTexture::new(odd, even))
+            }
+            "image" => {
+                let path = self.base_dir.join(tokens.word("image path")?);
+                Arc::new(ImageTexture::load(&path.to_string_lossy())?)
+            }
+            "noise" => Arc::new(NoiseTexture::new(tokens.float("noise scale")?)),
+            "vertex_color" => match tokens.is_empty() {
+                true => Arc::new(VertexColorTexture::new_c(vec3(0.73, 0.73, 0.73))),
+                false => Arc::new(VertexColorTexture::new(self.texture_ref(tokens)?)),
+            },
+            _ => return Err(format!("unknown texture kind `{}`", kind)),
+        };
+
+        Ok(texture)
+    }
+
+
+    /// 引用一个纹理：可以是纹理的名字，也可以直接是 `r g b` 颜色
+    fn texture_ref(&mut self, tokens: &mut Tokens) -> Result<SharedTexture, String>
+    {
+        let is_color = tokens.peek().is_some_and(|word| word.parse::<Float>().is_ok());
+        if is_color {
+            return Ok(Arc::new(SolidColor::new(tokens.vec3("color")?)));
+        }
+
+        let name = tokens.word("texture")?;
+        self.textures.get(&name).cloned().ok_or(format!("unknown texture `{}`", name))
+    }
+
+
+    fn material_def(&mut self, tokens: &mut Tokens) -> Result<SharedMaterial, String>
+    {
+        let kind = tokens.word("material kind")?;
+
+        let material: SharedMaterial = match kind.as_str() {
+            "lambertian" => Arc::new(Lambertian::new_t(self.texture_ref(tokens)?)),
+            "metal" => {
+                let albedo = tokens.vec3("metal albedo")?;
+                let fuzz = tokens.float("metal fuzz")?;
+                Arc::new(Metal::new(albedo, fuzz))
+            }
+            "dielectric" => Arc::new(Dielecric::new(tokens.float("index of refraction")?)),
+            "emit" => Arc::new(DiffuseEmit::new(self.texture_ref(tokens)?)),
+            _ => return Err(format!("unknown material kind `{}`", kind)),
+        };
+
+        Ok(material)
+    }
+
+
+    fn material_ref(&self, tokens: &mut Tokens) -> Result<SharedMaterial, String>
+    {
+        let name = tokens.word("material")?;
+        self.materials.get(&name).cloned().ok_or(format!("unknown material `{}`", name))
+    }
+
+
+    fn sdf_def(&mut self, tokens: &mut Tokens) -> Result<SharedSdf, String>
+    {
+        let kind = tokens.word("sdf kind")?;
+
+        let sdf: SharedSdf = match kind.as_str() {
+            "sphere" => {
+                let center = tokens.vec3("sphere center")?;
+                let radius = tokens.float("sphere radius")?;
+                if radius <= 0.0 {
+                    return Err("sphere radius must be greater than 0".to_string());
+                }
+                Arc::new(SdfSphere::new(center, radius))
+            }
+            "box" => {
+                let center = tokens.vec3("box center")?;
+                let half = tokens.vec3("box half size")?;
+                if half.x <= 0.0 || half.y <= 0.0 || half.z <= 0.0 {
+                    return Err("box size must be greater than 0".to_string());
+                }
+                Arc::new(SdfBox::new(center, half))
+            }
+            "torus" => {
+                let center = tokens.vec3("torus center")?;
+                let major = tokens.float("torus major radius")?;
+                let minor = tokens.float("torus minor radius")?;
+                if major <= 0.0 || minor <= 0.0 {
+                    return Err("torus radius must be greater than 0".to_string());
+                }
+                Arc::new(SdfTorus::new(center, major, minor))
+            }
+            "mandelbulb" => {
+                let power = tokens.float("mandelbulb power")?;
+                let iterations = tokens.u32("mandelbulb iterations")?;
+                if power <= 1.0 || iterations == 0 {
+                    return Err("mandelbulb needs power > 1 and iterations > 0".to_string());
+                }
+                Arc::new(Mandelbulb::new(power, iterations))
+            }
+            "smooth_union" => {
+                let a = self.sdf_ref(tokens)?;
+                let b = self.sdf_ref(tokens)?;
+                let k = tokens.float("smooth size")?;
+                if k <= 0.0 {
+                    return Err("smooth size must be greater than 0".to_string());
+                }
+                Arc::new(SmoothUnion::new(a, b, k))
+            }
+            "subtract" => {
+                let a = self.sdf_ref(tokens)?;
+                let b = self.sdf_ref(tokens)?;
+                Arc::new(Subtraction::new(a, b))
+            }
+            "repeat" => {
+                let sdf = self.sdf_ref(tokens)?;
+                let period = tokens.vec3("repeat period")?;
+                if period.x < 0.0 || period.y < 0.0 || period.z < 0.0 {
+                    return Err("repeat period can not be negative".to_string());
+                }
+                Arc::new(Repeat::new(sdf, period))
+            }
+            "twist" => {
+                let sdf = self.sdf_ref(tokens)?;
+                Arc::new(Twist::new(sdf, glm::radians(tokens.float("twist degree")?)))
+            }
+            _ => return Err(format!("unknown sdf kind `{}`", kind)),
+        };
+
+        Ok(sdf)
+    }
+
+
+    fn sdf_ref(&self, tokens: &mut Tokens) -> Result<SharedSdf, String>
+    {
+        let name = tokens.word("sdf")?;
+        self.sdfs.get(&name).cloned().ok_or(format!("unknown sdf `{}`", name))
+    }
+
+
+    fn shape_ref(&self, tokens: &mut Tokens) -> Result<SharedHittable, String>
+    {
+        let name = tokens.word("shape")?;
+        self.shapes.get(&name).cloned().ok_or(format!("unknown shape `{}`", name))
+    }
+
+
+    /// 读取剩余的所有 token 作为形状的名字
+    fn shape_refs(&self, tokens: &mut Tokens) -> Result<Vec<SharedHittable>, String>
+    {
+        let mut shapes = Vec::new();
+        while !tokens.is_empty() {
+            shapes.push(self.shape_ref(tokens)?);
+        }
+
+        if shapes.is_empty() {
+            return Err("expect at least one shape".to_string());
+        }
+        Ok(shapes)
+    }
+
+
+    /// 读取剩余的所有 token 作为光源的名字，obj 形状会被替换为其中发光的三角形
+    fn light_refs(&self, tokens: &mut Tokens) -> Result<Vec<SharedHittable>, String>
+    {
+        let mut lights = Vec::new();
+        while !tokens.is_empty() {
+            match tokens.peek().and_then(|name| self.emitters.get(name)) {
+                Some(emitters) => {
+                    lights.extend(emitters.iter().cloned());
+                    tokens.word("shape")?;
+                }
+                None => lights.push(self.shape_ref(tokens)?),
+            }
+        }
+
+        if lights.is_empty() {
+            return Err("expect at least one shape".to_string());
+        }
+        Ok(lights)
+    }
+
+
+    /// 读取 obj 模型，返回模型的 BVH 以及其中发光的三角形
+    fn obj_def(&mut self, tokens: &mut Tokens) -> Result<(SharedHittable, Vec<SharedHittable>), String>
+    {
+        tokens.word("shape kind")?;
+        let path = self.base_dir.join(tokens.word("obj path")?);
+        let default_mat = match tokens.is_empty() {
+            true => Arc::new(Lambertian::new(vec3(0.73, 0.73, 0.73))),
+            false => self.material_ref(tokens)?,
+        };
+
+        let model = load_obj(&path.to_string_lossy(), default_mat).map_err(|err| err.to_string())?;
+        let bvh = model.to_bvh().ok_or("obj model has no triangles".to_string())?;
+        Ok((Arc::new(bvh), model.lights()))
+    }
+
+
+    fn shape_def(&mut self, tokens: &mut Tokens) -> Result<SharedHittable, String>
+    {
+        let kind = tokens.word("shape kind")?;
+
+        let shape: SharedHittable = match kind.as_str() {
+            "sphere" => {
+                let center = tokens.vec3("sphere center")?;
+                let radius = tokens.float("sphere radius")?;
+                if radius == 0.0 {
+                    return Err("sphere radius can not be 0".to_string());
+                }
+                Arc::new(Sphere::new(center, radius, self.material_ref(tokens)?))
+            }
+            "moving_sphere" => {
+                let center0 = tokens.vec3("sphere center")?;
+                let time0 = tokens.float("time")?;
+                let center1 = tokens.vec3("sphere center")?;
+                let time1 = tokens.float("time")?;
+                let radius = tokens.float("sphere radius")?;
+                if radius == 0.0 {
+                    return Err("sphere radius can not be 0".to_string());
+                }
+                if time0 >= time1 {
+                    return Err("moving sphere needs time0 < time1".to_string());
+                }
+                Arc::new(MovingSphere::new(center0, time0, center1, time1, radius, self.material_ref(tokens)?))
+            }
+            "rect" => {
+                let axis = match tokens.word("rect axis")?.as_str() {
+                    "x" => Axis::X,
+                    "y" => Axis::Y,
+                    "z" => Axis::Z,
+                    other => return Err(format!("unknown axis `{}`", other)),
+                };
+                let p0 = tokens.vec2("rect corner")?;
+                let p1 = tokens.vec2("rect corner")?;
+                let k = tokens.float("rect position")?;
+                if p0.x >= p1.x || p0.y >= p1.y {
+                    return Err("rect corners must be (min, max)".to_string());
+                }
+                Arc::new(AxisRect::new(p0, p1, k, self.material_ref(tokens)?, axis))
+            }
+            "quad" => {
+                let q = tokens.vec3("quad corner")?;
+                let u = tokens.vec3("quad edge")?;
+                let v = tokens.vec3("quad edge")?;
+                if glm::length(glm::cross(u, v)) == 0.0 {
+                    return Err("degenerate quad".to_string());
+                }
+                Arc::new(Quad::new(q, u, v, self.material_ref(tokens)?))
+            }
+            "disk" => {
+                let center = tokens.vec3("disk center")?;
+                let radius = tokens.float("disk radius")?;
+                if radius <= 0.0 {
+                    return Err("disk radius must be greater than 0".to_string());
+                }
+                Arc::new(Disk::new(center, radius, self.material_ref(tokens)?))
+            }
+            "cylinder" | "cone" => {
+                let base = tokens.vec3("base center")?;
+                let radius = tokens.float("radius")?;
+                let height = tokens.float("height")?;
+                if radius <= 0.0 || height <= 0.0 {
+                    return Err(format!("{} radius and height must be greater than 0", kind));
+                }
+                let capped = tokens.peek() == Some("capped");
+                if capped {
+                    tokens.word("capped")?;
+                }
+
+                let mat = self.material_ref(tokens)?;
+                match kind.as_str() {
+                    "cylinder" => Arc::new(Cylinder::new(base, radius, height, capped, mat)),
+                    _ => Arc::new(Cone::new(base, radius, height, capped, mat)),
+                }
+            }
+            "torus" => {
+                let center = tokens.vec3("torus center")?;
+                let major = tokens.float("torus major radius")?;
+                let minor = tokens.float("torus minor radius")?;
+                if minor <= 0.0 || major <= minor {
+                    return Err("torus radius must satisfy 0 < minor < major".to_string());
+                }
+                Arc::new(Torus::new(center, major, minor, self.material_ref(tokens)?))
+            }
+            "sdf" => {
+                let sdf = self.sdf_ref(tokens)?;
+                let p0 = tokens.vec3("sdf bound")?;
+                let p1 = tokens.vec3("sdf bound")?;
+                if p0.x >= p1.x || p0.y >= p1.y || p0.z >= p1.z {
+                    return Err("sdf bound must be (min, max)".to_string());
+                }
+                Arc::new(SdfShape::new(sdf, AABB::new(p0, p1), self.material_ref(tokens)?))
+            }
+            "heightfield" => {
+                let source = tokens.word("heightfield source")?;
+                match source.as_str() {
+                    "image" => {
+                        let path = self.base_dir.join(tokens.word("heightfield image path")?);
+                        let (corner, size) = heightfield_extent(tokens)?;
+                        Arc::new(Heightfield::load(&path.to_string_lossy(), corner, size, self.material_ref(tokens)?)?)
+                    }
+                    "noise" => {
+                        let frequency = tokens.float("noise frequency")?;
+                        let nx = tokens.u32("heightfield resolution")? as usize;
+                        let nz = tokens.u32("heightfield resolution")? as usize;
+                        if nx < 2 || nz < 2 {
+                            return Err("heightfield resolution must be at least 2".to_string());
+                        }
+                        let (corner, size) = heightfield_extent(tokens)?;
+                        Arc::new(Heightfield::new_noise(&Perlin::new(), frequency, (nx, nz), corner, size, self.material_ref(tokens)?))
+                    }
+                    other => return Err(format!("unknown heightfield source `{}`", other)),
+                }
+            }
+            "plane" => {
+                let point = tokens.vec3("plane point")?;
+                let normal = tokens.vec3("plane normal")?;
+                if glm::length(normal) == 0.0 {
+                    return Err("plane normal can not be 0".to_string());
+                }
+                let mut tile = 1.0;
+                if tokens.peek() == Some("tile") {
+                    tokens.word("tile")?;
+                    tile = tokens.float("plane tile")?;
+                    if tile <= 0.0 {
+                        return Err("plane tile must be greater than 0".to_string());
+                    }
+                }
+                Arc::new(Plane::new_uv(point, normal, tile, self.material_ref(tokens)?))
+            }
+            "cube" => {
+                let p0 = tokens.vec3("cube corner")?;
+                let p1 = tokens.vec3("cube corner")?;
+                if p0.x >= p1.x || p0.y >= p1.y || p0.z >= p1.z {
+                    return Err("cube corners must be (min, max)".to_string());
+                }
+                Arc::new(Cube::new(p0, p1, self.material_ref(tokens)?))
+            }
+            "triangle" => {
+                let p0 = tokens.vec3("triangle vertex")?;
+                let p1 = tokens.vec3("triangle vertex")?;
+                let p2 = tokens.vec3("triangle vertex")?;
+                if glm::length(glm::cross(p1 - p0, p2 - p0)) == 0.0 {
+                    return Err("degenerate triangle".to_string());
+                }
+
+                let mut uv = [vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0)];
+                if tokens.peek() == Some("uv") {
+                    tokens.word("uv")?;
+                    for vertex_uv in &mut uv {
+                        *vertex_uv = tokens.vec2("triangle uv")?;
+                    }
+                }
+                Arc::new(Triangle::new_uv(p0, p1, p2, uv, self.material_ref(tokens)?))
+            }
+            "ply" => {
+                let path = self.base_dir.join(tokens.word("ply path")?);
+                let mat: SharedMaterial = match tokens.is_empty() {
+                    true => Arc::new(Lambertian::new_t(Arc::new(VertexColorTexture::new_c(vec3(0.73, 0.73, 0.73))))),
+                    false => self.material_ref(tokens)?,
+                };
+
+                let mesh = load_ply(&path.to_string_lossy(), mat).map_err(|err| err.to_string())?;
+                Arc::new(Arc::new(mesh).to_bvh())
+            }
+            "rotate_y" => {
+                let obj = self.shape_ref(tokens)?;
+                Arc::new(RotateY::new(obj, tokens.float("rotate degree")?))
+            }
+            "translate" => {
+                let obj = self.shape_ref(tokens)?;
+                Arc::new(Translate::new(obj, tokens.vec3("offset")?))
+            }
+            "transform" => {
+                let obj = self.shape_ref(tokens)?;
+                if tokens.is_empty() {
+                    return Err("expect at least one transform".to_string());
+                }
+                let matrix = transform_ops(tokens)?;
+                if affine_inverse(&matrix).is_none() {
+                    return Err("transform matrix is not invertible".to_string());
+                }
+                Arc::new(Transform::new(obj, matrix))
+            }
+            "instance" => {
+                let prototype = self.shape_ref(tokens)?;
+                let mut mat = None;
+                if tokens.peek() == Some("mat") {
+                    tokens.word("mat")?;
+                    mat = Some(self.material_ref(tokens)?);
+                }
+                let matrix = transform_ops(tokens)?;
+                if affine_inverse(&matrix).is_none() {
+                    return Err("instance matrix is not invertible".to_string());
+                }
+                Arc::new(Instance::new(prototype, matrix, mat))
+            }
+            "moving" => {
+                let obj = self.shape_ref(tokens)?;
+                let time0 = tokens.float("time")?;
+                let time1 = tokens.float("time")?;
+                if time0 > time1 {
+                    return Err("moving needs time0 <= time1".to_string());
+                }
+
+                let matrix0 = transform_ops(tokens)?;
+                if tokens.word("`to`")? != "to" {
+                    return Err("expect `to` between the two transforms".to_string());
+                }
+                let matrix1 = transform_ops(tokens)?;
+                if affine_inverse(&matrix0).is_none() || affine_inverse(&matrix1).is_none() {
+                    return Err("moving matrix is not invertible".to_string());
+                }
+                Arc::new(MovingTransform::new(obj, matrix0, time0, matrix1, time1))
+            }
+            "union" | "intersection" | "difference" => {
+                let op = match kind.as_str() {
+                    "union" => CsgOp::Union,
+                    "intersection" => CsgOp::Intersection,
+                    _ => CsgOp::Difference,
+                };
+                let a = self.shape_ref(tokens)?;
+                let b = self.shape_ref(tokens)?;
+                Arc::new(Csg::new(op, a, b))
+            }
+            "flip" => Arc::new(FlipFace::new(self.shape_ref(tokens)?)),
+            "medium" => {
+                let boundary = self.shape_ref(tokens)?;
+                let density = tokens.float("medium density")?;
+                if density <= 0.0 {
+                    return Err("medium density must be greater than 0".to_string());
+                }
+                Arc::new(ConstantMedium::new(boundary, density, self.texture_ref(tokens)?))
+            }
+            "list" => {
+                let mut list = HittableList::default();
+                for obj in self.shape_refs(tokens)? {
+                    list.add(obj);
+                }
+                Arc::new(list)
+            }
+            "bvh" => {
+                Arc::new(BVHNode::new(&self.shape_refs(tokens)?))
+            }
+            _ => return Err(format!("unknown shape kind `{}`", kind)),
+        };
+
+        Ok(shape)
+    }
+}
+
+
+/// 读取高度场的 <corner> <size>
+fn heightfield_extent(tokens: &mut Tokens) -> Result<(Vec3, Vec3), String>
+{
+    let corner = tokens.vec3("heightfield corner")?;
+    let size = tokens.vec3("heightfield size")?;
+    if size.x <= 0.0 || size.y < 0.0 || size.z <= 0.0 {
+        return Err("heightfield size must be positive".to_string());
+    }
+    Ok((corner, size))
+}
+
+
+/// 读取剩余的所有变换（遇到 `to` 时停止），组合为一个矩阵，先写出的变换先作用于物体；没有变换时为单位矩阵
+fn transform_ops(tokens: &mut Tokens) -> Result<Mat4, String>
+{
+    let identity = Mat4::one();
+    let mut matrix = identity;
+
+    while !tokens.is_empty() && tokens.peek() != Some("to") {
+        let op = tokens.word("transform")?;
+        let op_matrix = match op.as_str() {
+            "translate" => glm::ext::translate(&identity, tokens.vec3("offset")?),
+            "scale" => glm::ext::scale(&identity, tokens.vec3("scale")?),
+            "rotate" => {
+                let axis = tokens.vec3("rotate axis")?;
+                if glm::length(axis) == 0.0 {
+                    return Err("rotate axis can not be zero".to_string());
+                }
+                glm::ext::rotate(&identity, glm::radians(tokens.float("rotate degree")?), axis)
+            }
+            "rotate_x" => glm::ext::rotate(&identity, glm::radians(tokens.float("rotate degree")?), vec3(1.0, 0.0, 0.0)),
+            "rotate_y" => glm::ext::rotate(&identity, glm::radians(tokens.float("rotate degree")?), vec3(0.0, 1.0, 0.0)),
+            "rotate_z" => glm::ext::rotate(&identity, glm::radians(tokens.float("rotate degree")?), vec3(0.0, 0.0, 1.0)),
+            "matrix" => {
+                let mut m = [0.0; 16];
+                for val in &mut m {
+                    *val = tokens.float("matrix element")?;
+                }
+                if m[12..16] != [0.0, 0.0, 0.0, 1.0] {
+                    return Err("the last row of matrix must be `0 0 0 1`".to_string());
+                }
+                // 输入按行排列，glm 的矩阵按列存储
+                Mat4::new(vec4(m[0], m[4], m[8], m[12]),
+                          vec4(m[1], m[5], m[9], m[13]),
+                          vec4(m[2], m[6], m[10], m[14]),
+                          vec4(m[3], m[7], m[11], m[15]))
+            }
+            _ => return Err(format!("unknown transform `{}`", op)),
+        };
+        matrix = op_matrix * matrix;
+    }
+
+    Ok(matrix)
+}
+
+
+fn insert_unique<T>(map: &mut HashMap<String, T>, name: String, value: T, what: &str) -> Result<(), String>
+{
+    if map.contains_key(&name) {
+        return Err(format!("duplicate {} name `{}`", what, name));
+    }
+    map.insert(name, value);
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+
+    #[test]
+    fn test_parse_scene()
+    {
+        let src = r#"
+            # 一个简单的场景
+            camera from 0 0 -5 at 0 0 0 aspect 2 shutter 0 1
+            renderer samples 4 depth 8
+            background color 0 0 0
+
+            texture checker checker 0.2 0.3 0.1 0.9 0.9 0.9
+            material ground lambertian checker
+            material light emit 4 4 4
+            shape ball sphere 0 0 0 1 ground
+            shape lamp rect y -1 -1 1 1 3 light
+            shape blur moving_sphere 2 0 0 0 2 1 0 1 0.5 ground
+            shape swing moving ball 0 1 to translate 0 0 2 scale 0.5 0.5 0.5
+            shape hills heightfield noise 4 9 9 -4 -3 -4 8 1 8 ground
+            add ball lamp blur swing hills
+            light lamp
+        "#;
+
+        let scene = parse_scene(src, "test.scene", Path::new("")).unwrap();
+        assert_eq!(scene.quality, Some((4, 8)));
+        assert_eq!(scene.camera.aspect(), 2.0);
+        assert_eq!(scene.camera.shutter(), (0.0, 1.0));
+        assert!(scene.lights.is_some());
+        assert!(scene.world.bounding_box().is_some());
+    }
+
+    #[test]
+    fn test_parse_error()
+    {
+        let src = "camera from 0 0 -5 at 0 0 0\nmaterial white lambertian 1 1\n";
+
+        let err = parse_scene(src, "bad.scene", Path::new("")).err().unwrap();
+        assert_eq!(err.line, 2);
+        assert_eq!(err.entry, "material white lambertian 1 1");
+        assert!(err.to_string().starts_with("bad.scene:2:"));
+    }
+}
@@ -0,0 +1,626 @@
+use std::sync::Arc;
+
+use crate::geom::mesh::TriangleMesh;
+use crate::material::Material;
+use crate::scene::SceneError;
+use crate::float::{Float, Vec2, Vec3, vec2, vec3};
+
+
+/// 读取 PLY 文件，得到三角形网格
+///
+/// - 支持 ascii、binary_little_endian、binary_big_endian 三种编码
+/// - vertex 元素：位置 `x y z`，法线 `nx ny nz`，纹理坐标 `u v`（或者 `s t`、`texture_u texture_v`），
+///   顶点颜色 `red green blue`；整数类型的颜色会被归一化到 [0, 1]
+/// - face 元素：`vertex_indices`（或者 `vertex_index`）列表，多边形会被分解为三角形
+/// - 其他的元素和属性会被跳过
+pub fn load_ply(path: &str, mat: Arc<dyn Material + Send + Sync>) -> Result<TriangleMesh, SceneError>
+{
+    let data = std::fs::read(path).map_err(|err| SceneError {
+        file: path.to_string(),
+        line: 0,
+        entry: String::new(),
+        msg: format!("can not read ply file: {}", err),
+    })?;
+
+    parse_ply(&data, path, mat)
+}
+
+
+/// 解析 PLY 文件的内容，file 只用于错误信息
+pub fn parse_ply(data: &[u8], file: &str, mat: Arc<dyn Material + Send + Sync>) -> Result<TriangleMesh, SceneError>
+{
+    let to_error = |line: usize, entry: &str, msg: String| SceneError {
+        file: file.to_string(),
+        line,
+        entry: entry.trim().to_string(),
+        msg,
+    };
+
+    let header = parse_header(data).map_err(|(line, entry, msg)| to_error(line, &entry, msg))?;
+
+    let mut body = match header.format {
+        Format::Ascii => {
+            let text = std::str::from_utf8(&data[header.body_offset..])
+                .map_err(|_| to_error(0, "", "ascii ply body is not valid text".to_string()))?;
+            Body::Ascii { lines: text.lines(), line: header.lines, text: "", tokens: Vec::new(), pos: 0 }
+        }
+        Format::BinaryLittleEndian => Body::Binary { data, pos: header.body_offset, big_endian: false },
+        Format::BinaryBigEndian => Body::Binary { data, pos: header.body_offset, big_endian: true },
+    };
+
+    let mut builder = MeshData::default();
+    for element in &header.elements {
+        builder.read_element(element, &mut body)
+            .map_err(|msg| to_error(body.line(), body.entry(), msg))?;
+    }
+
+    builder.build(mat).map_err(|msg| to_error(0, "", msg))
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Format
+{
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum ScalarType
+{
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+
+impl ScalarType
+{
+    fn from_name(name: &str) -> Option<ScalarType>
+    {
+        match name {
+            "char" | "int8" => Some(ScalarType::I8),
+            "uchar" | "uint8" => Some(ScalarType::U8),
+            "short" | "int16" => Some(ScalarType::I16),
+            "ushort" | "uint16" => Some(ScalarType::U16),
+            "int" | "int32" => Some(ScalarType::I32),
+            "uint" | "uint32" => Some(ScalarType::U32),
+            "float" | "float32" => Some(ScalarType::F32),
+            "double" | "float64" => Some(ScalarType::F64),
+            _ => None,
+        }
+    }
+
+
+    fn size(self) -> usize
+    {
+        match self {
+            ScalarType::I8 | ScalarType::U8 => 1,
+            ScalarType::I16 | ScalarType::U16 => 2,
+            ScalarType::I32 | ScalarType::U32 | ScalarType::F32 => 4,
+            ScalarType::F64 => 8,
+        }
+    }
+
+
+    /// 颜色归一化时使用的最大值，浮点类型的颜色本身就在 [0, 1] 中
+    fn color_scale(self) -> f64
+    {
+        match self {
+            ScalarType::I8 => i8::MAX as f64,
+            ScalarType::U8 => u8::MAX as f64,
+            ScalarType::I16 => i16::MAX as f64,
+            ScalarType::U16 => u16::MAX as f64,
+            ScalarType::I32 => i32::MAX as f64,
+            ScalarType::U32 => u32::MAX as f64,
+            ScalarType::F32 | ScalarType::F64 => 1.0,
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug)]
+enum PropertyKind
+{
+    Scalar(ScalarType),
+
+    /// (数量的类型, 元素的类型)
+    List(ScalarType, ScalarType),
+}
+
+
+struct Property
+{
+    name: String,
+    kind: PropertyKind,
+}
+
+
+struct Element
+{
+    name: String,
+    count: usize,
+    props: Vec<Property>,
+}
+
+
+struct Header
+{
+    format: Format,
+    elements: Vec<Element>,
+
+    /// header 占用的行数
+    lines: usize,
+
+    /// 数据部分在文件中的起始位置
+    body_offset: usize,
+}
+
+
+/// 解析文件头，出错时返回 (行号, 该行的内容, 错误信息)
+fn parse_header(data: &[u8]) -> Result<Header, (usize, String, String)>
+{
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut offset = 0;
+    let mut line_num = 0;
+
+    loop {
+        let end = match data[offset..].iter().position(|&b| b == b'\n') {
+            Some(end) => offset + end,
+            None => return Err((line_num, String::new(), "missing `end_header`".to_string())),
+        };
+        let line = String::from_utf8_lossy(&data[offset..end]).trim_end_matches('\r').to_string();
+        offset = end + 1;
+        line_num += 1;
+
+        let error = |msg: &str| (line_num, line.clone(), msg.to_string());
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+
+        if line_num == 1 {
+            if tokens != ["ply"] {
+                return Err(error("not a ply file"));
+            }
+            continue;
+        }
+
+        match tokens.first().copied() {
+            None | Some("comment") | Some("obj_info") => {}
+            Some("format") => {
+                format = Some(match tokens.get(1).copied() {
+                    Some("ascii") => Format::Ascii,
+                    Some("binary_little_endian") => Format::BinaryLittleEndian,
+                    Some("binary_big_endian") => Format::BinaryBigEndian,
+                    _ => return Err(error("unknown ply format")),
+                });
+            }
+            Some("element") => {
+                let count = match tokens[..] {
+                    [_, _, count] => count.parse::<usize>().map_err(|_| error("invalid element count"))?,
+                    _ => return Err(error("expect `element <name> <count>`")),
+                };
+                elements.push(Element { name: tokens[1].to_string(), count, props: Vec::new() });
+            }
+            Some("property") => {
+                let element = elements.last_mut().ok_or_else(|| error("property before element"))?;
+                let scalar = |name: &str| ScalarType::from_name(name).ok_or_else(|| error("unknown property type"));
+
+                let (kind, name) = match tokens[..] {
+                    [_, "list", count_ty, item_ty, name] => {
+                        let count_ty = scalar(count_ty)?;
+                        if matches!(count_ty, ScalarType::F32 | ScalarType::F64) {
+                            return Err(error("list count must be an integer type"));
+                        }
+                        (PropertyKind::List(count_ty, scalar(item_ty)?), name)
+                    }
+                    [_, ty, name] => (PropertyKind::Scalar(scalar(ty)?), name),
+                    _ => return Err(error("expect `property <type> <name>` or `property list <type> <type> <name>`")),
+                };
+                element.props.push(Property { name: name.to_string(), kind });
+            }
+            Some("end_header") => break,
+            Some(_) => return Err(error("unknown header entry")),
+        }
+    }
+
+    let format = format.ok_or((0, String::new(), "missing `format` in header".to_string()))?;
+    Ok(Header { format, elements, lines: line_num, body_offset: offset })
+}
+
+
+/// 文件的数据部分
+enum Body<'a>
+{
+    /// ascii 编码，每个元素占一行
+    Ascii
+    {
+        lines: std::str::Lines<'a>,
+
+        /// 当前元素所在的行号，以及这一行的内容
+        line: usize,
+        text: &'a str,
+        tokens: Vec<&'a str>,
+        pos: usize,
+    },
+
+    Binary
+    {
+        data: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    },
+}
+
+
+impl<'a> Body<'a>
+{
+    /// 开始读取一个元素
+    fn begin(&mut self) -> Result<(), String>
+    {
+        if let Body::Ascii { lines, line, text, tokens, pos } = self {
+            loop {
+                *text = lines.next().ok_or("unexpected end of file".to_string())?;
+                *line += 1;
+                *tokens = text.split_whitespace().collect();
+                *pos = 0;
+                if !tokens.is_empty() { break; }
+            }
+        }
+        Ok(())
+    }
+
+
+    /// 结束读取一个元素，ascii 编码中一行不能有多余的内容
+    fn end(&self) -> Result<(), String>
+    {
+        match self {
+            Body::Ascii { tokens, pos, .. } if *pos < tokens.len() => Err(format!("unexpected `{}`", tokens[*pos])),
+            _ => Ok(()),
+        }
+    }
+
+
+    fn read(&mut self, ty: ScalarType) -> Result<f64, String>
+    {
+        match self {
+            Body::Ascii { tokens, pos, .. } => {
+                let word = tokens.get(*pos).ok_or("too few values".to_string())?;
+                *pos += 1;
+
+                let val = match ty {
+                    ScalarType::F32 | ScalarType::F64 => word.parse::<f64>().ok(),
+                    _ => word.parse::<i64>().ok().map(|val| val as f64),
+                };
+                val.ok_or(format!("invalid {:?} value `{}`", ty, word))
+            }
+
+            Body::Binary { data, pos, big_endian } => {
+                let size = ty.size();
+                let bytes = data.get(*pos..*pos + size).ok_or("unexpected end of file".to_string())?;
+                *pos += size;
+
+                macro_rules! decode {
+                    ($t:ty) => {{
+                        let bytes = bytes.try_into().unwrap();
+                        (if *big_endian { <$t>::from_be_bytes(bytes) } else { <$t>::from_le_bytes(bytes) }) as f64
+                    }};
+                }
+
+                Ok(match ty {
+                    ScalarType::I8 => bytes[0] as i8 as f64,
+                    ScalarType::U8 => bytes[0] as f64,
+                    ScalarType::I16 => decode!(i16),
+                    ScalarType::U16 => decode!(u16),
+                    ScalarType::I32 => decode!(i32),
+                    ScalarType::U32 => decode!(u32),
+                    ScalarType::F32 => decode!(f32),
+                    ScalarType::F64 => decode!(f64),
+                })
+            }
+        }
+    }
+
+
+    /// 用于错误信息的行号，二进制编码没有行号
+    fn line(&self) -> usize
+    {
+        match self {
+            Body::Ascii { line, .. } => *line,
+            Body::Binary { .. } => 0,
+        }
+    }
+
+
+    /// 用于错误信息的当前行内容
+    fn entry(&self) -> &str
+    {
+        match self {
+            Body::Ascii { text, .. } => text,
+            Body::Binary { .. } => "",
+        }
+    }
+}
+
+
+/// vertex 元素中各个属性的用途
+#[derive(Clone, Copy, PartialEq)]
+enum VertexAttr
+{
+    Position(usize),
+    Normal(usize),
+    Uv(usize),
+    Color(usize),
+    Ignored,
+}
+
+
+impl VertexAttr
+{
+    fn from_name(name: &str) -> VertexAttr
+    {
+        match name {
+            "x" => VertexAttr::Position(0),
+            "y" => VertexAttr::Position(1),
+            "z" => VertexAttr::Position(2),
+            "nx" => VertexAttr::Normal(0),
+            "ny" => VertexAttr::Normal(1),
+            "nz" => VertexAttr::Normal(2),
+            "u" | "s" | "texture_u" | "texture_s" => VertexAttr::Uv(0),
+            "v" | "t" | "texture_v" | "texture_t" => VertexAttr::Uv(1),
+            "red" | "r" | "diffuse_red" => VertexAttr::Color(0),
+            "green" | "g" | "diffuse_green" => VertexAttr::Color(1),
+            "blue" | "b" | "diffuse_blue" => VertexAttr::Color(2),
+            _ => VertexAttr::Ignored,
+        }
+    }
+}
+
+
+#[derive(Default)]
+struct MeshData
+{
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<Vec2>,
+    colors: Vec<Vec3>,
+    indices: Vec<[u32; 3]>,
+}
+
+
+impl MeshData
+{
+    fn read_element(&mut self, element: &Element, body: &mut Body) -> Result<(), String>
+    {
+        match element.name.as_str() {
+            "vertex" => self.read_vertices(element, body),
+            "face" => self.read_faces(element, body),
+            _ => {
+                for _ in 0..element.count {
+                    body.begin()?;
+                    for prop in &element.props {
+                        read_property(prop, body, |_| Ok(()))?;
+                    }
+                    body.end()?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+
+    fn read_vertices(&mut self, element: &Element, body: &mut Body) -> Result<(), String>
+    {
+        let attrs: Vec<VertexAttr> = element.props.iter().map(|prop| VertexAttr::from_name(&prop.name)).collect();
+        let has_all = |f: fn(usize) -> VertexAttr, n: usize| (0..n).all(|i| attrs.contains(&f(i)));
+
+        if !has_all(VertexAttr::Position, 3) {
+            return Err("vertex element needs `x`, `y`, `z` properties".to_string());
+        }
+        let has_normal = has_all(VertexAttr::Normal, 3);
+        let has_uv = has_all(VertexAttr::Uv, 2);
+        let has_color = has_all(VertexAttr::Color, 3);
+
+        for idx in 0..element.count {
+            let mut position = vec3(0.0, 0.0, 0.0);
+            let mut normal = vec3(0.0, 0.0, 0.0);
+            let mut uv = vec2(0.0, 0.0);
+            let mut color = vec3(0.0, 0.0, 0.0);
+
+            body.begin()?;
+            for (prop, attr) in element.props.iter().zip(&attrs) {
+                let val = match prop.kind {
+                    PropertyKind::Scalar(ty) => body.read(ty)?,
+                    PropertyKind::List(..) => {
+                        read_property(prop, body, |_| Ok(()))?;
+                        continue;
+                    }
+                };
+
+                match *attr {
+                    VertexAttr::Position(i) => position[i] = val as Float,
+                    VertexAttr::Normal(i) => normal[i] = val as Float,
+                    VertexAttr::Uv(i) => uv[i] = val as Float,
+                    VertexAttr::Color(i) => {
+                        let scale = match prop.kind {
+                            PropertyKind::Scalar(ty) => ty.color_scale(),
+                            PropertyKind::List(..) => 1.0,
+                        };
+                        color[i] = (val / scale) as Float;
+                    }
+                    VertexAttr::Ignored => {}
+                }
+            }
+            body.end()?;
+
+            if !(position.x.is_finite() && position.y.is_finite() && position.z.is_finite()) {
+                return Err(format!("vertex {} has invalid position", idx));
+            }
+
+            self.positions.push(position);
+            if has_normal {
+                let len = glm::length(normal);
+                self.normals.push(if len > 0.0 && len.is_finite() { normal / len } else { vec3(0.0, 1.0, 0.0) });
+            }
+            if has_uv { self.uvs.push(uv); }
+            if has_color { self.colors.push(color); }
+        }
+
+        Ok(())
+    }
+
+
+    fn read_faces(&mut self, element: &Element, body: &mut Body) -> Result<(), String>
+    {
+        let is_indices = |prop: &Property| {
+            matches!(prop.kind, PropertyKind::List(..)) && (prop.name == "vertex_indices" || prop.name == "vertex_index")
+        };
+        if !element.props.iter().any(is_indices) {
+            return Err("face element needs a `vertex_indices` list property".to_string());
+        }
+
+        let mut polygon = Vec::new();
+        for idx in 0..element.count {
+            body.begin()?;
+            for prop in &element.props {
+                if !is_indices(prop) {
+                    read_property(prop, body, |_| Ok(()))?;
+                    continue;
+                }
+
+                polygon.clear();
+                read_property(prop, body, |val| {
+                    if val < 0.0 || val > u32::MAX as f64 {
+                        return Err(format!("face {} has invalid vertex index {}", idx, val));
+                    }
+                    polygon.push(val as u32);
+                    Ok(())
+                })?;
+
+                if polygon.len() < 3 {
+                    return Err(format!("face {} needs at least 3 vertices", idx));
+                }
+                // 使用扇形的方式分解多边形
+                for i in 1..polygon.len() - 1 {
+                    self.indices.push([polygon[0], polygon[i], polygon[i + 1]]);
+                }
+            }
+            body.end()?;
+        }
+
+        Ok(())
+    }
+
+
+    fn build(self, mat: Arc<dyn Material + Send + Sync>) -> Result<TriangleMesh, String>
+    {
+        if self.indices.is_empty() {
+            return Err("ply file has no faces".to_string());
+        }
+        if let Some(idx) = self.indices.iter().flatten().find(|&&i| i as usize >= self.positions.len()) {
+            return Err(format!("vertex index {} out of range, there are {} vertices", idx, self.positions.len()));
+        }
+
+        let mut mesh = TriangleMesh::new(self.positions, self.indices, mat);
+        mesh.set_normals(self.normals);
+        mesh.set_uvs(self.uvs);
+        mesh.set_colors(self.colors);
+        Ok(mesh)
+    }
+}
+
+
+/// 读取一个属性，对于列表属性，每个元素都会调用一次 f；标量属性的值也会传递给 f
+fn read_property(prop: &Property, body: &mut Body, mut f: impl FnMut(f64) -> Result<(), String>) -> Result<(), String>
+{
+    match prop.kind {
+        PropertyKind::Scalar(ty) => f(body.read(ty)?),
+        PropertyKind::List(count_ty, item_ty) => {
+            let count = body.read(count_ty)?;
+            if count < 0.0 {
+                return Err(format!("negative list length for `{}`", prop.name));
+            }
+            for _ in 0..count as usize {
+                f(body.read(item_ty)?)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test
+{
+    use super::*;
+    use crate::hit::Hittable;
+    use crate::material::Lambertian;
+    use crate::ray::Ray;
+    use crate::texture::{Texture, VertexColorTexture};
+
+    /// 一个位于 y = 0 的正方形，四个顶点分别为红、绿、蓝、白色
+    fn quad_binary(big_endian: bool) -> Vec<u8>
+    {
+        let format = if big_endian { "binary_big_endian" } else { "binary_little_endian" };
+        let mut data = format!("ply\nformat {} 1.0\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                                property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                                element face 1\nproperty list uchar int vertex_indices\nend_header\n", format).into_bytes();
+
+        let vertices = [(-1.0f32, -1.0f32, [255u8, 0, 0]), (1.0, -1.0, [0, 255, 0]), (1.0, 1.0, [0, 0, 255]), (-1.0, 1.0, [255, 255, 255])];
+        for (x, z, color) in vertices {
+            for val in [x, 0.0, z] {
+                data.extend(if big_endian { val.to_be_bytes() } else { val.to_le_bytes() });
+            }
+            data.extend(color);
+        }
+
+        data.push(4);
+        for idx in [0i32, 3, 2, 1] {
+            data.extend(if big_endian { idx.to_be_bytes() } else { idx.to_le_bytes() });
+        }
+        data
+    }
+
+    #[test]
+    fn test_parse_ply()
+    {
+        let ascii = "ply\nformat ascii 1.0\ncomment quad\nelement vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                     property uchar red\nproperty uchar green\nproperty uchar blue\n\
+                     element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                     -1 0 -1 255 0 0\n1 0 -1 0 255 0\n1 0 1 0 0 255\n-1 0 1 255 255 255\n4 0 3 2 1\n";
+
+        let texture = VertexColorTexture::new_c(vec3(0.0, 0.0, 0.0));
+        for data in [ascii.as_bytes().to_vec(), quad_binary(false), quad_binary(true)] {
+            let mesh = parse_ply(&data, "test.ply", Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))).unwrap();
+            assert_eq!(mesh.triangle_count(), 2);
+            assert_eq!(mesh.colors().len(), 4);
+
+            // 靠近红色顶点的位置，颜色以红色为主
+            let bvh = Arc::new(mesh).to_bvh();
+            let ray = Ray::new(vec3(-0.9, 1.0, -0.9), vec3(-0.9, 0.0, -0.9));
+            let payload = bvh.hit(&ray, (0.001, Float::INFINITY)).unwrap();
+            assert!(payload.front_face());
+            let color = texture.sample_hit(&payload);
+            assert!(color.x > 0.8 && color.y < 0.2 && color.z < 0.2);
+        }
+    }
+
+    #[test]
+    fn test_ply_error()
+    {
+        let src = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\n\
+                   element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n1 0 0\n0 1\n3 0 1 2\n";
+        let err = parse_ply(src.as_bytes(), "bad.ply", Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))).err().unwrap();
+        assert_eq!(err.line, 12);
+
+        let src = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float q\nend_header\n";
+        let err = parse_ply(src.as_bytes(), "bad.ply", Arc::new(Lambertian::new(vec3(0.5, 0.5, 0.5)))).err().unwrap();
+        assert!(err.msg.contains("`x`"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::ops::{Add, BitAnd, BitOr, Div, Index, Mul, Neg, Not, Sub};
+use crate::float::{Float, Vec3};
+
+
+/// 光线包中同时计算的 lane 的数量，和 SSE 寄存器中 f32 的数量（或者 AVX 寄存器中 f64 的数量）一致
+pub const LANES: usize = 4;
+
+
+/// 4 个 lane 的浮点数
+///
+/// 只是 4 个标量的数组，每个运算对所有 lane 执行相同的操作，是否编译为 SIMD 指令取决于编译器的自动向量化，
+/// 这里没有直接使用 `std::arch`。需要按照条件选择结果时，先通过比较得到 `MaskX4`，再使用 `MaskX4::select`
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C, align(16))]
+pub struct FloatX4(pub [Float; LANES]);
+
+
+/// 每个 lane 的比较结果，同样是逐个 lane 存放的标量
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaskX4(pub [bool; LANES]);
+
+
+impl FloatX4
+{
+    #[inline(always)]
+    pub fn splat(x: Float) -> FloatX4 { FloatX4([x; LANES]) }
+
+
+    /// 取出每个向量的同一个分量
+    #[inline(always)]
+    pub fn gather(v: &[Vec3; LANES], axis: usize) -> FloatX4
+    {
+        FloatX4([v[0][axis], v[1][axis], v[2][axis], v[3][axis]])
+    }
+
+
+    #[inline(always)]
+    fn map(self, f: impl Fn(Float) -> Float) -> FloatX4
+    {
+        FloatX4([f(self.0[0]), f(self.0[1]), f(self.0[2]), f(self.0[3])])
+    }
+
+
+    #[inline(always)]
+    fn zip(self, rhs: FloatX4, f: impl Fn(Float, Float) -> Float) -> FloatX4
+    {
+        FloatX4([f(self.0[0], rhs.0[0]), f(self.0[1], rhs.0[1]), f(self.0[2], rhs.0[2]), f(self.0[3], rhs.0[3])])
+    }
+
+
+    #[inline(always)]
+    fn cmp(self, rhs: FloatX4, f: impl Fn(Float, Float) -> bool) -> MaskX4
+    {
+        MaskX4([f(self.0[0], rhs.0[0]), f(self.0[1], rhs.0[1]), f(self.0[2], rhs.0[2]), f(self.0[3], rhs.0[3])])
+    }
+
+
+    /// 和 `Float::min` 相同，只有一个 lane 是 NaN 时返回另一个
+    #[inline(always)]
+    pub fn min(self, rhs: FloatX4) -> FloatX4 { self.zip(rhs, Float::min) }
+
+    #[inline(always)]
+    pub fn max(self, rhs: FloatX4) -> FloatX4 { self.zip(rhs, Float::max) }
+
+    #[inline(always)]
+    pub fn abs(self) -> FloatX4 { self.map(Float::abs) }
+
+    #[inline(always)]
+    pub fn sqrt(self) -> FloatX4 { self.map(Float::sqrt) }
+
+    #[inline(always)]
+    pub fn lt(self, rhs: FloatX4) -> MaskX4 { self.cmp(rhs, |a, b| a < b) }
+
+    #[inline(always)]
+    pub fn le(self, rhs: FloatX4) -> MaskX4 { self.cmp(rhs, |a, b| a <= b) }
+
+    #[inline(always)]
+    pub fn gt(self, rhs: FloatX4) -> MaskX4 { self.cmp(rhs, |a, b| a > b) }
+
+    #[inline(always)]
+    pub fn ge(self, rhs: FloatX4) -> MaskX4 { self.cmp(rhs, |a, b| a >= b) }
+
+    #[inline(always)]
+    pub fn eq(self, rhs: FloatX4) -> MaskX4 { self.cmp(rhs, |a, b| a == b) }
+
+    #[inline(always)]
+    pub fn ne(self, rhs: FloatX4) -> MaskX4 { self.cmp(rhs, |a, b| a != b) }
+
+    /// 既不是无穷大也不是 NaN：NaN 和任何数的比较都是 false
+    #[inline(always)]
+    pub fn is_finite(self) -> MaskX4 { self.abs().lt(FloatX4::splat(Float::INFINITY)) }
+}
+
+
+impl Index<usize> for FloatX4
+{
+    type Output = Float;
+
+    #[inline(always)]
+    fn index(&self, lane: usize) -> &Float { &self.0[lane] }
+}
+
+
+impl Add for FloatX4
+{
+    type Output = FloatX4;
+
+    #[inline(always)]
+    fn add(self, rhs: FloatX4) -> FloatX4 { self.zip(rhs, |a, b| a + b) }
+}
+
+
+impl Sub for FloatX4
+{
+    type Output = FloatX4;
+
+    #[inline(always)]
+    fn sub(self, rhs: FloatX4) -> FloatX4 { self.zip(rhs, |a, b| a - b) }
+}
+
+
+impl Mul for FloatX4
+{
+    type Output = FloatX4;
+
+    #[inline(always)]
+    fn mul(self, rhs: FloatX4) -> FloatX4 { self.zip(rhs, |a, b| a * b) }
+}
+
+
+impl Div for FloatX4
+{
+    type Output = FloatX4;
+
+    #[inline(always)]
+    fn div(self, rhs: FloatX4) -> FloatX4 { self.zip(rhs, |a, b| a / b) }
+}
+
+
+impl Neg for FloatX4
+{
+    type Output = FloatX4;
+
+    #[inline(always)]
+    fn neg(self) -> FloatX4 { self.map(|a| -a) }
+}
+
+
+impl MaskX4
+{
+    #[inline(always)]
+    pub fn splat(b: bool) -> MaskX4 { MaskX4([b; LANES]) }
+
+
+    #[inline(always)]
+    pub fn any(self) -> bool { (self.0[0] | self.0[1]) | (self.0[2] | self.0[3]) }
+
+
+    #[inline(always)]
+    pub fn lane(self, lane: usize) -> bool { self.0[lane] }
+
+
+    /// 为 true 的 lane 取 a，否则取 b
+    #[inline(always)]
+    pub fn select(self, a: FloatX4, b: FloatX4) -> FloatX4
+    {
+        let pick = |lane: usize| if self.0[lane] { a.0[lane] } else { b.0[lane] };
+        FloatX4([pick(0), pick(1), pick(2), pick(3)])
+    }
+}
+
+
+impl BitAnd for MaskX4
+{
+    type Output = MaskX4;
+
+    #[inline(always)]
+    fn bitand(self, rhs: MaskX4) -> MaskX4
+    {
+        MaskX4([self.0[0] & rhs.0[0], self.0[1] & rhs.0[1], self.0[2] & rhs.0[2], self.0[3] & rhs.0[3]])
+    }
+}
+
+
+impl BitOr for MaskX4
+{
+    type Output = MaskX4;
+
+    #[inline(always)]
+    fn bitor(self, rhs: MaskX4) -> MaskX4
+    {
+        MaskX4([self.0[0] | rhs.0[0], self.0[1] | rhs.0[1], self.0[2] | rhs.0[2], self.0[3] | rhs.0[3]])
+    }
+}
+
+
+impl Not for MaskX4
+{
+    type Output = MaskX4;
+
+    #[inline(always)]
+    fn not(self) -> MaskX4 { MaskX4([!self.0[0], !self.0[1], !self.0[2], !self.0[3]]) }
+}
@@ -1,6 +1,8 @@
 use std::sync::Arc;
+use crate::hit::HitPayload;
 use crate::texture::Texture;
 use crate::texture::solidcolor::SolidColor;
+use crate::float::{Float, Vec2, Vec3};
 
 
 /// 棋盘格样式的纹理
@@ -8,6 +10,9 @@ pub struct CheckerTexture
 {
     odd: Arc<dyn Texture + Send + Sync>,
     even: Arc<dyn Texture + Send + Sync>,
+
+    /// 为 None 时根据空间坐标交替；否则根据纹理坐标交替，值为 [0, 1) 范围内每个方向上格子的数量
+    uv_squares: Option<Float>,
 }
 
 
@@ -15,28 +20,57 @@ impl CheckerTexture
 {
     pub fn new(odd: Arc<dyn Texture + Send + Sync>, even: Arc<dyn Texture + Send + Sync>) -> CheckerTexture
     {
-        CheckerTexture { odd, even }
+        CheckerTexture { odd, even, uv_squares: None }
     }
 
-    pub fn new_c(color1: glm::Vec3, color2: glm::Vec3) -> CheckerTexture
+    pub fn new_c(color1: Vec3, color2: Vec3) -> CheckerTexture
     {
         CheckerTexture {
             odd: Arc::new(SolidColor::new(color1)),
             even: Arc::new(SolidColor::new(color2)),
+            uv_squares: None,
+        }
+    }
+
+
+    /// 根据纹理坐标交替的棋盘格，纹理坐标的每个方向上有 squares 个格子
+    ///
+    /// 适用于平面这类空间坐标某个分量恒定的表面，例如 y = 0 的地面上 sin(10y) 恒为 0，空间棋盘格会退化为单一的颜色
+    pub fn new_uv_c(color1: Vec3, color2: Vec3, squares: Float) -> CheckerTexture
+    {
+        debug_assert!(squares > 0.0);
+
+        CheckerTexture {
+            odd: Arc::new(SolidColor::new(color1)),
+            even: Arc::new(SolidColor::new(color2)),
+            uv_squares: Some(squares),
         }
     }
 }
 
 
+impl CheckerTexture
+{
+    /// 空间棋盘格在 xyz 三个方向都会存在纹理交替，纹理棋盘格在 uv 两个方向交替
+    fn select(&self, uv: &Vec2, p: &Vec3) -> &Arc<dyn Texture + Send + Sync>
+    {
+        let odd = match self.uv_squares {
+            None => Float::sin(10.0 * p.x) * Float::sin(10.0 * p.y) * Float::sin(10.0 * p.z) < 0.0,
+            Some(squares) => ((uv.x * squares).floor() + (uv.y * squares).floor()) as i64 % 2 != 0,
+        };
+        if odd { &self.odd } else { &self.even }
+    }
+}
+
+
 impl Texture for CheckerTexture
 {
-    fn sample(&self, uv: &glm::Vec2, p: &glm::Vec3) -> glm::Vec3 {
-        // 在 xyz 三个方向都会存在纹理交替
-        let sines = f32::sin(10.0 * p.x) * f32::sin(10.0 * p.y) * f32::sin(10.0 * p.z);
-        if sines < 0.0 {
-            self.odd.sample(uv, p)
-        } else {
-            self.even.sample(uv, p)
-        }
+    fn sample(&self, uv: &Vec2, p: &Vec3) -> Vec3 {
+        self.select(uv, p).sample(uv, p)
+    }
+
+
+    fn sample_hit(&self, payload: &HitPayload) -> Vec3 {
+        self.select(payload.uv(), payload.hit_point()).sample_hit(payload)
     }
 }
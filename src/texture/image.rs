@@ -1,5 +1,6 @@
 use num::Zero;
 use crate::texture::Texture;
+use crate::float::{Float, Vec2, Vec3, Vec4, vec3};
 use stb_image::image as stbi;
 
 
@@ -12,32 +13,36 @@ pub struct ImageTexture
 
 impl ImageTexture
 {
-    pub fn new(filename: &String) -> ImageTexture
+    pub fn new(filename: &str) -> ImageTexture
     {
-        let img = match stbi::load(filename) {
-            stbi::LoadResult::Error(msg) => {
-                panic!("error load image({}): {}", filename, msg);
-            }
-            stbi::LoadResult::ImageF32(_) => {
-                panic!("currently not support f32 image: {}", filename);
-            }
-            stbi::LoadResult::ImageU8(img) => img
-        };
-
-        ImageTexture { img }
+        match Self::load(filename) {
+            Ok(texture) => texture,
+            Err(msg) => panic!("{}", msg),
+        }
+    }
+
+
+    /// 读取图片作为纹理，读取失败时返回错误信息，而不是 panic
+    pub fn load(filename: &str) -> Result<ImageTexture, String>
+    {
+        match stbi::load(filename) {
+            stbi::LoadResult::Error(msg) => Err(format!("error load image({}): {}", filename, msg)),
+            stbi::LoadResult::ImageF32(_) => Err(format!("currently not support f32 image: {}", filename)),
+            stbi::LoadResult::ImageU8(img) => Ok(ImageTexture { img }),
+        }
     }
 
 
-    fn get_color(&self, pos: (usize, usize)) -> glm::Vec4
+    fn get_color(&self, pos: (usize, usize)) -> Vec4
     {
         debug_assert!(pos.0 < self.img.width && pos.1 < self.img.height);
 
         let idx = pos.1 * (self.img.width * self.img.depth) + pos.0 * self.img.depth;
 
-        let mut color = glm::Vec4::zero();
+        let mut color = Vec4::zero();
         let color_scale = 1.0 / 255.0;
         for i in 0..self.img.depth {
-            color[i] = self.img.data[idx + i] as f32 * color_scale;
+            color[i] = self.img.data[idx + i] as Float * color_scale;
         }
 
         color
@@ -47,15 +52,15 @@ impl ImageTexture
 
 impl Texture for ImageTexture
 {
-    fn sample(&self, uv: &glm::Vec2, _p: &glm::Vec3) -> glm::Vec3 {
+    fn sample(&self, uv: &Vec2, _p: &Vec3) -> Vec3 {
         // 将 uv 的范围限制在 [0, 1]，并翻转 v（这个和 stbi 的读取有关）
         let u = uv.x.clamp(0.0, 1.0);
         let v = 1.0 - uv.y.clamp(0.0, 1.0);
 
-        let i = ((self.img.width as f32 * u) as usize).min(self.img.width - 1);
-        let j = ((self.img.height as f32 * v) as usize).min(self.img.height - 1);
+        let i = ((self.img.width as Float * u) as usize).min(self.img.width - 1);
+        let j = ((self.img.height as Float * v) as usize).min(self.img.height - 1);
 
         let color = self.get_color((i, j));
-        glm::vec3(color.x, color.y, color.z)
+        vec3(color.x, color.y, color.z)
     }
 }
\ No newline at end of file
@@ -1,16 +1,29 @@
+use crate::hit::HitPayload;
+use crate::float::{Vec2, Vec3};
+
+
 pub trait Texture
 {
-    fn sample(&self, uv: &glm::Vec2, p: &glm::Vec3) -> glm::Vec3;
+    fn sample(&self, uv: &Vec2, p: &Vec3) -> Vec3;
+
+
+    /// 根据交点的信息进行采样，默认只使用纹理坐标和交点位置；需要交点其他信息（例如顶点颜色）的纹理可以覆盖这个方法
+    fn sample_hit(&self, payload: &HitPayload) -> Vec3
+    {
+        self.sample(payload.uv(), payload.hit_point())
+    }
 }
 
 
 mod checker;
 mod solidcolor;
 mod image;
+mod vertex_color;
 
 
 pub use checker::CheckerTexture;
 pub use solidcolor::SolidColor;
 pub use image::ImageTexture;
+pub use vertex_color::VertexColorTexture;
 
 
@@ -1,15 +1,16 @@
 use crate::texture::Texture;
+use crate::float::{Vec2, Vec3, vec3};
 
 
 pub struct SolidColor
 {
-    color: glm::Vec3,
+    color: Vec3,
 }
 
 
 impl SolidColor
 {
-    pub fn new(color: glm::Vec3) -> Self
+    pub fn new(color: Vec3) -> Self
     {
         SolidColor { color }
     }
@@ -18,14 +19,14 @@ impl SolidColor
 
 impl Default for SolidColor {
     fn default() -> Self {
-        SolidColor { color: glm::vec3(0., 0., 0.) }
+        SolidColor { color: vec3(0., 0., 0.) }
     }
 }
 
 
 impl Texture for SolidColor
 {
-    fn sample(&self, _uv: &glm::Vec2, _p: &glm::Vec3) -> glm::Vec3 {
+    fn sample(&self, _uv: &Vec2, _p: &Vec3) -> Vec3 {
         self.color
     }
 }
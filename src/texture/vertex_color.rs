@@ -0,0 +1,42 @@
+use std::sync::Arc;
+use crate::hit::HitPayload;
+use crate::texture::{SolidColor, Texture};
+use crate::float::{Vec2, Vec3};
+
+
+/// 使用交点处插值得到的顶点颜色作为纹理，物体没有顶点颜色时使用 fallback 纹理
+pub struct VertexColorTexture
+{
+    fallback: Arc<dyn Texture + Send + Sync>,
+}
+
+
+impl VertexColorTexture
+{
+    pub fn new(fallback: Arc<dyn Texture + Send + Sync>) -> VertexColorTexture
+    {
+        VertexColorTexture { fallback }
+    }
+
+    pub fn new_c(fallback: Vec3) -> VertexColorTexture
+    {
+        VertexColorTexture { fallback: Arc::new(SolidColor::new(fallback)) }
+    }
+}
+
+
+impl Texture for VertexColorTexture
+{
+    /// 没有交点信息时，无法得到顶点颜色
+    fn sample(&self, uv: &Vec2, p: &Vec3) -> Vec3 {
+        self.fallback.sample(uv, p)
+    }
+
+
+    fn sample_hit(&self, payload: &HitPayload) -> Vec3 {
+        match payload.color() {
+            Some(color) => *color,
+            None => self.fallback.sample_hit(payload),
+        }
+    }
+}
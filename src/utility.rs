@@ -1,21 +1,80 @@
-use rand::{Rand, Rng};
+use rand::{Rand, Rng, SeedableRng, XorShiftRng};
+use std::cell::RefCell;
 use std::sync::mpsc::Sender;
 use num::traits::FloatConst;
+use crate::float::{Float, Vec2, Vec3, vec2, vec3};
+
+
+thread_local! {
+    /// 每个线程独立的随机数生成器，默认使用随机的种子
+    static LOCAL_RNG: RefCell<XorShiftRng> = RefCell::new(rand::thread_rng().gen());
+}
+
+
+/// 当前线程的随机数生成器，可以通过 `seed_rng` 设置种子，使得结果可以复现
+#[derive(Clone, Copy)]
+pub struct LocalRng;
+
+
+impl Rng for LocalRng
+{
+    fn next_u32(&mut self) -> u32 {
+        LOCAL_RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        LOCAL_RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        LOCAL_RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+}
+
+
+/// 获取当前线程的随机数生成器
+pub fn rng() -> LocalRng { LocalRng }
+
+
+/// 使用当前线程的随机数生成器，生成一个随机数
+pub fn random<T: Rand>() -> T { LocalRng.gen() }
+
+
+/// 设置当前线程的随机数种子
+pub fn seed_rng(seed: u64)
+{
+    // 使用 splitmix64 将种子扩展为 xorshift 需要的 128 位状态，且状态不会全为 0
+    let mut state = seed;
+    let mut next = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    let (a, b) = (next(), next());
+    let mut words = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    if words.iter().all(|&w| w == 0) {
+        words[0] = 1;
+    }
+
+    LOCAL_RNG.with(|rng| *rng.borrow_mut() = XorShiftRng::from_seed(words));
+}
 
 
 /// 在单位球内随机取一点
-pub fn rand_in_unit_sphere() -> glm::Vec3
+pub fn rand_in_unit_sphere() -> Vec3
 {
     // 一个简单的实现方法是：
     // 在 [-1, 1]^3 的正方体中取一点，判断是否在球内
     // 如果在，就接受这个点
     // 否则，重复上面的操作
 
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
 
     loop {
         // glm::rand 生成的是 [0, 1]^3 的点，需要手动将点映射到 [-1, 1]^3
-        let p = glm::Vec3::rand(&mut rng) * 2.0 - 1.0;
+        let p = Vec3::rand(&mut rng) * 2.0 - 1.0;
 
         if glm::length(p) >= 1.0 { continue; }
 
@@ -25,11 +84,11 @@ pub fn rand_in_unit_sphere() -> glm::Vec3
 
 
 /// 在单位圆中随机取一点
-pub fn rand_in_unit_disk() -> glm::Vec2
+pub fn rand_in_unit_disk() -> Vec2
 {
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
     loop {
-        let p = glm::vec2(rng.gen_range(-1., 1.), rng.gen_range(-1., 1.));
+        let p = vec2(rng.gen_range(-1., 1.), rng.gen_range(-1., 1.));
         if glm::length(p) >= 1. { continue; }
         return p;
     }
@@ -37,31 +96,59 @@ pub fn rand_in_unit_disk() -> glm::Vec2
 
 
 /// 随机方向的单位向量，等价于在单位球上随机取一点
-pub fn rand_unit_vec() -> glm::Vec3
+pub fn rand_unit_vec() -> Vec3
 {
     glm::normalize(rand_in_unit_sphere())
 }
 
 
 /// 在半球表面随机取一点，使得立体角的概率密度为 cos(theta)/pi
-pub fn rand_cos_dir() -> glm::Vec3
+pub fn rand_cos_dir() -> Vec3
+{
+    cos_dir(&vec2(random(), random()))
+}
+
+
+/// [0, 1)^3 中均匀分布的随机数，作为 BSDF 采样的输入
+pub fn rand_vec3() -> Vec3
+{
+    vec3(random(), random(), random())
+}
+
+
+/// 将 [0, 1)^2 中均匀分布的 u 映射到 z 轴正方向的半球表面，立体角的概率密度为 cos(theta)/pi
+pub fn cos_dir(u: &Vec2) -> Vec3
+{
+    let z = Float::sqrt(1.0 - u.y);
+    let phi = 2.0 * Float::PI() * u.x;
+    let sin_theta = Float::sqrt(u.y);
+    let x = Float::cos(phi) * sin_theta;
+    let y = Float::sin(phi) * sin_theta;
+
+    vec3(x, y, z)
+}
+
+
+/// 将 [0, 1)^2 中均匀分布的 u 映射到单位球面上，立体角的概率密度为 1/(4pi)
+pub fn unit_vec(u: &Vec2) -> Vec3
 {
-    let mut rng = rand::thread_rng();
-    let r1 = rng.gen::<f32>();
-    let r2 = rng.gen::<f32>();
+    let z = 1.0 - 2.0 * u.x;
+    let r = Float::sqrt(Float::max(0.0, 1.0 - z * z));
+    let phi = 2.0 * Float::PI() * u.y;
+
+    vec3(r * Float::cos(phi), r * Float::sin(phi), z)
+}
 
-    let z = f32::sqrt(1.0 - r2);
-    let phi = 2.0 * f32::PI() * r1;
-    let sin_theta = f32::sqrt(r2);
-    let x = f32::cos(phi) * sin_theta;
-    let y = f32::sin(phi) * sin_theta;
 
-    glm::vec3(x, y, z)
+/// 将 [0, 1)^3 中均匀分布的 u 映射到单位球内，体积的概率密度处处相同
+pub fn in_unit_sphere(u: &Vec3) -> Vec3
+{
+    unit_vec(&vec2(u.y, u.z)) * Float::cbrt(u.x)
 }
 
 
 /// 将真实的颜色值进行使用 Gamma 函数进行编码
-pub fn gamma_correction(color: glm::Vec3) -> glm::Vec3
+pub fn gamma_correction(color: Vec3) -> Vec3
 {
     // 最简单的 Gamma = 2.0
     glm::sqrt(color)
@@ -69,9 +156,9 @@ pub fn gamma_correction(color: glm::Vec3) -> glm::Vec3
 
 
 /// 向量是否很接近零
-pub fn near_zero(vec: &glm::Vec3) -> bool
+pub fn near_zero(vec: &Vec3) -> bool
 {
-    const S: f32 = 1e-8;
+    const S: Float = 1e-8;
 
     vec.x.abs() < S && vec.y.abs() < S && vec.z.abs() < S
 }
@@ -79,7 +166,7 @@ pub fn near_zero(vec: &glm::Vec3) -> bool
 
 /// 检查 vec 的所有分量是否都满足某个条件
 #[inline(always)]
-pub fn check_and<F: Fn(f32) -> bool>(vec: &glm::Vec3, check: F) -> bool
+pub fn check_and<F: Fn(Float) -> bool>(vec: &Vec3, check: F) -> bool
 {
     check(vec.x) && check(vec.y) && check(vec.z)
 }
@@ -87,35 +174,45 @@ pub fn check_and<F: Fn(f32) -> bool>(vec: &glm::Vec3, check: F) -> bool
 
 /// 检查 vec 是否存在某个分量满足条件
 #[inline(always)]
-pub fn check_or<F: Fn(f32) -> bool>(vec: &glm::Vec3, check: F) -> bool
+pub fn check_or<F: Fn(Float) -> bool>(vec: &Vec3, check: F) -> bool
 {
     check(vec.x) || check(vec.y) || check(vec.z)
 }
 
 
 /// 向量是否已经正规化
-pub fn is_normalized(vec: &glm::Vec3) -> bool
+pub fn is_normalized(vec: &Vec3) -> bool
 {
     (glm::length(*vec) - 1.0).abs() < 0.001
 }
 
 
+/// 浮点数误差分析中的 gamma(n) = n * eps / (1 - n * eps)，eps 是单次舍入的最大相对误差
+///
+/// n 次浮点运算的累积相对误差不超过 gamma(n)，参考 pbrt-v3 3.9.1
+pub const fn gamma(n: u32) -> Float
+{
+    let eps = Float::EPSILON * 0.5;
+    (n as Float * eps) / (1.0 - n as Float * eps)
+}
+
+
 /// 在单位球的圆锥范围内均匀选择一个方向
 ///
 /// 圆锥以 z 轴正方向为中心，圆锥范围由 theta_max 确定
-pub fn rand_in_cone(cos_theta_max: f32) -> glm::Vec3
+pub fn rand_in_cone(cos_theta_max: Float) -> Vec3
 {
     debug_assert!(cos_theta_max >= 0.0 && cos_theta_max <= 1.0);
 
-    let mut rng = rand::thread_rng();
+    let mut rng = rng();
 
-    let z = 1.0 + rng.gen::<f32>() * (cos_theta_max - 1.0);
-    let sin_theta = f32::sqrt(1.0 - z * z);
-    let phi = 2.0 * f32::PI() * rng.gen::<f32>();
-    let x = sin_theta * f32::cos(phi);
-    let y = sin_theta * f32::sin(phi);
+    let z = 1.0 + rng.gen::<Float>() * (cos_theta_max - 1.0);
+    let sin_theta = Float::sqrt(1.0 - z * z);
+    let phi = 2.0 * Float::PI() * rng.gen::<Float>();
+    let x = sin_theta * Float::cos(phi);
+    let y = sin_theta * Float::sin(phi);
 
-    glm::vec3(x, y, z)
+    vec3(x, y, z)
 }
 
 
@@ -134,9 +231,9 @@ mod test
     #[test]
     fn test_temp()
     {
-        let acc = glm::normalize(glm::Vec3::zero());
+        let acc = glm::normalize(Vec3::zero());
         println!("{:#?}", acc);
-        assert!(f32::is_nan(acc.x));
+        assert!(Float::is_nan(acc.x));
     }
 }
 